@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE note_attachments (
+    id UUID PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES sticky_notes(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    storage_key TEXT NOT NULL,
+    thumbnail_key TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX note_attachments_note_id_idx ON note_attachments (note_id);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE note_links (
+    id UUID PRIMARY KEY,
+    source_note_id UUID NOT NULL REFERENCES sticky_notes(id) ON DELETE CASCADE,
+    -- left NULL when the target note is deleted so the link shows up as broken
+    target_note_id UUID REFERENCES sticky_notes(id) ON DELETE SET NULL,
+    target_title TEXT NOT NULL,
+    kind TEXT NOT NULL DEFAULT 'wiki',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX note_links_source_note_id_idx ON note_links (source_note_id);
+CREATE INDEX note_links_target_note_id_idx ON note_links (target_note_id);
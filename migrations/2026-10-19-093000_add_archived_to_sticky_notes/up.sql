@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE sticky_notes ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE space_settings (
+    space_id INT4 PRIMARY KEY REFERENCES spaces(id) ON DELETE CASCADE,
+    description TEXT,
+    icon TEXT,
+    default_color TEXT,
+    default_text_color TEXT,
+    background TEXT,
+    time_zone TEXT,
+    default_playlist TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
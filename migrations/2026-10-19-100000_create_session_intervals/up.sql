@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE session_intervals (
+    id UUID PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES time_tracking_sessions(id) ON DELETE CASCADE,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP
+);
+
+CREATE INDEX session_intervals_session_id_idx ON session_intervals (session_id);
+
+-- existing sessions become a single uninterrupted interval
+INSERT INTO session_intervals (id, session_id, start_time, end_time)
+SELECT gen_random_uuid(), id, start_time, end_time FROM time_tracking_sessions;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE time_tracking_sessions DROP COLUMN IF EXISTS activity_id;
+DROP TABLE IF EXISTS activities;
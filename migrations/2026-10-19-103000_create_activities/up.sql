@@ -0,0 +1,38 @@
+-- Your SQL goes here
+CREATE TABLE activities (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    color TEXT,
+    category TEXT,
+    billable BOOLEAN NOT NULL DEFAULT FALSE,
+    hourly_rate_cents BIGINT,
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX activities_space_id_name_idx ON activities (space_id, lower(name));
+
+ALTER TABLE time_tracking_sessions
+    ADD COLUMN activity_id UUID REFERENCES activities(id) ON DELETE SET NULL;
+
+CREATE INDEX time_tracking_sessions_activity_id_idx ON time_tracking_sessions (activity_id);
+
+-- one activity per space for all spellings that only differ in case or surrounding
+-- whitespace, named after the spelling that was used the most
+INSERT INTO activities (space_id, user_id, name)
+SELECT DISTINCT ON (t.space_id, lower(trim(t.activity_name)))
+       t.space_id, s.user_id, trim(t.activity_name)
+FROM time_tracking_sessions t
+JOIN spaces s ON s.id = t.space_id
+WHERE trim(t.activity_name) <> ''
+GROUP BY t.space_id, s.user_id, trim(t.activity_name)
+ORDER BY t.space_id, lower(trim(t.activity_name)), COUNT(*) DESC;
+
+UPDATE time_tracking_sessions t
+SET activity_id = a.id,
+    activity_name = a.name
+FROM activities a
+WHERE a.space_id = t.space_id
+  AND lower(a.name) = lower(trim(t.activity_name));
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS budget_notifications;
+DROP TABLE IF EXISTS activity_budgets;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE activity_budgets (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    activity_id UUID NOT NULL REFERENCES activities(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    period TEXT NOT NULL,        -- day, week or month
+    kind TEXT NOT NULL,          -- max or min
+    limit_seconds BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (activity_id, period, kind)
+);
+
+-- one row per budget and period once its alert went out, so it is only sent once
+CREATE TABLE budget_notifications (
+    budget_id UUID NOT NULL REFERENCES activity_budgets(id) ON DELETE CASCADE,
+    period_start DATE NOT NULL,
+    tracked_seconds BIGINT NOT NULL,
+    sent_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (budget_id, period_start)
+);
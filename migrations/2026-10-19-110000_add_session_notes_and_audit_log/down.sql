@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS session_audit_log;
+ALTER TABLE time_tracking_sessions DROP COLUMN IF EXISTS notes;
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+ALTER TABLE time_tracking_sessions ADD COLUMN notes TEXT;
+
+-- no foreign key on session_id, the history of a deleted session is kept
+CREATE TABLE session_audit_log (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    session_id UUID NOT NULL,
+    user_id TEXT NOT NULL,
+    action TEXT NOT NULL,   -- create, update or delete
+    before JSONB,
+    after JSONB,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX session_audit_log_session_id_idx ON session_audit_log (session_id, created_at);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS pomodoros;
+DROP TABLE IF EXISTS pomodoro_runs;
+ALTER TABLE time_tracking_sessions DROP COLUMN IF EXISTS session_type;
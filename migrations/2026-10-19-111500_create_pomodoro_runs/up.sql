@@ -0,0 +1,38 @@
+-- Your SQL goes here
+ALTER TABLE time_tracking_sessions ADD COLUMN session_type TEXT NOT NULL DEFAULT 'regular';
+
+-- a run of work and break phases, `session_id` is the session of the running work phase
+CREATE TABLE pomodoro_runs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id TEXT NOT NULL,
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    activity_id UUID NOT NULL REFERENCES activities(id) ON DELETE CASCADE,
+    work_seconds BIGINT NOT NULL,
+    short_break_seconds BIGINT NOT NULL,
+    long_break_seconds BIGINT NOT NULL,
+    long_break_every INT4 NOT NULL,
+    phase TEXT NOT NULL,   -- work, short_break, long_break or finished
+    phase_started_at TIMESTAMP NOT NULL,
+    phase_ends_at TIMESTAMP NOT NULL,
+    completed_pomodoros INT4 NOT NULL DEFAULT 0,
+    session_id UUID REFERENCES time_tracking_sessions(id) ON DELETE SET NULL,
+    started_at TIMESTAMP NOT NULL,
+    ended_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX pomodoro_runs_one_active_idx ON pomodoro_runs (user_id) WHERE ended_at IS NULL;
+CREATE INDEX pomodoro_runs_phase_ends_at_idx ON pomodoro_runs (phase_ends_at) WHERE ended_at IS NULL;
+
+-- every work phase that ran to the end
+CREATE TABLE pomodoros (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    run_id UUID NOT NULL REFERENCES pomodoro_runs(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    activity_id UUID NOT NULL REFERENCES activities(id) ON DELETE CASCADE,
+    session_id UUID REFERENCES time_tracking_sessions(id) ON DELETE SET NULL,
+    started_at TIMESTAMP NOT NULL,
+    completed_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX pomodoros_user_id_completed_at_idx ON pomodoros (user_id, completed_at);
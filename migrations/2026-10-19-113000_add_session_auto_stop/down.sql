@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS time_tracking_sessions_review_idx;
+DROP INDEX IF EXISTS time_tracking_sessions_open_idx;
+ALTER TABLE time_tracking_sessions
+    DROP COLUMN IF EXISTS reviewed_at,
+    DROP COLUMN IF EXISTS auto_stop_reason,
+    DROP COLUMN IF EXISTS auto_stopped,
+    DROP COLUMN IF EXISTS last_heartbeat_at;
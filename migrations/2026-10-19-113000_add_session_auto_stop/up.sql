@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE time_tracking_sessions
+    ADD COLUMN last_heartbeat_at TIMESTAMP,
+    ADD COLUMN auto_stopped BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN auto_stop_reason TEXT,   -- idle or max_length
+    ADD COLUMN reviewed_at TIMESTAMP;
+
+CREATE INDEX time_tracking_sessions_open_idx ON time_tracking_sessions (start_time) WHERE end_time IS NULL;
+CREATE INDEX time_tracking_sessions_review_idx ON time_tracking_sessions (user_id) WHERE auto_stopped AND reviewed_at IS NULL;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS time_tracking_sessions_note_idx;
+ALTER TABLE time_tracking_sessions
+    DROP COLUMN IF EXISTS note_line,
+    DROP COLUMN IF EXISTS note_id;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE time_tracking_sessions
+    ADD COLUMN note_id UUID REFERENCES sticky_notes(id) ON DELETE SET NULL,
+    ADD COLUMN note_line INT4;  -- index into the note's lines, only set together with note_id
+
+CREATE INDEX time_tracking_sessions_note_idx ON time_tracking_sessions (note_id) WHERE note_id IS NOT NULL;
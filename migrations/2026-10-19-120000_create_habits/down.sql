@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS habit_completions;
+DROP TABLE IF EXISTS habits;
+DROP TABLE IF EXISTS line_check_events;
@@ -0,0 +1,39 @@
+-- Your SQL goes here
+-- every time a note line gets checked or unchecked, lines have no id so the text identifies them
+CREATE TABLE line_check_events (
+    id UUID PRIMARY KEY,
+    note_id UUID NOT NULL REFERENCES sticky_notes(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    line_text TEXT NOT NULL,
+    checked BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX line_check_events_note_idx ON line_check_events (note_id, line_text, created_at);
+CREATE INDEX line_check_events_space_idx ON line_check_events (space_id, created_at);
+
+-- a checklist habit is done on a day it is checked off (by hand or through its note line),
+-- a time habit when at least target_seconds of its activity were tracked that day
+CREATE TABLE habits (
+    id UUID PRIMARY KEY,
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('checklist', 'time')),
+    activity_id UUID REFERENCES activities(id) ON DELETE CASCADE,
+    target_seconds BIGINT,
+    note_id UUID REFERENCES sticky_notes(id) ON DELETE SET NULL,
+    line_text TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (kind <> 'time' OR (activity_id IS NOT NULL AND target_seconds > 0))
+);
+
+CREATE INDEX habits_space_idx ON habits (space_id);
+
+CREATE TABLE habit_completions (
+    habit_id UUID NOT NULL REFERENCES habits(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    completed_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (habit_id, day)
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS time_tracking_sessions_tags_idx;
+ALTER TABLE time_tracking_sessions DROP COLUMN IF EXISTS tags;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE time_tracking_sessions ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX time_tracking_sessions_tags_idx ON time_tracking_sessions USING GIN (tags);
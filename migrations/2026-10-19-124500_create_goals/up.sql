@@ -0,0 +1,29 @@
+-- Your SQL goes here
+-- a target to reach between two dates, `target` is in seconds of `activity_id` for a
+-- time goal and in checked lines (of `note_id`, any note in the space when NULL) for a checklist goal
+CREATE TABLE goals (
+    id UUID PRIMARY KEY,
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('checklist', 'time')),
+    target BIGINT NOT NULL CHECK (target > 0),
+    activity_id UUID REFERENCES activities(id) ON DELETE CASCADE,
+    note_id UUID REFERENCES sticky_notes(id) ON DELETE CASCADE,
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (end_date >= start_date),
+    CHECK (kind <> 'time' OR activity_id IS NOT NULL)
+);
+
+CREATE INDEX goals_space_idx ON goals (space_id);
+
+-- progress as it stood at the end of (or so far on) a local day
+CREATE TABLE goal_snapshots (
+    goal_id UUID NOT NULL REFERENCES goals(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    progress BIGINT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (goal_id, day)
+);
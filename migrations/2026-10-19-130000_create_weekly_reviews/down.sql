@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE weekly_reviews;
+DROP TABLE note_deletions;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+-- notes are deleted for good, this keeps what they were for the weekly review
+CREATE TABLE note_deletions (
+    id UUID PRIMARY KEY,
+    note_id UUID NOT NULL,
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    lines TEXT[],
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX note_deletions_space_idx ON note_deletions (space_id, deleted_at);
+
+-- a saved weekly review, saving the same week again replaces it
+CREATE TABLE weekly_reviews (
+    id UUID PRIMARY KEY,
+    space_id INT4 NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    week_start DATE NOT NULL,
+    review JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (space_id, week_start)
+);
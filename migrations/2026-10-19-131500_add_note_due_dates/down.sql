@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX sticky_notes_due_date_idx;
+ALTER TABLE sticky_notes DROP COLUMN due_date;
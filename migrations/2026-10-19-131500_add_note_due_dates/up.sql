@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- lines carry their own due date inside the line string
+ALTER TABLE sticky_notes ADD COLUMN due_date DATE;
+
+CREATE INDEX sticky_notes_due_date_idx ON sticky_notes (user_id, due_date) WHERE due_date IS NOT NULL;
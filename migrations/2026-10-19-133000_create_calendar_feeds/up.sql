@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- one subscribable calendar per user, only a hash of the secret token is kept
+CREATE TABLE calendar_feeds (
+    user_id TEXT PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMPTZ
+);
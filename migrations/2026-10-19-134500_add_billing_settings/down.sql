@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE space_settings
+    DROP COLUMN client_name,
+    DROP COLUMN billable,
+    DROP COLUMN hourly_rate_cents,
+    DROP COLUMN currency,
+    DROP COLUMN rounding_minutes,
+    DROP COLUMN rounding_mode;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- a space stands for a client when invoicing, its rate applies to activities without one
+ALTER TABLE space_settings
+    ADD COLUMN client_name TEXT,
+    ADD COLUMN billable BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN hourly_rate_cents BIGINT CHECK (hourly_rate_cents >= 0),
+    ADD COLUMN currency TEXT,
+    ADD COLUMN rounding_minutes INTEGER CHECK (rounding_minutes > 0),
+    ADD COLUMN rounding_mode TEXT CHECK (rounding_mode IN ('up', 'down', 'nearest'));
@@ -0,0 +1,32 @@
+-- Your SQL goes here
+-- a user may have only one running session, older duplicates are closed when the newest started
+CREATE TEMPORARY TABLE duplicate_open_sessions AS
+SELECT s.id, newest.start_time AS close_at
+FROM time_tracking_sessions s
+JOIN LATERAL (
+    SELECT n.id, n.start_time
+    FROM time_tracking_sessions n
+    WHERE n.user_id = s.user_id AND n.end_time IS NULL
+    ORDER BY n.start_time DESC, n.id DESC
+    LIMIT 1
+) newest ON newest.id <> s.id
+WHERE s.end_time IS NULL;
+
+UPDATE session_intervals i
+SET end_time = GREATEST(d.close_at, i.start_time)
+FROM duplicate_open_sessions d
+WHERE i.session_id = d.id AND i.end_time IS NULL;
+
+UPDATE time_tracking_sessions s
+SET end_time = d.close_at,
+    duration = COALESCE(
+        (SELECT SUM(EXTRACT(EPOCH FROM i.end_time - i.start_time))::BIGINT
+         FROM session_intervals i
+         WHERE i.session_id = s.id),
+        GREATEST(EXTRACT(EPOCH FROM d.close_at - s.start_time)::BIGINT, 0))
+FROM duplicate_open_sessions d
+WHERE s.id = d.id;
+
+DROP TABLE duplicate_open_sessions;
+
+CREATE UNIQUE INDEX time_tracking_sessions_one_open_idx ON time_tracking_sessions (user_id) WHERE end_time IS NULL;
@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+UPDATE sticky_notes n
+SET lines = (
+    SELECT array_agg(
+        concat_ws('|',
+            split_part(l.line, '|', 1),
+            split_part(l.line, '|', 2),
+            split_part(l.line, '|', 3),
+            COALESCE((
+                SELECT (p.ord - 1)::TEXT
+                FROM unnest(n.lines) WITH ORDINALITY AS p(line, ord)
+                WHERE split_part(l.line, '|', 4) <> '' AND split_part(p.line, '|', 7) = split_part(l.line, '|', 4)
+                ORDER BY p.ord
+                LIMIT 1), ''),
+            split_part(l.line, '|', 5),
+            split_part(l.line, '|', 6))
+        ORDER BY l.ord)
+    FROM unnest(n.lines) WITH ORDINALITY AS l(line, ord)
+)
+WHERE cardinality(n.lines) > 0;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- every line gets a stable id as its seventh part, `text|color|is_checked|parent|progress|due|id`,
+-- and a parent is stored by that id instead of its index
+UPDATE sticky_notes n
+SET lines = (
+    SELECT array_agg(
+        concat_ws('|',
+            split_part(l.line, '|', 1),
+            split_part(l.line, '|', 2),
+            COALESCE(NULLIF(split_part(l.line, '|', 3), ''), 'false'),
+            CASE
+                WHEN split_part(l.line, '|', 4) ~ '^[0-9]{1,9}$' AND split_part(l.line, '|', 4)::INT < cardinality(n.lines)
+                THEN md5(n.id::TEXT || '-' || split_part(l.line, '|', 4)::INT)
+                ELSE ''
+            END,
+            split_part(l.line, '|', 5),
+            split_part(l.line, '|', 6),
+            md5(n.id::TEXT || '-' || (l.ord - 1)))
+        ORDER BY l.ord)
+    FROM unnest(n.lines) WITH ORDINALITY AS l(line, ord)
+)
+WHERE cardinality(n.lines) > 0;
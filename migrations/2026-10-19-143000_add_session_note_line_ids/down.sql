@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE time_tracking_sessions ADD COLUMN note_line INTEGER;
+
+UPDATE time_tracking_sessions t
+SET note_line = (
+    SELECT (l.ord - 1)::INTEGER
+    FROM sticky_notes n, unnest(n.lines) WITH ORDINALITY AS l(line, ord)
+    WHERE n.id = t.note_id AND split_part(l.line, '|', 7) = t.note_line_id
+    ORDER BY l.ord
+    LIMIT 1)
+WHERE t.note_line_id IS NOT NULL;
+
+ALTER TABLE time_tracking_sessions DROP COLUMN note_line_id;
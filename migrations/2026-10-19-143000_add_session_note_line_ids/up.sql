@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- a session follows its note line by the line's id, the index changed whenever lines were reordered
+ALTER TABLE time_tracking_sessions ADD COLUMN note_line_id TEXT;
+
+UPDATE time_tracking_sessions t
+SET note_line_id = NULLIF(split_part(n.lines[t.note_line + 1], '|', 7), '')
+FROM sticky_notes n
+WHERE n.id = t.note_id AND t.note_line IS NOT NULL;
+
+ALTER TABLE time_tracking_sessions DROP COLUMN note_line;
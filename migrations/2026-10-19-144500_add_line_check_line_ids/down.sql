@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS line_check_events_note_idx;
+CREATE INDEX line_check_events_note_idx ON line_check_events (note_id, line_text, created_at);
+
+ALTER TABLE habits DROP COLUMN IF EXISTS line_id;
+ALTER TABLE line_check_events DROP COLUMN IF EXISTS line_id;
@@ -0,0 +1,32 @@
+-- Your SQL goes here
+-- check events and habits follow a line by its id (see the seventh part of a stored line),
+-- the text stays as it read at the time. Where no line with that text is left the id stays
+-- NULL and the text keeps identifying the line.
+ALTER TABLE line_check_events ADD COLUMN line_id TEXT;
+ALTER TABLE habits ADD COLUMN line_id TEXT;
+
+UPDATE line_check_events e
+SET line_id = (
+    SELECT NULLIF(split_part(l.line, '|', 7), '')
+    FROM sticky_notes n, unnest(n.lines) WITH ORDINALITY AS l(line, ord)
+    WHERE n.id = e.note_id AND split_part(l.line, '|', 1) = e.line_text
+    ORDER BY l.ord
+    LIMIT 1);
+
+UPDATE habits h
+SET line_id = (
+    SELECT NULLIF(split_part(l.line, '|', 7), '')
+    FROM sticky_notes n, unnest(n.lines) WITH ORDINALITY AS l(line, ord)
+    WHERE n.id = h.note_id AND split_part(l.line, '|', 1) = h.line_text
+    ORDER BY l.ord
+    LIMIT 1)
+WHERE h.note_id IS NOT NULL AND h.line_text IS NOT NULL;
+
+-- events of notes that were moved before the space followed along
+UPDATE line_check_events e
+SET space_id = n.space_id
+FROM sticky_notes n
+WHERE n.id = e.note_id AND e.space_id <> n.space_id;
+
+DROP INDEX IF EXISTS line_check_events_note_idx;
+CREATE INDEX line_check_events_note_idx ON line_check_events (note_id, (COALESCE(line_id, line_text)), created_at);
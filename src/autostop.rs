@@ -0,0 +1,246 @@
+// Stops timers that were forgotten. Clients send a heartbeat while a timer runs,
+// a session is closed at its last heartbeat plus `idle_grace_secs`, and any
+// session is closed once it was running for `max_session_secs` (pauses left out).
+// Either way it is flagged `auto_stopped` until the user reviews or corrects it.
+
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::db::{self, DbConn, DbPool};
+use crate::events::{Notifier, TrackingEvent};
+use crate::models::{SessionInterval, TimeTrackingSession};
+use crate::tracking::TrackingConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Idle,
+    MaxLength,
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::Idle => "idle",
+            StopReason::MaxLength => "max_length",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AutoStopped {
+    pub reason: StopReason,
+    pub session: TimeTrackingSession,
+}
+
+// The moment the session had been running for `max_secs` counting only its intervals,
+// None while it has not. Sessions from before pausing existed run from their start.
+fn max_length_reached(
+    session: &TimeTrackingSession,
+    intervals: &[SessionInterval],
+    max_secs: i64,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let mut ranges: Vec<(DateTime<Utc>, DateTime<Utc>)> = intervals
+        .iter()
+        .filter(|interval| interval.session_id == session.id)
+        .map(|interval| (interval.start_time, interval.end_time.unwrap_or(now)))
+        .collect();
+    if ranges.is_empty() {
+        ranges.push((session.start_time, now));
+    }
+    ranges.sort();
+
+    let mut remaining = chrono::Duration::seconds(max_secs);
+    for (start, end) in ranges {
+        let length = (end - start).max(chrono::Duration::zero());
+        if length >= remaining {
+            return Some(start + remaining);
+        }
+        remaining -= length;
+    }
+    None
+}
+
+// When and why `session` should have been stopped, None while it may keep running.
+// Sessions without any heartbeat (older clients) are only held to the max length.
+pub fn stop_due(
+    session: &TimeTrackingSession,
+    intervals: &[SessionInterval],
+    config: &TrackingConfig,
+    now: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, StopReason)> {
+    let idle = session
+        .last_heartbeat_at
+        .filter(|_| config.idle_grace_secs > 0)
+        .map(|heartbeat| (heartbeat + chrono::Duration::seconds(config.idle_grace_secs), StopReason::Idle));
+    let max_length = (config.max_session_secs > 0)
+        .then(|| max_length_reached(session, intervals, config.max_session_secs, now))
+        .flatten()
+        .map(|at| (at, StopReason::MaxLength));
+
+    [idle, max_length]
+        .into_iter()
+        .flatten()
+        .filter(|(at, _)| *at <= now)
+        .min_by_key(|(at, _)| *at)
+}
+
+pub async fn stop_forgotten_sessions(
+    conn: &DbConn,
+    config: &TrackingConfig,
+    notifier: &dyn Notifier,
+) -> Result<(), diesel::result::Error> {
+    let now = chrono::Utc::now();
+
+    let sessions = db::get_all_open_sessions(conn).await?;
+    let intervals = db::get_session_intervals(conn, sessions.iter().map(|session| session.id).collect()).await?;
+
+    for session in sessions {
+        let Some((at, reason)) = stop_due(&session, &intervals, config, now) else { continue };
+
+        if let Some(stopped) = db::auto_stop_session(conn, session.id, at, reason.as_str().to_string()).await? {
+            let user_id = stopped.user_id.clone();
+            notifier.notify(&user_id, TrackingEvent::SessionAutoStopped(AutoStopped { reason, session: stopped })).await;
+        }
+    }
+
+    Ok(())
+}
+
+// Runs for as long as the server does, see `time_tracking.auto_stop_check_interval_secs`
+pub async fn run_checker(pool: DbPool, config: TrackingConfig, notifier: Arc<dyn Notifier>) {
+    let mut ticker = rocket::tokio::time::interval(Duration::from_secs(config.auto_stop_check_interval_secs.max(1)));
+    loop {
+        ticker.tick().await;
+
+        let Some(conn) = db::checkout(&pool).await else {
+            eprintln!("Auto stop check skipped, no database connection available");
+            continue;
+        };
+        if let Err(e) = stop_forgotten_sessions(&conn, &config, notifier.as_ref()).await {
+            eprintln!("Error stopping forgotten sessions: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn config(max_session_secs: i64, idle_grace_secs: i64) -> TrackingConfig {
+        TrackingConfig { max_session_secs, idle_grace_secs, ..Default::default() }
+    }
+
+    fn session(start: &str, last_heartbeat: Option<&str>) -> TimeTrackingSession {
+        TimeTrackingSession {
+            id: Uuid::new_v4(),
+            user_id: "u".to_string(),
+            space_id: 1,
+            activity_name: "Writing".to_string(),
+            start_time: utc(start),
+            end_time: None,
+            duration: None,
+            activity_id: None,
+            notes: None,
+            session_type: crate::models::SESSION_TYPE_REGULAR.to_string(),
+            last_heartbeat_at: last_heartbeat.map(utc),
+            auto_stopped: false,
+            auto_stop_reason: None,
+            reviewed_at: None,
+            note_id: None,
+            note_line_id: None,
+            tags: Vec::new(),
+        }
+    }
+
+    fn interval(session: &TimeTrackingSession, start: &str, end: Option<&str>) -> SessionInterval {
+        SessionInterval { id: Uuid::new_v4(), session_id: session.id, start_time: utc(start), end_time: end.map(utc) }
+    }
+
+    #[test]
+    fn a_session_within_its_limits_keeps_running() {
+        let session = session("2026-01-05T09:00:00Z", Some("2026-01-05T09:50:00Z"));
+        let intervals = [interval(&session, "2026-01-05T09:00:00Z", None)];
+
+        assert_eq!(stop_due(&session, &intervals, &config(3600, 900), utc("2026-01-05T09:55:00Z")), None);
+    }
+
+    #[test]
+    fn a_quiet_client_stops_the_session_after_the_grace() {
+        let session = session("2026-01-05T09:00:00Z", Some("2026-01-05T09:10:00Z"));
+        let intervals = [interval(&session, "2026-01-05T09:00:00Z", None)];
+
+        assert_eq!(
+            stop_due(&session, &intervals, &config(0, 900), utc("2026-01-05T09:40:00Z")),
+            Some((utc("2026-01-05T09:25:00Z"), StopReason::Idle))
+        );
+    }
+
+    #[test]
+    fn pauses_do_not_count_towards_the_max_length() {
+        let session = session("2026-01-05T09:00:00Z", None);
+        let intervals = [
+            interval(&session, "2026-01-05T09:00:00Z", Some("2026-01-05T09:40:00Z")),
+            interval(&session, "2026-01-05T11:00:00Z", None),
+        ];
+        let config = config(3600, 900);
+
+        assert_eq!(stop_due(&session, &intervals, &config, utc("2026-01-05T11:10:00Z")), None);
+        assert_eq!(
+            stop_due(&session, &intervals, &config, utc("2026-01-05T12:00:00Z")),
+            Some((utc("2026-01-05T11:20:00Z"), StopReason::MaxLength))
+        );
+    }
+
+    #[test]
+    fn a_session_without_intervals_runs_from_its_start() {
+        let session = session("2026-01-05T09:00:00Z", None);
+
+        assert_eq!(
+            stop_due(&session, &[], &config(3600, 900), utc("2026-01-05T12:00:00Z")),
+            Some((utc("2026-01-05T10:00:00Z"), StopReason::MaxLength))
+        );
+    }
+
+    #[test]
+    fn the_earlier_reason_wins() {
+        let session = session("2026-01-05T09:00:00Z", Some("2026-01-05T09:50:00Z"));
+        let intervals = [interval(&session, "2026-01-05T09:00:00Z", None)];
+
+        assert_eq!(
+            stop_due(&session, &intervals, &config(3600, 900), utc("2026-01-05T12:00:00Z")),
+            Some((utc("2026-01-05T10:00:00Z"), StopReason::MaxLength))
+        );
+        assert_eq!(
+            stop_due(&session, &intervals, &config(7200, 900), utc("2026-01-05T12:00:00Z")),
+            Some((utc("2026-01-05T10:05:00Z"), StopReason::Idle))
+        );
+    }
+
+    #[test]
+    fn zero_turns_a_limit_off() {
+        let session = session("2026-01-05T09:00:00Z", Some("2026-01-05T09:00:00Z"));
+        let intervals = [interval(&session, "2026-01-05T09:00:00Z", None)];
+
+        assert_eq!(stop_due(&session, &intervals, &config(0, 0), utc("2026-01-07T09:00:00Z")), None);
+    }
+
+    #[test]
+    fn intervals_of_other_sessions_are_ignored() {
+        let other = session("2026-01-05T06:00:00Z", None);
+        let session = session("2026-01-05T09:00:00Z", None);
+        let intervals = [
+            interval(&other, "2026-01-05T06:00:00Z", Some("2026-01-05T08:30:00Z")),
+            interval(&session, "2026-01-05T09:00:00Z", None),
+        ];
+
+        assert_eq!(stop_due(&session, &intervals, &config(3600, 900), utc("2026-01-05T09:30:00Z")), None);
+    }
+}
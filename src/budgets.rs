@@ -0,0 +1,234 @@
+// Time budgets per activity. A background task goes over every budget on an
+// interval and sends an alert (once per period) when a max budget is overrun or
+// a period ends below a min budget.
+
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::db::{self, DbConn, DbPool};
+use crate::events::{Notifier, TrackingEvent};
+use crate::models::{Activity, ActivityBudget, BudgetNotification};
+use crate::reports::{local_midnight_utc, Granularity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetKind {
+    Max, // alert as soon as more than the limit is tracked
+    Min, // alert when a period ends with less than the limit
+}
+
+impl BudgetKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "max" => Some(BudgetKind::Max),
+            "min" => Some(BudgetKind::Min),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertReason {
+    Exceeded,
+    Missed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetAlert {
+    pub budget_id: Uuid,
+    pub activity_id: Uuid,
+    pub activity_name: String,
+    pub space_id: i32,
+    pub period: String,
+    pub period_start: NaiveDate,
+    pub reason: AlertReason,
+    pub limit_seconds: i64,
+    pub tracked_seconds: i64,
+}
+
+#[derive(Serialize)]
+pub struct BudgetStatus {
+    #[serde(flatten)]
+    pub budget: ActivityBudget,
+    pub activity_name: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate, // exclusive
+    pub tracked_seconds: i64,
+    pub remaining_seconds: i64, // negative once a max budget is overrun
+    pub over_limit: bool,
+    pub alerted: bool,
+}
+
+// The period (in local dates) that `now` falls in and its bounds in UTC
+struct Period {
+    start: NaiveDate,
+    end: NaiveDate,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+fn period_containing(granularity: Granularity, date: NaiveDate, time_zone: Tz) -> Period {
+    let start = granularity.bucket_start(date);
+    let end = granularity.next_bucket_start(date);
+    Period {
+        start,
+        end,
+        from: local_midnight_utc(start, time_zone),
+        to: local_midnight_utc(end, time_zone),
+    }
+}
+
+fn local_today(now: DateTime<Utc>, time_zone: Tz) -> NaiveDate {
+    now.with_timezone(&time_zone).date_naive()
+}
+
+// The user's own time zone, otherwise the space's, UTC when neither is set
+pub async fn user_time_zone(conn: &DbConn, user_id: &str, space_id: i32) -> Tz {
+    db::get_time_zone_name(conn, user_id.to_string(), Some(space_id))
+        .await
+        .ok()
+        .flatten()
+        .and_then(|name| name.parse::<Tz>().ok())
+        .unwrap_or(chrono_tz::UTC)
+}
+
+// Where the budget stands in the current period
+pub async fn budget_status(
+    conn: &DbConn,
+    budget: ActivityBudget,
+    activity: &Activity,
+    time_zone: Tz,
+    notifications: &[BudgetNotification],
+) -> Result<BudgetStatus, diesel::result::Error> {
+    let now = chrono::Utc::now();
+    let granularity = Granularity::parse(&budget.period).unwrap_or(Granularity::Day);
+    let period = period_containing(granularity, local_today(now, time_zone), time_zone);
+
+    let tracked_seconds = db::tracked_seconds(conn, activity.id, period.from, period.to, now).await?;
+    let over_limit = match BudgetKind::parse(&budget.kind) {
+        Some(BudgetKind::Max) => tracked_seconds > budget.limit_seconds,
+        _ => false,
+    };
+    let alerted = notifications
+        .iter()
+        .any(|sent| sent.budget_id == budget.id && sent.period_start == period.start);
+
+    Ok(BudgetStatus {
+        activity_name: activity.name.clone(),
+        period_start: period.start,
+        period_end: period.end,
+        tracked_seconds,
+        remaining_seconds: budget.limit_seconds - tracked_seconds,
+        over_limit,
+        alerted,
+        budget,
+    })
+}
+
+// Checks a single budget, returns the alert when one is due and was not sent before
+async fn check_budget(
+    conn: &DbConn,
+    budget: &ActivityBudget,
+    activity: &Activity,
+    time_zone: Tz,
+    now: DateTime<Utc>,
+) -> Result<Option<BudgetAlert>, diesel::result::Error> {
+    let (Some(granularity), Some(kind)) = (Granularity::parse(&budget.period), BudgetKind::parse(&budget.kind)) else {
+        return Ok(None);
+    };
+    let current = period_containing(granularity, local_today(now, time_zone), time_zone);
+
+    let (period, reason, tracked_seconds) = match kind {
+        BudgetKind::Max => {
+            let tracked = db::tracked_seconds(conn, activity.id, current.from, current.to, now).await?;
+            if tracked <= budget.limit_seconds {
+                return Ok(None);
+            }
+            (current, AlertReason::Exceeded, tracked)
+        }
+        BudgetKind::Min => {
+            // only whole periods count, the one that just ended has to lie after the budget was set up
+            let previous = period_containing(granularity, current.start.pred_opt().unwrap_or(current.start), time_zone);
+            if previous.from < budget.created_at {
+                return Ok(None);
+            }
+            let tracked = db::tracked_seconds(conn, activity.id, previous.from, previous.to, now).await?;
+            if tracked >= budget.limit_seconds {
+                return Ok(None);
+            }
+            (previous, AlertReason::Missed, tracked)
+        }
+    };
+
+    let first_time = db::record_budget_notification(
+        conn,
+        BudgetNotification {
+            budget_id: budget.id,
+            period_start: period.start,
+            tracked_seconds,
+            sent_at: now,
+        },
+    )
+    .await?;
+    if !first_time {
+        return Ok(None);
+    }
+
+    Ok(Some(BudgetAlert {
+        budget_id: budget.id,
+        activity_id: activity.id,
+        activity_name: activity.name.clone(),
+        space_id: activity.space_id,
+        period: budget.period.clone(),
+        period_start: period.start,
+        reason,
+        limit_seconds: budget.limit_seconds,
+        tracked_seconds,
+    }))
+}
+
+pub async fn check_budgets(conn: &DbConn, notifier: &dyn Notifier) -> Result<(), diesel::result::Error> {
+    let now = chrono::Utc::now();
+    let budgets = db::get_budgets(conn, None).await?;
+
+    let mut time_zones: Vec<(String, i32, Tz)> = Vec::new();
+    for (budget, activity) in budgets {
+        let known = time_zones
+            .iter()
+            .find(|(user_id, space_id, _)| *user_id == budget.user_id && *space_id == activity.space_id);
+        let time_zone = match known {
+            Some((_, _, time_zone)) => *time_zone,
+            None => {
+                let time_zone = user_time_zone(conn, &budget.user_id, activity.space_id).await;
+                time_zones.push((budget.user_id.clone(), activity.space_id, time_zone));
+                time_zone
+            }
+        };
+
+        if let Some(alert) = check_budget(conn, &budget, &activity, time_zone, now).await? {
+            notifier.notify(&budget.user_id, TrackingEvent::BudgetAlert(alert)).await;
+        }
+    }
+
+    Ok(())
+}
+
+// Runs for as long as the server does, see `time_tracking.budget_check_interval_secs`
+pub async fn run_checker(pool: DbPool, notifier: Arc<dyn Notifier>, interval: Duration) {
+    let mut ticker = rocket::tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let Some(conn) = db::checkout(&pool).await else {
+            eprintln!("Budget check skipped, no database connection available");
+            continue;
+        };
+        if let Err(e) = check_budgets(&conn, notifier.as_ref()).await {
+            eprintln!("Error checking time budgets: {:?}", e);
+        }
+    }
+}
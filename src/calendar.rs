@@ -0,0 +1,327 @@
+// Everything on a calendar between two dates: tracked sessions as timed blocks (a
+// running one up to now) and the notes and lines that are due, day by day in the
+// user's time zone. A session that crosses midnight shows up on every day it touches.
+// Blocks come with layout hints, overlapping blocks of a day get a column each.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::models::{note_completion, CurrentTimer, StickyNote, TimeTrackingSession};
+use crate::reports::local_midnight_utc;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CalendarBlock {
+    pub session_id: Uuid,
+    pub space_id: i32,
+    pub space_name: String,
+    pub activity_name: String,
+    pub color: Option<String>, // of the activity
+    pub tags: Vec<String>,
+    pub note_id: Option<Uuid>,
+    pub session_start: DateTime<Utc>,
+    pub session_end: Option<DateTime<Utc>>, // None while it runs
+    pub running: bool,
+    pub start: DateTime<Utc>, // of the part on this day
+    pub end: DateTime<Utc>,
+    pub start_minute: i64, // the same as minutes after local midnight
+    pub end_minute: i64,
+    pub column: usize,  // 0 based, among the blocks it overlaps with
+    pub columns: usize, // how many columns that group of overlapping blocks needs
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DueKind {
+    Note,
+    Line,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DueItem {
+    pub kind: DueKind,
+    pub note_id: Uuid,
+    pub note_title: String,
+    pub space_id: i32,
+    pub space_name: String,
+    pub color: String,
+    pub line_id: Option<String>, // line items only
+    pub text: Option<String>,
+    pub done: bool, // a checked line, or a note whose lines are all done
+}
+
+#[derive(Serialize)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    pub blocks: Vec<CalendarBlock>,
+    pub due: Vec<DueItem>,
+}
+
+#[derive(Serialize)]
+pub struct CalendarView {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub time_zone: String,
+    pub running: Option<CurrentTimer>,
+    pub days: Vec<CalendarDay>,
+}
+
+// Gives every block a column, the lowest one free at its start, and tells each group
+// of (transitively) overlapping blocks how many columns it spans
+pub fn layout(blocks: &mut [CalendarBlock]) {
+    blocks.sort_by_key(|block| (block.start_minute, std::cmp::Reverse(block.end_minute)));
+
+    let mut group_start = 0;
+    let mut group_end = i64::MIN;
+    let mut column_ends: Vec<i64> = Vec::new();
+    for index in 0..blocks.len() {
+        let (start, end) = (blocks[index].start_minute, blocks[index].end_minute);
+        if index > group_start && start >= group_end {
+            for block in &mut blocks[group_start..index] {
+                block.columns = column_ends.len();
+            }
+            group_start = index;
+            column_ends.clear();
+        }
+
+        let column = match column_ends.iter().position(|column_end| *column_end <= start) {
+            Some(column) => {
+                column_ends[column] = end;
+                column
+            }
+            None => {
+                column_ends.push(end);
+                column_ends.len() - 1
+            }
+        };
+        blocks[index].column = column;
+        group_end = if index == group_start { end } else { group_end.max(end) };
+    }
+    let columns = column_ends.len();
+    for block in &mut blocks[group_start..] {
+        block.columns = columns;
+    }
+}
+
+fn day_index(from: NaiveDate, date: NaiveDate, days: usize) -> Option<usize> {
+    let index = (date - from).num_days();
+    (index >= 0 && (index as usize) < days).then_some(index as usize)
+}
+
+// One entry per day from `from` to `to` (inclusive), `space_names` holds the names
+// of the spaces that appear in `sessions` and `notes`
+pub fn build_days(
+    from: NaiveDate,
+    to: NaiveDate,
+    time_zone: Tz,
+    sessions: Vec<(TimeTrackingSession, Option<String>)>,
+    notes: Vec<StickyNote>,
+    space_names: &[(i32, String)],
+) -> Vec<CalendarDay> {
+    let now = chrono::Utc::now();
+    let space_name = |space_id: i32| {
+        space_names.iter().find(|(id, _)| *id == space_id).map(|(_, name)| name.clone()).unwrap_or_default()
+    };
+
+    let mut days: Vec<CalendarDay> = from
+        .iter_days()
+        .take_while(|date| *date <= to)
+        .map(|date| CalendarDay { date, blocks: Vec::new(), due: Vec::new() })
+        .collect();
+    let day_count = days.len();
+
+    for (session, color) in sessions {
+        let session_end = session.end_time.unwrap_or(now);
+        for day in days.iter_mut() {
+            let day_start = local_midnight_utc(day.date, time_zone);
+            let day_end = local_midnight_utc(day.date + Duration::days(1), time_zone);
+            let start = session.start_time.max(day_start);
+            let end = session_end.min(day_end);
+            if start >= end {
+                continue;
+            }
+
+            let start_minute = (start - day_start).num_minutes();
+            let end_minute = ((end - day_start).num_seconds() + 59) / 60;
+            day.blocks.push(CalendarBlock {
+                session_id: session.id,
+                space_id: session.space_id,
+                space_name: space_name(session.space_id),
+                activity_name: session.activity_name.clone(),
+                color: color.clone(),
+                tags: session.tags.clone(),
+                note_id: session.note_id,
+                session_start: session.start_time,
+                session_end: session.end_time,
+                running: session.end_time.is_none(),
+                start,
+                end,
+                start_minute,
+                end_minute: end_minute.max(start_minute + 1),
+                column: 0,
+                columns: 1,
+            });
+        }
+    }
+    for day in days.iter_mut() {
+        layout(&mut day.blocks);
+    }
+
+    for note in notes {
+        let lines = note.sticky_lines();
+        if let Some(index) = note.due_date.and_then(|due| day_index(from, due, day_count)) {
+            days[index].due.push(DueItem {
+                kind: DueKind::Note,
+                note_id: note.id,
+                note_title: note.title.clone(),
+                space_id: note.space_id,
+                space_name: space_name(note.space_id),
+                color: note.color.clone(),
+                line_id: None,
+                text: None,
+                done: !lines.is_empty() && note_completion(&lines) >= 100.0,
+            });
+        }
+        for line in &lines {
+            let Some(index) = line.due.and_then(|due| day_index(from, due, day_count)) else { continue };
+            days[index].due.push(DueItem {
+                kind: DueKind::Line,
+                note_id: note.id,
+                note_title: note.title.clone(),
+                space_id: note.space_id,
+                space_name: space_name(note.space_id),
+                color: note.color.clone(),
+                line_id: Some(line.id.clone()),
+                text: Some(line.text.clone()),
+                done: line.is_checked,
+            });
+        }
+    }
+
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn block(start_minute: i64, end_minute: i64) -> CalendarBlock {
+        let now = chrono::Utc::now();
+        CalendarBlock {
+            session_id: Uuid::new_v4(),
+            space_id: 1,
+            space_name: String::new(),
+            activity_name: format!("{}-{}", start_minute, end_minute),
+            color: None,
+            tags: Vec::new(),
+            note_id: None,
+            session_start: now,
+            session_end: Some(now),
+            running: false,
+            start: now,
+            end: now,
+            start_minute,
+            end_minute,
+            column: 0,
+            columns: 1,
+        }
+    }
+
+    fn session(start: &str, end: Option<&str>) -> TimeTrackingSession {
+        TimeTrackingSession {
+            id: Uuid::new_v4(),
+            user_id: "u".to_string(),
+            space_id: 1,
+            activity_name: "Writing".to_string(),
+            start_time: utc(start),
+            end_time: end.map(utc),
+            duration: None,
+            activity_id: None,
+            notes: None,
+            session_type: crate::models::SESSION_TYPE_REGULAR.to_string(),
+            last_heartbeat_at: None,
+            auto_stopped: false,
+            auto_stop_reason: None,
+            reviewed_at: None,
+            note_id: None,
+            note_line_id: None,
+            tags: Vec::new(),
+        }
+    }
+
+    fn columns(blocks: &[CalendarBlock]) -> Vec<(i64, usize, usize)> {
+        blocks.iter().map(|block| (block.start_minute, block.column, block.columns)).collect()
+    }
+
+    #[test]
+    fn blocks_apart_keep_one_column() {
+        let mut blocks = vec![block(120, 180), block(60, 120)];
+        layout(&mut blocks);
+        assert_eq!(columns(&blocks), vec![(60, 0, 1), (120, 0, 1)]);
+    }
+
+    #[test]
+    fn overlapping_blocks_share_the_width() {
+        let mut blocks = vec![block(60, 180), block(90, 120), block(150, 240), block(300, 360)];
+        layout(&mut blocks);
+        // the third block reuses the column the second one freed, the group still needs two
+        assert_eq!(columns(&blocks), vec![(60, 0, 2), (90, 1, 2), (150, 1, 2), (300, 0, 1)]);
+    }
+
+    #[test]
+    fn a_session_over_midnight_is_split_by_local_day() {
+        let sessions = vec![(session("2026-01-05T21:30:00Z", Some("2026-01-06T00:15:00Z")), None)];
+        let days = build_days(date("2026-01-05"), date("2026-01-07"), chrono_tz::Europe::Berlin, sessions, Vec::new(), &[]);
+
+        // 22:30 to 01:15 in Berlin
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[0].blocks.len(), 1);
+        assert_eq!((days[0].blocks[0].start_minute, days[0].blocks[0].end_minute), (22 * 60 + 30, 24 * 60));
+        assert_eq!(days[0].blocks[0].end, utc("2026-01-05T23:00:00Z"));
+        assert_eq!(days[1].blocks.len(), 1);
+        assert_eq!((days[1].blocks[0].start_minute, days[1].blocks[0].end_minute), (0, 75));
+        assert!(days[2].blocks.is_empty());
+    }
+
+    #[test]
+    fn due_notes_and_lines_land_on_their_day() {
+        let note = StickyNote {
+            id: Uuid::new_v4(),
+            space_id: 1,
+            user_id: "u".to_string(),
+            title: "Launch".to_string(),
+            color: "FFEB3B".to_string(),
+            text_color: "000000".to_string(),
+            created_at: chrono::Utc::now(),
+            updated_at: None,
+            tags: None,
+            lines: Some(vec![
+                "Copy|red|true|||2026-01-06|l1".to_string(),
+                "Later|red|false|||2026-02-01|l2".to_string(),
+            ]),
+            archived: false,
+            due_date: Some(date("2026-01-07")),
+        };
+        let spaces = [(1, "acme".to_string())];
+
+        let days = build_days(date("2026-01-05"), date("2026-01-07"), chrono_tz::UTC, Vec::new(), vec![note], &spaces);
+
+        assert!(days[0].due.is_empty());
+        assert_eq!(days[1].due.len(), 1);
+        assert_eq!(days[1].due[0].kind, DueKind::Line);
+        assert_eq!(days[1].due[0].line_id.as_deref(), Some("l1"));
+        assert!(days[1].due[0].done);
+        assert_eq!(days[2].due.len(), 1);
+        assert_eq!(days[2].due[0].kind, DueKind::Note);
+        assert_eq!(days[2].due[0].space_name, "acme");
+        assert!(!days[2].due[0].done);
+    }
+}
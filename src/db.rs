@@ -1,9 +1,14 @@
 use diesel::prelude::*;
 use rocket_sync_db_pools::database;
 use uuid::Uuid;
-use chrono::NaiveDateTime;
-use crate::models::StickyLine;
-use crate::models::{StickyNote, TimeTrackingSession};
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::reports::{Granularity, ReportRow, TagReportRow};
+use crate::pomodoro::{self, PomodoroCount, Transition};
+use crate::habits::DayTotal;
+use crate::review::CheckedLineRow;
+use crate::models::{NewStickyNote, StickyLine, TimeTrackingSessionWithIntervals, active_seconds, assign_line_ids, fit_intervals, line_check_changes, wiki_link_titles, LINK_KIND_EXPLICIT, LINK_KIND_WIKI, DEFAULT_NOTE_COLOR, DEFAULT_NOTE_TEXT_COLOR};
+use crate::models::{Activity, ActivityBudget, ActivityChoice, BudgetNotification, CalendarFeed, Goal, GoalSnapshot, Habit, HabitCompletion, LineCheckEvent, PomodoroRun, SessionAuditEntry, SessionChanges, SessionDetails, SessionSaveOutcome, TimerStart, UpdateActivity, SESSION_TYPE_REGULAR};
+use crate::models::{BulkAction, BulkItemResult, NoteAttachment, NoteDeletion, NoteLineTime, SavedReview, NoteLink, NoteTime, SessionFilter, SessionInterval, SpaceSettings, StickyNote, TimeTrackingSession, UserSettings};
 
 
 
@@ -11,6 +16,13 @@ use crate::models::{StickyNote, TimeTrackingSession};
 #[database("postgres_database")]
 pub struct DbConn(diesel::PgConnection);
 
+pub type DbPool = rocket_sync_db_pools::ConnectionPool<DbConn, diesel::PgConnection>;
+
+// A connection for work that runs outside of a request, like the background checkers
+pub async fn checkout(pool: &DbPool) -> Option<DbConn> {
+    pool.get().await.map(DbConn)
+}
+
 pub async fn get_user_spaces(conn: &DbConn, user_id_param: &str) -> Result<Vec<String>, diesel::result::Error> {
     use crate::schema::spaces::dsl::*;
 
@@ -46,6 +58,181 @@ pub async fn create_space(conn: &DbConn, user_id: String, space_name: String) ->
 
 
 
+pub async fn get_space_name(
+    conn: &DbConn,
+    space_id_param: i32,
+) -> Result<String, diesel::result::Error> {
+    use crate::schema::spaces::dsl::*;
+
+    conn.run(move |c| {
+        spaces
+            .find(space_id_param)
+            .select(space_name)
+            .first::<String>(c)
+    })
+    .await
+}
+
+pub async fn get_space_settings(
+    conn: &DbConn,
+    space_id_param: i32,
+) -> Result<Option<SpaceSettings>, diesel::result::Error> {
+    use crate::schema::space_settings::dsl::*;
+
+    conn.run(move |c| {
+        space_settings
+            .find(space_id_param)
+            .first::<SpaceSettings>(c)
+            .optional()
+    })
+    .await
+}
+
+pub async fn save_space_settings(
+    conn: &DbConn,
+    settings: SpaceSettings,
+) -> Result<SpaceSettings, diesel::result::Error> {
+    use crate::schema::space_settings::dsl::*;
+
+    conn.run(move |c| {
+        diesel::insert_into(space_settings)
+            .values(&settings)
+            .on_conflict(space_id)
+            .do_update()
+            .set(&settings)
+            .get_result(c)
+    })
+    .await
+}
+
+// The user's spaces with their settings, all of them or just `space_id_param`
+pub async fn get_spaces_with_settings(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+) -> Result<Vec<(crate::models::Space, Option<SpaceSettings>)>, diesel::result::Error> {
+    use crate::schema::{space_settings, spaces};
+
+    conn.run(move |c| {
+        let mut query = spaces::table
+            .left_join(space_settings::table)
+            .filter(spaces::user_id.eq(user_id_param))
+            .into_boxed();
+        if let Some(space) = space_id_param {
+            query = query.filter(spaces::id.eq(space));
+        }
+        query
+            .order(spaces::space_name.asc())
+            .load::<(crate::models::Space, Option<SpaceSettings>)>(c)
+    })
+    .await
+}
+
+pub async fn get_user_settings(
+    conn: &DbConn,
+    user_id_param: String,
+) -> Result<Option<UserSettings>, diesel::result::Error> {
+    use crate::schema::user_settings::dsl::*;
+
+    conn.run(move |c| {
+        user_settings
+            .find(user_id_param)
+            .first::<UserSettings>(c)
+            .optional()
+    })
+    .await
+}
+
+pub async fn save_user_settings(
+    conn: &DbConn,
+    settings: UserSettings,
+) -> Result<UserSettings, diesel::result::Error> {
+    use crate::schema::user_settings::dsl::*;
+
+    conn.run(move |c| {
+        diesel::insert_into(user_settings)
+            .values(&settings)
+            .on_conflict(user_id)
+            .do_update()
+            .set(&settings)
+            .get_result(c)
+    })
+    .await
+}
+
+// The time zone the user's days are counted in: their own setting, otherwise the space's
+pub async fn get_time_zone_name(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+) -> Result<Option<String>, diesel::result::Error> {
+    use crate::schema::{space_settings, user_settings};
+
+    conn.run(move |c| {
+        let own: Option<String> = user_settings::table
+            .find(user_id_param)
+            .select(user_settings::time_zone)
+            .first::<Option<String>>(c)
+            .optional()?
+            .flatten();
+        if own.is_some() {
+            return Ok(own);
+        }
+
+        match space_id_param {
+            Some(space) => space_settings::table
+                .find(space)
+                .select(space_settings::time_zone)
+                .first::<Option<String>>(c)
+                .optional()
+                .map(Option::flatten),
+            None => Ok(None),
+        }
+    })
+    .await
+}
+
+pub async fn get_calendar_feed(conn: &DbConn, user_id_param: String) -> Result<Option<CalendarFeed>, diesel::result::Error> {
+    use crate::schema::calendar_feeds::dsl::*;
+
+    conn.run(move |c| calendar_feeds.find(user_id_param).first::<CalendarFeed>(c).optional()).await
+}
+
+// A new token replaces the old one, which stops working right away
+pub async fn save_calendar_feed(conn: &DbConn, feed: CalendarFeed) -> Result<CalendarFeed, diesel::result::Error> {
+    use crate::schema::calendar_feeds::dsl::*;
+
+    conn.run(move |c| {
+        diesel::insert_into(calendar_feeds)
+            .values(&feed)
+            .on_conflict(user_id)
+            .do_update()
+            .set((token_hash.eq(&feed.token_hash), created_at.eq(feed.created_at), last_used_at.eq(None::<DateTime<Utc>>)))
+            .get_result(c)
+    })
+    .await
+}
+
+pub async fn delete_calendar_feed(conn: &DbConn, user_id_param: String) -> Result<usize, diesel::result::Error> {
+    use crate::schema::calendar_feeds::dsl::*;
+
+    conn.run(move |c| diesel::delete(calendar_feeds.find(user_id_param)).execute(c)).await
+}
+
+// The owner of the feed with this token hash, marking the feed as used
+pub async fn use_calendar_feed(conn: &DbConn, hash: String) -> Result<Option<String>, diesel::result::Error> {
+    use crate::schema::calendar_feeds::dsl::*;
+
+    conn.run(move |c| {
+        diesel::update(calendar_feeds.filter(token_hash.eq(hash)))
+            .set(last_used_at.eq(Some(chrono::Utc::now())))
+            .returning(user_id)
+            .get_result::<String>(c)
+            .optional()
+    })
+    .await
+}
+
 // pub async fn create_space(conn: &DbConn, user_id: &str, space_name: &str) {
 //     use crate::schema::spaces;
 
@@ -88,29 +275,29 @@ fn format_lines_for_storage(lines: Option<Vec<StickyLine>>) -> Option<Vec<String
 // }
 
 
+// Colors `note` leaves out are the app defaults
 pub async fn create_sticky_note(
     conn: &DbConn,
     user_id: &str,
-    titile: &str,
     space_id: i32,
-    color: &str,
-    text_color: &str,
-    tags: Option<Vec<String>>,
-    lines: Option<Vec<StickyLine>>, // Incoming as Vec<StickyLine>
+    note: NewStickyNote,
 ) -> Result<StickyNote, diesel::result::Error> {
     use crate::schema::sticky_notes;
 
+    let lines: Option<Vec<StickyLine>> = note.lines.map(|lines| lines.iter().map(|line| StickyLine::from_string(line)).collect());
     let new_note = StickyNote {
         id: Uuid::new_v4(),
         user_id: user_id.to_string(),
-        title: titile.to_string(),
-        space_id: space_id,
-        color: color.to_string(),
-        text_color: text_color.to_string(),
-        created_at: chrono::Utc::now().naive_utc(),
-        updated_at: Some(chrono::Utc::now().naive_utc()),
-        tags: tags,
-        lines: format_lines_for_storage(lines), // Convert StickyLine to Vec<String> for storage
+        title: note.title,
+        space_id,
+        color: note.color.unwrap_or_else(|| DEFAULT_NOTE_COLOR.to_string()),
+        text_color: note.text_color.unwrap_or_else(|| DEFAULT_NOTE_TEXT_COLOR.to_string()),
+        created_at: chrono::Utc::now(),
+        updated_at: Some(chrono::Utc::now()),
+        tags: note.tags,
+        lines: format_lines_for_storage(lines.map(|lines| assign_line_ids(&[], lines))), // Convert StickyLine to Vec<String> for storage
+        archived: false,
+        due_date: note.due_date,
     };
 
     conn.run(move |c| {
@@ -151,34 +338,74 @@ pub async fn get_sticky_notes(
     conn: &DbConn,
     user_id_param: String,
     space_id_param: i32,
+    include_archived: bool,
 ) -> Result<Vec<StickyNote>, diesel::result::Error> {
     use crate::schema::sticky_notes::dsl::*;
 
     conn.run(move |c| {
-        sticky_notes
+        let mut query = sticky_notes
             .filter(user_id.eq(user_id_param)) 
             .filter(space_id.eq(space_id_param))
-            .load::<StickyNote>(c)
+            .into_boxed();
+
+        if !include_archived {
+            query = query.filter(archived.eq(false));
+        }
+
+        query.load::<StickyNote>(c)
+    })
+    .await
+}
+
+pub async fn get_sticky_note(
+    conn: &DbConn,
+    user_id_param: String,
+    note_id: Uuid,
+) -> Result<StickyNote, diesel::result::Error> {
+    use crate::schema::sticky_notes::dsl::*;
+
+    conn.run(move |c| {
+        sticky_notes
+            .filter(id.eq(note_id))
+            .filter(user_id.eq(user_id_param))
+            .first::<StickyNote>(c)
     })
     .await
 }
 
 pub async fn update_sticky_header(
     conn: &DbConn,
-    user_id: String,
-    space_id: i32,
+    user_id_param: String,
+    space_id_param: i32,
     note_id: Uuid,
     new_title: String,
 ) -> Result<StickyNote, diesel::result::Error> {
     use crate::schema::sticky_notes::dsl::*;
     
     conn.run(move |c| {
-        diesel::update(sticky_notes.find(note_id))
-            .set((
-                title.eq(new_title), // Update only the title
-                updated_at.eq(Some(chrono::Utc::now().naive_utc())), // Update the timestamp
-            ))
-            .get_result(c)
+        c.transaction(|c| {
+            let old_title: String = sticky_notes
+                .filter(id.eq(note_id))
+                .filter(user_id.eq(&user_id_param))
+                .filter(space_id.eq(space_id_param))
+                .select(title)
+                .first(c)?;
+
+            let note: StickyNote = diesel::update(sticky_notes.find(note_id))
+                .set((
+                    title.eq(new_title), // Update only the title
+                    updated_at.eq(Some(chrono::Utc::now())), // Update the timestamp
+                ))
+                .get_result(c)?;
+
+            // links point at the note id, only the cached title and [[...]] text need to follow
+            if old_title != note.title {
+                rename_link_target(c, &note, &old_title)?;
+            }
+            relink_broken_links(c, &note)?;
+
+            Ok(note)
+        })
     })
     .await
 }
@@ -186,201 +413,2435 @@ pub async fn update_sticky_header(
 
 pub async fn update_sticky_note(
     conn: &DbConn,
-    user_id: String,
-    space_id: i32,
+    user_id_param: String,
+    space_id_param: i32,
     note_id: Uuid,
-    color: Option<String>,
-    text_color: Option<String>,
-    tags: Option<Vec<String>>,
+    color_param: Option<String>,
+    text_color_param: Option<String>,
+    tags_param: Option<Vec<String>>,
     newlines: Option<Vec<StickyLine>>,
 ) -> Result<StickyNote, diesel::result::Error> {
     use crate::schema::sticky_notes::dsl::*;
     
     conn.run(move |c| {
-        diesel::update(sticky_notes.find(note_id))
-            .set((
-                space_id.eq(space_id),
-                color.eq(color),
-                text_color.eq(text_color),
-                tags.eq(tags),
-                lines.eq(format_lines_for_storage(newlines)),
-                updated_at.eq(Some(chrono::Utc::now().naive_utc())),
-            ))
-            .get_result(c)
+        c.transaction(|c| {
+            let note_query = sticky_notes
+                .filter(id.eq(note_id))
+                .filter(user_id.eq(user_id_param))
+                .filter(space_id.eq(space_id_param));
+            let old_note: StickyNote = note_query.clone().first(c)?;
+
+            let note: StickyNote = diesel::update(note_query)
+                .set((
+                    color.eq(color_param.unwrap_or_else(|| old_note.color.clone())),
+                    text_color.eq(text_color_param.unwrap_or_else(|| old_note.text_color.clone())),
+                    tags.eq(tags_param),
+                    lines.eq(format_lines_for_storage(newlines.map(|newlines| assign_line_ids(&old_note.sticky_lines(), newlines)))),
+                    updated_at.eq(Some(chrono::Utc::now())),
+                ))
+                .get_result(c)?;
+
+            record_line_checks(c, &old_note, &note)?;
+
+            Ok(note)
+        })
     })
     .await
 }
 
+// Keeps a check event for every line that got checked or unchecked, habits are built on them
+fn record_line_checks(c: &mut PgConnection, old_note: &StickyNote, note: &StickyNote) -> QueryResult<()> {
+    use crate::schema::line_check_events;
+
+    let now = chrono::Utc::now();
+    let lines = note.sticky_lines();
+    let events: Vec<LineCheckEvent> = line_check_changes(&old_note.sticky_lines(), &lines)
+        .into_iter()
+        .map(|line| LineCheckEvent {
+            id: Uuid::new_v4(),
+            note_id: note.id,
+            user_id: note.user_id.clone(),
+            space_id: note.space_id,
+            line_text: line.text.clone(),
+            checked: line.is_checked,
+            created_at: now,
+            line_id: Some(line.id.clone()),
+        })
+        .collect();
+
+    if !events.is_empty() {
+        diesel::insert_into(line_check_events::table).values(&events).execute(c)?;
+    }
+    Ok(())
+}
+
 
 
 
 
 
 
+// Deletes the user's note, returns its attachments (their files are left to the caller)
+// or None when the note does not exist
 pub async fn delete_sticky_note(
     conn: &DbConn,
+    user_id_param: String,
     note_id: Uuid,
-) -> Result<usize, diesel::result::Error> {
+) -> Result<Option<Vec<NoteAttachment>>, diesel::result::Error> {
+    use crate::schema::note_attachments;
     use crate::schema::sticky_notes::dsl::*;
 
     conn.run(move |c| {
-        diesel::delete(sticky_notes.filter(id.eq(note_id)))
-            .execute(c)
+        c.transaction(|c| {
+            let note = sticky_notes
+                .filter(id.eq(note_id))
+                .filter(user_id.eq(user_id_param))
+                .first::<StickyNote>(c)
+                .optional()?;
+            let Some(note) = note else { return Ok(None) };
+
+            let attachments: Vec<NoteAttachment> = note_attachments::table
+                .filter(note_attachments::note_id.eq(note.id))
+                .load(c)?;
+            record_note_deletion(c, &note)?;
+            diesel::delete(sticky_notes.filter(id.eq(note_id)))
+                .execute(c)?;
+            Ok(Some(attachments))
+        })
     })
     .await
 }
 
-
-
-// time tracking
-
-pub async fn create_time_tracking_session(
+pub async fn set_note_due_date(
     conn: &DbConn,
-    user_id: String,
-    space_id: i32,
-    activity_name: String,
-    start_time: NaiveDateTime,
-) -> Result<TimeTrackingSession, diesel::result::Error> {
-    use crate::schema::time_tracking_sessions;
-
-    let new_session = TimeTrackingSession {
-        id: Uuid::new_v4(),
-        user_id,
-        space_id,
-        activity_name,
-        start_time,
-        end_time: None,
-        duration: None,
-    };
+    user_id_param: String,
+    note_id: Uuid,
+    due: Option<NaiveDate>,
+) -> Result<StickyNote, diesel::result::Error> {
+    use crate::schema::sticky_notes::dsl::*;
 
     conn.run(move |c| {
-        diesel::insert_into(time_tracking_sessions::table)
-            .values(&new_session)
+        diesel::update(sticky_notes.filter(id.eq(note_id)).filter(user_id.eq(user_id_param)))
+            .set((due_date.eq(due), updated_at.eq(Some(chrono::Utc::now()))))
             .get_result(c)
     })
     .await
-    .map_err(|e| {
-        eprintln!("Error creating new Time Track session: {:?}", e);
-        e
+}
+
+// Every note of the user (in one space or all of them) that is due or has a line
+// that is, the lines are picked out by the caller
+pub async fn get_notes_with_due_dates(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+) -> Result<Vec<StickyNote>, diesel::result::Error> {
+    use crate::schema::sticky_notes::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = sticky_notes
+            .filter(user_id.eq(user_id_param))
+            .filter(archived.eq(false))
+            .filter(due_date.is_not_null().or(diesel::dsl::sql::<diesel::sql_types::Bool>(
+                "EXISTS (SELECT 1 FROM unnest(lines) AS line WHERE split_part(line, '|', 6) <> '')",
+            )))
+            .into_boxed();
+        if let Some(space) = space_id_param {
+            query = query.filter(space_id.eq(space));
+        }
+        query.order(created_at.asc()).load::<StickyNote>(c)
     })
+    .await
 }
 
+// Keeps the title and lines of a note that is about to be deleted, for the weekly review
+fn record_note_deletion(c: &mut PgConnection, note: &StickyNote) -> QueryResult<()> {
+    use crate::schema::note_deletions;
 
-// Assuming `end_time` is passed as a UNIX timestamp (seconds since epoch) from the frontend
-pub async fn complete_time_tracking_session(
-    conn: &DbConn,
-    session_id: Uuid,
-    end_time_timestamp: i64, // UNIX timestamp
-) -> Result<TimeTrackingSession, diesel::result::Error> {
-    use crate::schema::time_tracking_sessions::dsl::*;
+    diesel::insert_into(note_deletions::table)
+        .values(&NoteDeletion::from(note))
+        .execute(c)
+        .map(|_| ())
+}
 
-    // Convert UNIX timestamp to NaiveDateTime
-    let end_time_pending = NaiveDateTime::from_timestamp_opt(end_time_timestamp, 0)
-        .ok_or_else(|| diesel::result::Error::NotFound)?; // Handle conversion error
 
-    // Fetch the session
-    let session = conn.run(move |c| {
-        time_tracking_sessions
-            .filter(id.eq(session_id))
-            .first::<TimeTrackingSession>(c)
-    }).await?;
 
-    // Calculate the duration
-    let start_time_pending = session.start_time;
-    let duration_pending = end_time_pending.signed_duration_since(start_time_pending).num_seconds();
+// Applies one bulk action to each note inside a single transaction. Every note gets
+// its own savepoint so one bad item is reported without undoing the others.
+// Returns the per note results and the attachments of deleted notes, whose files
+// still have to be removed from storage once the transaction has committed.
+pub async fn bulk_update_notes(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: i32,
+    note_ids: Vec<Uuid>,
+    action: BulkAction,
+    target_space_id: Option<i32>,
+) -> Result<(Vec<BulkItemResult>, Vec<NoteAttachment>), diesel::result::Error> {
+    use crate::schema::line_check_events;
+    use crate::schema::note_attachments;
+    use crate::schema::sticky_notes::dsl::*;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let mut results = Vec::new();
+            let mut removed_attachments = Vec::new();
+
+            for note_id in note_ids {
+                let outcome = c.transaction(|c| {
+                    let note = sticky_notes
+                        .filter(id.eq(note_id))
+                        .filter(user_id.eq(&user_id_param))
+                        .filter(space_id.eq(space_id_param))
+                        .first::<StickyNote>(c)
+                        .optional()?;
+
+                    let Some(note) = note else { return Ok(false) };
+                    let target = sticky_notes.find(note.id);
+                    let now = Some(chrono::Utc::now());
+
+                    match &action {
+                        BulkAction::Delete => {
+                            let attachments: Vec<NoteAttachment> = note_attachments::table
+                                .filter(note_attachments::note_id.eq(note.id))
+                                .load(c)?;
+                            record_note_deletion(c, &note)?;
+                            diesel::delete(target).execute(c)?;
+                            removed_attachments.extend(attachments);
+                        }
+                        BulkAction::Archive | BulkAction::Unarchive => {
+                            diesel::update(target)
+                                .set((archived.eq(matches!(action, BulkAction::Archive)), updated_at.eq(now)))
+                                .execute(c)?;
+                        }
+                        BulkAction::Recolor { color: new_color, text_color: new_text_color } => {
+                            diesel::update(target)
+                                .set((
+                                    color.eq(new_color.clone().unwrap_or(note.color)),
+                                    text_color.eq(new_text_color.clone().unwrap_or(note.text_color)),
+                                    updated_at.eq(now),
+                                ))
+                                .execute(c)?;
+                        }
+                        BulkAction::AddTags { tags: added } => {
+                            let mut merged = note.tags.unwrap_or_default();
+                            for tag in added {
+                                if !merged.contains(tag) {
+                                    merged.push(tag.clone());
+                                }
+                            }
+                            diesel::update(target)
+                                .set((tags.eq(Some(merged)), updated_at.eq(now)))
+                                .execute(c)?;
+                        }
+                        BulkAction::RemoveTags { tags: removed } => {
+                            let remaining: Vec<String> = note.tags
+                                .unwrap_or_default()
+                                .into_iter()
+                                .filter(|tag| !removed.contains(tag))
+                                .collect();
+                            diesel::update(target)
+                                .set((tags.eq(Some(remaining)), updated_at.eq(now)))
+                                .execute(c)?;
+                        }
+                        BulkAction::Move { .. } => {
+                            let new_space = target_space_id.ok_or(diesel::result::Error::NotFound)?;
+                            diesel::update(target)
+                                .set((space_id.eq(new_space), updated_at.eq(now)))
+                                .execute(c)?;
+                            // the checks count towards the space the note lives in
+                            diesel::update(line_check_events::table.filter(line_check_events::note_id.eq(note.id)))
+                                .set(line_check_events::space_id.eq(new_space))
+                                .execute(c)?;
+                        }
+                    }
+
+                    Ok::<bool, diesel::result::Error>(true)
+                });
+
+                results.push(match outcome {
+                    Ok(true) => BulkItemResult { note_id, status: "ok".to_string(), error: None },
+                    Ok(false) => BulkItemResult { note_id, status: "not_found".to_string(), error: None },
+                    Err(e) => BulkItemResult { note_id, status: "error".to_string(), error: Some(e.to_string()) },
+                });
+            }
+
+            Ok((results, removed_attachments))
+        })
+    })
+    .await
+}
 
-    // println!("Backend start time: {:?}", start_time_pending);
-    // println!("Backend end time: {:?}", end_time_pending);
-    // println!("Duration in seconds: {}", duration_pending);
 
+// note links
+
+pub async fn sync_wiki_links(
+    conn: &DbConn,
+    note_id: Uuid,
+) -> Result<(), diesel::result::Error> {
+    use crate::schema::note_links;
+    use crate::schema::sticky_notes;
 
-    // Update the session with the new end_time and duration
     conn.run(move |c| {
-        diesel::update(time_tracking_sessions.filter(id.eq(session_id)))
-            .set((
-                end_time.eq(Some(end_time_pending)),
-                duration.eq(Some(duration_pending)),
-            ))
-            .get_result(c)
+        c.transaction(|c| {
+            let note: StickyNote = sticky_notes::table.find(note_id).first(c)?;
+
+            diesel::delete(
+                note_links::table
+                    .filter(note_links::source_note_id.eq(note.id))
+                    .filter(note_links::kind.eq(LINK_KIND_WIKI)),
+            )
+            .execute(c)?;
+
+            let mut links = Vec::new();
+            for linked_title in wiki_link_titles(&note.sticky_lines()) {
+                let target: Option<Uuid> = sticky_notes::table
+                    .filter(sticky_notes::user_id.eq(&note.user_id))
+                    .filter(sticky_notes::space_id.eq(note.space_id))
+                    .filter(sticky_notes::title.eq(&linked_title))
+                    .filter(sticky_notes::id.ne(note.id))
+                    .select(sticky_notes::id)
+                    .first(c)
+                    .optional()?;
+
+                links.push(NoteLink {
+                    id: Uuid::new_v4(),
+                    source_note_id: note.id,
+                    target_note_id: target,
+                    target_title: linked_title,
+                    kind: LINK_KIND_WIKI.to_string(),
+                    created_at: chrono::Utc::now(),
+                });
+            }
+
+            diesel::insert_into(note_links::table)
+                .values(&links)
+                .execute(c)?;
+
+            relink_broken_links(c, &note)
+        })
     })
     .await
 }
 
+// Points broken wiki links in the same space at a note that now carries their title
+fn relink_broken_links(c: &mut PgConnection, note: &StickyNote) -> QueryResult<()> {
+    use crate::schema::note_links;
+    use crate::schema::sticky_notes;
+
+    let space_notes = sticky_notes::table
+        .filter(sticky_notes::user_id.eq(&note.user_id))
+        .filter(sticky_notes::space_id.eq(note.space_id))
+        .filter(sticky_notes::id.ne(note.id))
+        .select(sticky_notes::id);
+
+    diesel::update(
+        note_links::table
+            .filter(note_links::target_note_id.is_null())
+            .filter(note_links::kind.eq(LINK_KIND_WIKI))
+            .filter(note_links::target_title.eq(&note.title))
+            .filter(note_links::source_note_id.eq_any(space_notes)),
+    )
+    .set(note_links::target_note_id.eq(Some(note.id)))
+    .execute(c)
+    .map(|_| ())
+}
+
+// Keeps links to a renamed note intact by rewriting `[[old]]` in the linking notes
+fn rename_link_target(c: &mut PgConnection, note: &StickyNote, old_title: &str) -> QueryResult<()> {
+    use crate::schema::note_links;
+    use crate::schema::sticky_notes;
+
+    // only the owner's notes follow, wiki links never leave the space
+    let own_notes = sticky_notes::table
+        .filter(sticky_notes::user_id.eq(&note.user_id))
+        .select(sticky_notes::id);
+    let space_notes = sticky_notes::table
+        .filter(sticky_notes::user_id.eq(&note.user_id))
+        .filter(sticky_notes::space_id.eq(note.space_id))
+        .select(sticky_notes::id);
+
+    diesel::update(
+        note_links::table
+            .filter(note_links::target_note_id.eq(note.id))
+            .filter(note_links::source_note_id.eq_any(own_notes)),
+    )
+    .set(note_links::target_title.eq(&note.title))
+    .execute(c)?;
+
+    let source_ids: Vec<Uuid> = note_links::table
+        .filter(note_links::target_note_id.eq(note.id))
+        .filter(note_links::kind.eq(LINK_KIND_WIKI))
+        .filter(note_links::source_note_id.eq_any(space_notes))
+        .select(note_links::source_note_id)
+        .distinct()
+        .load(c)?;
+
+    let old_link = format!("[[{}]]", old_title);
+    let new_link = format!("[[{}]]", note.title);
+
+    for source_id in source_ids {
+        let source_lines: Option<Vec<String>> = sticky_notes::table
+            .find(source_id)
+            .select(sticky_notes::lines)
+            .first(c)?;
+
+        let Some(source_lines) = source_lines else { continue };
+        let renamed: Vec<String> = source_lines.iter().map(|line| line.replace(&old_link, &new_link)).collect();
+
+        if renamed != source_lines {
+            diesel::update(sticky_notes::table.find(source_id))
+                .set(sticky_notes::lines.eq(Some(renamed)))
+                .execute(c)?;
+        }
+    }
+
+    Ok(())
+}
 
-pub async fn get_all_time_tracking_sessions(
+pub async fn create_note_link(
     conn: &DbConn,
     user_id_param: String,
-    space_id_param: i32,
-) -> Result<Vec<TimeTrackingSession>, diesel::result::Error> {
-    use crate::schema::time_tracking_sessions::dsl::*;
+    source_id: Uuid,
+    target_id: Uuid,
+) -> Result<NoteLink, diesel::result::Error> {
+    use crate::schema::note_links;
+    use crate::schema::sticky_notes;
 
     conn.run(move |c| {
-        time_tracking_sessions
-            .filter(user_id.eq(user_id_param)) 
-            .filter(space_id.eq(space_id_param))
-            .load::<TimeTrackingSession>(c)
+        let target_title: String = sticky_notes::table
+            .filter(sticky_notes::id.eq(target_id))
+            .filter(sticky_notes::user_id.eq(user_id_param))
+            .select(sticky_notes::title)
+            .first(c)?;
+
+        let link = NoteLink {
+            id: Uuid::new_v4(),
+            source_note_id: source_id,
+            target_note_id: Some(target_id),
+            target_title,
+            kind: LINK_KIND_EXPLICIT.to_string(),
+            created_at: chrono::Utc::now(),
+        };
+
+        diesel::insert_into(note_links::table)
+            .values(&link)
+            .get_result(c)
     })
     .await
 }
 
-// pub async fn get_a_tracking_session(
-//     conn: &DbConn,
-//     session_id: Uuid,
-// ) -> Result<TimeTrackingSession, diesel::result::Error> {
-//     use crate::schema::time_tracking_sessions::dsl::*;
+pub async fn get_note_links(
+    conn: &DbConn,
+    note_id: Uuid,
+) -> Result<Vec<NoteLink>, diesel::result::Error> {
+    use crate::schema::note_links::dsl::*;
 
-//     conn.run(move |c| {
-//         time_tracking_sessions
-//             .filter(id.eq(session_id))
-//             .first::<TimeTrackingSession>(c)
-//     })
-//     .await
-// }
+    conn.run(move |c| {
+        note_links
+            .filter(source_note_id.eq(note_id))
+            .order(created_at.asc())
+            .load::<NoteLink>(c)
+    })
+    .await
+}
 
+pub async fn get_backlinks(
+    conn: &DbConn,
+    note_id: Uuid,
+) -> Result<Vec<(NoteLink, String)>, diesel::result::Error> {
+    use crate::schema::note_links;
+    use crate::schema::sticky_notes;
 
-pub async fn delete_time_tracking_session(
+    conn.run(move |c| {
+        note_links::table
+            .inner_join(sticky_notes::table)
+            .filter(note_links::target_note_id.eq(note_id))
+            .order(note_links::created_at.asc())
+            .select((note_links::all_columns, sticky_notes::title))
+            .load::<(NoteLink, String)>(c)
+    })
+    .await
+}
+
+pub async fn delete_note_link(
     conn: &DbConn,
-    session_id: Uuid,
+    user_id_param: String,
+    link_id: Uuid,
 ) -> Result<usize, diesel::result::Error> {
-    use crate::schema::time_tracking_sessions::dsl::*;
+    use crate::schema::note_links;
+    use crate::schema::sticky_notes;
 
     conn.run(move |c| {
-        diesel::delete(time_tracking_sessions.filter(id.eq(session_id)))
-            .execute(c)
+        let own_notes = sticky_notes::table
+            .filter(sticky_notes::user_id.eq(user_id_param))
+            .select(sticky_notes::id);
+
+        diesel::delete(
+            note_links::table
+                .filter(note_links::id.eq(link_id))
+                .filter(note_links::source_note_id.eq_any(own_notes)),
+        )
+        .execute(c)
     })
     .await
 }
 
 
-// pub async fn check_and_send_notification(
-//     conn: &DbConn,
-//     session_id: Uuid,
-//     time_limit: Duration,
-// ) -> Result<(), diesel::result::Error> {
-//     use crate::schema::time_tracking_sessions::dsl::{
-//         time_tracking_sessions, limit_notification_sent, id
-//     };
+// attachments
 
-//     let session = get_a_tracking_session(conn, session_id).await?;
+pub async fn create_note_attachment(
+    conn: &DbConn,
+    attachment: NoteAttachment,
+) -> Result<NoteAttachment, diesel::result::Error> {
+    use crate::schema::note_attachments;
 
-//     if let Some(end_time) = session.end_time {
-//         let session_duration = end_time.signed_duration_since(session.start_time);
+    conn.run(move |c| {
+        diesel::insert_into(note_attachments::table)
+            .values(&attachment)
+            .get_result(c)
+    })
+    .await
+    .map_err(|e| {
+        eprintln!("Error saving note attachment: {:?}", e);
+        e
+    })
+}
 
-//         if session_duration > time_limit && !session.limit_notification_sent {
-//             // Send notification here (logic depends on your notification system)
+pub async fn get_note_attachments(
+    conn: &DbConn,
+    note_id_param: Uuid,
+) -> Result<Vec<NoteAttachment>, diesel::result::Error> {
+    use crate::schema::note_attachments::dsl::*;
 
-//             // Update the session to indicate that the notification has been sent
-//             conn.run(move |c| {
-//                 diesel::update(time_tracking_sessions.find(session_id))
-//                     .set(limit_notification_sent.eq(true))
-//                     .execute(c)
-//             })
-//             .await?;
-//         }
-//     }
+    conn.run(move |c| {
+        note_attachments
+            .filter(note_id.eq(note_id_param))
+            .order(created_at.asc())
+            .load::<NoteAttachment>(c)
+    })
+    .await
+}
 
-//     Ok(())
-// }
+pub async fn get_note_attachment(
+    conn: &DbConn,
+    user_id_param: String,
+    attachment_id: Uuid,
+) -> Result<NoteAttachment, diesel::result::Error> {
+    use crate::schema::note_attachments::dsl::*;
 
+    conn.run(move |c| {
+        note_attachments
+            .filter(id.eq(attachment_id))
+            .filter(user_id.eq(user_id_param))
+            .first::<NoteAttachment>(c)
+    })
+    .await
+}
 
+pub async fn delete_note_attachment(
+    conn: &DbConn,
+    attachment_id: Uuid,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::note_attachments::dsl::*;
 
+    conn.run(move |c| {
+        diesel::delete(note_attachments.filter(id.eq(attachment_id)))
+            .execute(c)
+    })
+    .await
+}
+
+
+// activities
+
+diesel::define_sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+// The space's activity called `name` (ignoring case), created on first use
+fn find_or_create_activity(c: &mut PgConnection, owner: &str, space: i32, activity_name: &str) -> QueryResult<Activity> {
+    use crate::schema::activities::dsl::*;
+
+    let activity_name = activity_name.trim();
+    let existing = activities
+        .filter(space_id.eq(space))
+        .filter(lower(name).eq(activity_name.to_lowercase()))
+        .first::<Activity>(c)
+        .optional()?;
+    if let Some(activity) = existing {
+        return Ok(activity);
+    }
+
+    diesel::insert_into(activities)
+        .values(&Activity {
+            id: Uuid::new_v4(),
+            space_id: space,
+            user_id: owner.to_string(),
+            name: activity_name.to_string(),
+            color: None,
+            category: None,
+            billable: false,
+            hourly_rate_cents: None,
+            archived: false,
+            created_at: chrono::Utc::now(),
+        })
+        .get_result(c)
+}
+
+pub async fn resolve_activity(
+    conn: &DbConn,
+    user_id: String,
+    space_id: i32,
+    activity_name: String,
+) -> Result<Activity, diesel::result::Error> {
+    conn.run(move |c| find_or_create_activity(c, &user_id, space_id, &activity_name)).await
+}
+
+pub async fn get_activities(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: i32,
+    include_archived: bool,
+) -> Result<Vec<Activity>, diesel::result::Error> {
+    use crate::schema::activities::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = activities
+            .filter(user_id.eq(user_id_param))
+            .filter(space_id.eq(space_id_param))
+            .into_boxed();
+        if !include_archived {
+            query = query.filter(archived.eq(false));
+        }
+        query.order(lower(name).asc()).load::<Activity>(c)
+    })
+    .await
+}
+
+pub async fn get_activity(
+    conn: &DbConn,
+    user_id_param: String,
+    activity_id: Uuid,
+) -> Result<Activity, diesel::result::Error> {
+    use crate::schema::activities::dsl::*;
+
+    conn.run(move |c| {
+        activities
+            .filter(id.eq(activity_id))
+            .filter(user_id.eq(user_id_param))
+            .first::<Activity>(c)
+    })
+    .await
+}
+
+pub async fn create_activity(conn: &DbConn, activity: Activity) -> Result<Activity, diesel::result::Error> {
+    use crate::schema::activities::dsl::*;
+
+    conn.run(move |c| diesel::insert_into(activities).values(&activity).get_result(c)).await
+}
+
+// A rename is copied onto the sessions' `activity_name` in the same transaction
+pub async fn update_activity(
+    conn: &DbConn,
+    user_id_param: String,
+    activity_id: Uuid,
+    changes: UpdateActivity,
+) -> Result<Activity, diesel::result::Error> {
+    use crate::schema::activities::dsl::*;
+    use crate::schema::time_tracking_sessions;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let activity: Activity = activities
+                .filter(id.eq(activity_id))
+                .filter(user_id.eq(&user_id_param))
+                .first(c)?;
+            if changes.name.is_none()
+                && changes.color.is_none()
+                && changes.category.is_none()
+                && changes.billable.is_none()
+                && changes.hourly_rate_cents.is_none()
+                && changes.archived.is_none()
+            {
+                return Ok(activity);
+            }
+
+            let updated: Activity = diesel::update(activities.filter(id.eq(activity.id)))
+                .set(&changes)
+                .get_result(c)?;
+
+            if updated.name != activity.name {
+                diesel::update(time_tracking_sessions::table.filter(time_tracking_sessions::activity_id.eq(updated.id)))
+                    .set(time_tracking_sessions::activity_name.eq(&updated.name))
+                    .execute(c)?;
+            }
+
+            Ok(updated)
+        })
+    })
+    .await
+}
+
+// Moves every session of `source` over to `target` and removes `source`,
+// for cleaning up activities that were split by a typo
+pub async fn merge_activities(
+    conn: &DbConn,
+    user_id_param: String,
+    source_id: Uuid,
+    target_id: Uuid,
+) -> Result<(Activity, usize), diesel::result::Error> {
+    use crate::schema::activities::dsl::*;
+    use crate::schema::time_tracking_sessions;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let source: Activity = activities
+                .filter(id.eq(source_id))
+                .filter(user_id.eq(&user_id_param))
+                .first(c)?;
+            let target: Activity = activities
+                .filter(id.eq(target_id))
+                .filter(user_id.eq(&user_id_param))
+                .filter(space_id.eq(source.space_id))
+                .first(c)?;
+
+            let moved = diesel::update(time_tracking_sessions::table.filter(time_tracking_sessions::activity_id.eq(source.id)))
+                .set((
+                    time_tracking_sessions::activity_id.eq(Some(target.id)),
+                    time_tracking_sessions::activity_name.eq(&target.name),
+                ))
+                .execute(c)?;
+
+            diesel::delete(activities.filter(id.eq(source.id))).execute(c)?;
+
+            Ok((target, moved))
+        })
+    })
+    .await
+}
+
+// `text` with the LIKE wildcards taken literally
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+// Active activities whose name contains `term`, names starting with it first,
+// then the ones with the most sessions
+pub async fn autocomplete_activities(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: i32,
+    term: String,
+    limit: i64,
+) -> Result<Vec<Activity>, diesel::result::Error> {
+    use diesel::sql_types::{BigInt, Integer, Text};
+
+    let escaped = escape_like(term.trim());
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT a.*
+            FROM activities a
+            LEFT JOIN time_tracking_sessions t ON t.activity_id = a.id
+            WHERE a.user_id = $1
+              AND a.space_id = $2
+              AND NOT a.archived
+              AND a.name ILIKE '%' || $3 || '%'
+            GROUP BY a.id
+            ORDER BY a.name ILIKE $3 || '%' DESC, COUNT(t.id) DESC, lower(a.name)
+            LIMIT $4",
+        )
+        .bind::<Text, _>(user_id_param)
+        .bind::<Integer, _>(space_id_param)
+        .bind::<Text, _>(escaped)
+        .bind::<BigInt, _>(limit)
+        .load::<Activity>(c)
+    })
+    .await
+}
+
+// time tracking
+
+// Starts a session for `activity`, which is only created by name once the start goes
+// ahead. With `stop_running` the user's running timer is stopped at `start_time` in the
+// same transaction, otherwise it leaves the start refused.
+pub async fn create_time_tracking_session(
+    conn: &DbConn,
+    user_id: String,
+    space_id: i32,
+    activity: ActivityChoice,
+    start_time: DateTime<Utc>,
+    details: SessionDetails,
+    stop_running: bool,
+) -> Result<TimerStart<TimeTrackingSession>, diesel::result::Error> {
+    let mut new_session = TimeTrackingSession {
+        id: Uuid::new_v4(),
+        user_id,
+        space_id,
+        activity_name: String::new(), // filled in from `activity`
+        start_time,
+        end_time: None,
+        duration: None,
+        activity_id: None,
+        notes: details.notes,
+        session_type: SESSION_TYPE_REGULAR.to_string(),
+        last_heartbeat_at: None,
+        auto_stopped: false,
+        auto_stop_reason: None,
+        reviewed_at: None,
+        note_id: details.note_id,
+        note_line_id: details.note_line_id,
+        tags: details.tags,
+    };
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            start_timer(c, &new_session.user_id.clone(), start_time, stop_running, |c| {
+                let activity = resolve_activity_choice(c, &new_session.user_id, new_session.space_id, activity)?;
+                new_session.activity_id = Some(activity.id);
+                new_session.activity_name = activity.name;
+                insert_open_session(c, &new_session)
+            })
+        })
+    })
+    .await
+        .map_err(|e| {
+            eprintln!("Error creating new Time Track session: {:?}", e);
+            e
+        })
+}
+
+// Runs `start` once the user's running timer is out of the way, a user runs one timer at a
+// time (a pomodoro run counts as one during its breaks too). With `stop` the open sessions
+// are closed at `at` and an active pomodoro run is finished, otherwise nothing is started.
+fn start_timer<T>(
+    c: &mut PgConnection,
+    user_id_param: &str,
+    at: DateTime<Utc>,
+    stop: bool,
+    start: impl FnOnce(&mut PgConnection) -> QueryResult<T>,
+) -> QueryResult<TimerStart<T>> {
+    use crate::schema::{pomodoro_runs, session_intervals, time_tracking_sessions};
+
+    let run: Option<PomodoroRun> = pomodoro_runs::table
+        .filter(pomodoro_runs::user_id.eq(user_id_param))
+        .filter(pomodoro_runs::ended_at.is_null())
+        .for_update()
+        .first(c)
+        .optional()?;
+    let running: Vec<TimeTrackingSession> = time_tracking_sessions::table
+        .filter(time_tracking_sessions::user_id.eq(user_id_param))
+        .filter(time_tracking_sessions::end_time.is_null())
+        .for_update()
+        .load(c)?;
+    if (run.is_some() || !running.is_empty()) && !stop {
+        return Ok(TimerStart::Running);
+    }
+
+    // a running session can't be stopped before it started or was last resumed
+    let resumed: Vec<DateTime<Utc>> = session_intervals::table
+        .filter(session_intervals::session_id.eq_any(running.iter().map(|session| session.id).collect::<Vec<_>>()))
+        .filter(session_intervals::end_time.is_null())
+        .select(session_intervals::start_time)
+        .load(c)?;
+    if running.iter().map(|session| session.start_time).chain(resumed).any(|since| at < since) {
+        return Ok(TimerStart::TooEarly);
+    }
+
+    let mut stopped = None;
+    let mut closed = None;
+    if let Some(run) = run {
+        let session = running.iter().find(|session| Some(session.id) == run.session_id);
+        let transition = pomodoro::transition(&run, session, "", at.max(run.phase_started_at), pomodoro::Advance::Stop);
+        // the run is locked, it can't have moved on
+        apply_pomodoro_transition(c, &transition)?;
+        closed = transition.close_at.map(|(session_id, _)| session_id);
+        stopped = Some(transition.change);
+    }
+    for session in running.iter().filter(|session| Some(session.id) != closed) {
+        close_open_session(c, session.id, at)?;
+    }
+
+    Ok(TimerStart::Started(start(c)?, stopped))
+}
+
+// Writes a running session together with its first interval
+fn insert_open_session(c: &mut PgConnection, new_session: &TimeTrackingSession) -> QueryResult<TimeTrackingSession> {
+    use crate::schema::{session_intervals, time_tracking_sessions};
+
+    let session = diesel::insert_into(time_tracking_sessions::table)
+        .values(new_session)
+        .get_result(c)?;
+
+    diesel::insert_into(session_intervals::table)
+        .values(&SessionInterval {
+            id: Uuid::new_v4(),
+            session_id: new_session.id,
+            start_time: new_session.start_time,
+            end_time: None,
+        })
+        .execute(c)?;
+
+    Ok(session)
+}
+
+
+pub async fn get_time_tracking_session(
+    conn: &DbConn,
+    user_id_param: String,
+    session_id: Uuid,
+) -> Result<TimeTrackingSession, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        time_tracking_sessions
+            .filter(id.eq(session_id))
+            .filter(user_id.eq(user_id_param))
+            .first::<TimeTrackingSession>(c)
+    })
+    .await
+}
+
+// `end_time_pending` has already been checked against the start time by the caller.
+// Closes the running interval and sums up the intervals into `duration`.
+pub async fn complete_time_tracking_session(
+    conn: &DbConn,
+    session_id: Uuid,
+    end_time_pending: DateTime<Utc>,
+) -> Result<TimeTrackingSession, diesel::result::Error> {
+    conn.run(move |c| c.transaction(|c| close_open_session(c, session_id, end_time_pending))).await
+}
+
+fn close_open_session(
+    c: &mut PgConnection,
+    session_id: Uuid,
+    end_time_pending: DateTime<Utc>,
+) -> QueryResult<TimeTrackingSession> {
+    use crate::schema::session_intervals;
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    let session = time_tracking_sessions
+        .filter(id.eq(session_id))
+        .first::<TimeTrackingSession>(c)?;
+
+    diesel::update(
+        session_intervals::table
+            .filter(session_intervals::session_id.eq(session_id))
+            .filter(session_intervals::end_time.is_null()),
+    )
+    .set(session_intervals::end_time.eq(Some(end_time_pending)))
+    .execute(c)?;
+
+    let intervals: Vec<SessionInterval> = session_intervals::table
+        .filter(session_intervals::session_id.eq(session_id))
+        .load(c)?;
+
+    // sessions from before pausing existed may not have any intervals
+    let duration_pending = if intervals.is_empty() {
+        end_time_pending.signed_duration_since(session.start_time).num_seconds()
+    } else {
+        active_seconds(&intervals, end_time_pending)
+    };
+
+    // Update the session with the new end_time and duration
+    diesel::update(time_tracking_sessions.filter(id.eq(session_id)))
+        .set((
+            end_time.eq(Some(end_time_pending)),
+            duration.eq(Some(duration_pending)),
+        ))
+        .get_result(c)
+}
+
+// Closes the running interval, Ok(false) when the session was not running
+pub async fn pause_time_tracking_session(
+    conn: &DbConn,
+    session_id_param: Uuid,
+    at: DateTime<Utc>,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::session_intervals::dsl::*;
+
+    conn.run(move |c| {
+        diesel::update(
+            session_intervals
+                .filter(session_id.eq(session_id_param))
+                .filter(end_time.is_null()),
+        )
+        .set(end_time.eq(Some(at)))
+        .execute(c)
+        .map(|updated| updated > 0)
+    })
+    .await
+}
+
+// Opens a new interval, Ok(false) when the session was not paused
+pub async fn resume_time_tracking_session(
+    conn: &DbConn,
+    session_id_param: Uuid,
+    at: DateTime<Utc>,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::session_intervals::dsl::*;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let running: i64 = session_intervals
+                .filter(session_id.eq(session_id_param))
+                .filter(end_time.is_null())
+                .count()
+                .get_result(c)?;
+
+            if running > 0 {
+                return Ok(false);
+            }
+
+            diesel::insert_into(session_intervals)
+                .values(&SessionInterval {
+                    id: Uuid::new_v4(),
+                    session_id: session_id_param,
+                    start_time: at,
+                    end_time: None,
+                })
+                .execute(c)
+                .map(|_| true)
+        })
+    })
+    .await
+}
+
+pub async fn get_session_intervals(
+    conn: &DbConn,
+    session_ids: Vec<Uuid>,
+) -> Result<Vec<SessionInterval>, diesel::result::Error> {
+    use crate::schema::session_intervals::dsl::*;
+
+    conn.run(move |c| {
+        session_intervals
+            .filter(session_id.eq_any(session_ids))
+            .order(start_time.asc())
+            .load::<SessionInterval>(c)
+    })
+    .await
+}
+
+// Pairs every session with its own intervals
+pub async fn with_intervals(
+    conn: &DbConn,
+    sessions: Vec<TimeTrackingSession>,
+) -> Result<Vec<TimeTrackingSessionWithIntervals>, diesel::result::Error> {
+    let intervals = get_session_intervals(conn, sessions.iter().map(|s| s.id).collect()).await?;
+
+    Ok(sessions
+        .into_iter()
+        .map(|session| {
+            let own = intervals.iter().filter(|i| i.session_id == session.id).cloned().collect();
+            TimeTrackingSessionWithIntervals::new(session, own)
+        })
+        .collect())
+}
+
+
+// Sessions that overlap `from`..`to` with their activity color, for the calendar.
+// Running sessions are always included, the caller cuts them off at now.
+pub async fn get_calendar_sessions(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<(TimeTrackingSession, Option<String>)>, diesel::result::Error> {
+    use crate::schema::{activities, time_tracking_sessions};
+
+    conn.run(move |c| {
+        let mut query = time_tracking_sessions::table
+            .left_join(activities::table)
+            .filter(time_tracking_sessions::user_id.eq(user_id_param))
+            .filter(time_tracking_sessions::start_time.lt(to))
+            .filter(time_tracking_sessions::end_time.gt(from).or(time_tracking_sessions::end_time.is_null()))
+            .select((time_tracking_sessions::all_columns, activities::color.nullable()))
+            .into_boxed();
+        if let Some(space) = space_id_param {
+            query = query.filter(time_tracking_sessions::space_id.eq(space));
+        }
+        query
+            .order(time_tracking_sessions::start_time.asc())
+            .load::<(TimeTrackingSession, Option<String>)>(c)
+    })
+    .await
+}
+
+// Sessions without an end time in any space, newest first
+pub async fn get_open_sessions(
+    conn: &DbConn,
+    user_id_param: String,
+) -> Result<Vec<TimeTrackingSession>, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        time_tracking_sessions
+            .filter(user_id.eq(user_id_param))
+            .filter(end_time.is_null())
+            .order(start_time.desc())
+            .load::<TimeTrackingSession>(c)
+    })
+    .await
+}
+
+// Every session of the user touching [from, to), running ones included
+pub async fn get_sessions_overlapping(
+    conn: &DbConn,
+    user_id_param: String,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<TimeTrackingSession>, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        time_tracking_sessions
+            .filter(user_id.eq(user_id_param))
+            .filter(start_time.lt(to))
+            .filter(end_time.gt(from).or(end_time.is_null()))
+            .load::<TimeTrackingSession>(c)
+    })
+    .await
+}
+
+// Writes finished sessions (from an import or a manual entry) with a single interval each
+pub async fn insert_completed_sessions(
+    conn: &DbConn,
+    sessions: Vec<TimeTrackingSession>,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::{session_intervals, time_tracking_sessions};
+
+    let intervals: Vec<SessionInterval> = sessions
+        .iter()
+        .map(|session| SessionInterval {
+            id: Uuid::new_v4(),
+            session_id: session.id,
+            start_time: session.start_time,
+            end_time: session.end_time,
+        })
+        .collect();
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let mut sessions = sessions;
+            for session in sessions.iter_mut().filter(|session| session.activity_id.is_none()) {
+                let activity = find_or_create_activity(c, &session.user_id, session.space_id, &session.activity_name)?;
+                session.activity_name = activity.name;
+                session.activity_id = Some(activity.id);
+            }
+
+            let inserted = diesel::insert_into(time_tracking_sessions::table)
+                .values(&sessions)
+                .execute(c)?;
+
+            diesel::insert_into(session_intervals::table)
+                .values(&intervals)
+                .execute(c)?;
+
+            Ok(inserted)
+        })
+    })
+    .await
+}
+
+fn filtered_sessions(filter: SessionFilter) -> crate::schema::time_tracking_sessions::BoxedQuery<'static, diesel::pg::Pg> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    let mut query = time_tracking_sessions
+        .filter(user_id.eq(filter.user_id))
+        .into_boxed();
+
+    if let Some(space) = filter.space_id {
+        query = query.filter(space_id.eq(space));
+    }
+    if let Some(activity) = filter.activity_name {
+        query = query.filter(activity_name.eq(activity));
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(start_time.ge(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(start_time.lt(to));
+    }
+    if filter.completed_only {
+        query = query.filter(end_time.is_not_null());
+    }
+    if let Some(search) = filter.search {
+        let pattern = format!("%{}%", escape_like(&search));
+        query = query.filter(activity_name.ilike(pattern.clone()).or(notes.ilike(pattern).assume_not_null()));
+    }
+    if !filter.tags.is_empty() {
+        query = query.filter(tags.contains(filter.tags));
+    }
+    query
+}
+
+// Every session matching the filter, most recent first
+pub async fn get_filtered_sessions(
+    conn: &DbConn,
+    filter: SessionFilter,
+) -> Result<Vec<TimeTrackingSession>, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        filtered_sessions(filter)
+            .order((start_time.desc(), id.desc()))
+            .load::<TimeTrackingSession>(c)
+    })
+    .await
+}
+
+// One page of sessions ordered by start time, continuing after the `after` key
+// (start_time, id) of the previous page so exports never hold everything at once.
+pub async fn get_sessions_page(
+    conn: &DbConn,
+    filter: SessionFilter,
+    after: Option<(DateTime<Utc>, Uuid)>,
+    limit: i64,
+) -> Result<Vec<TimeTrackingSession>, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = filtered_sessions(filter);
+        if let Some((after_start, after_id)) = after {
+            query = query.filter(start_time.gt(after_start).or(start_time.eq(after_start).and(id.gt(after_id))));
+        }
+
+        query
+            .order((start_time.asc(), id.asc()))
+            .limit(limit)
+            .load::<TimeTrackingSession>(c)
+    })
+    .await
+}
+
+// Sums completed sessions per grouping set in one query. Sessions are bucketed by
+// their start time as seen in `time_zone`, the range is [from, to_exclusive) local time.
+pub async fn time_report_rows(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+    time_zone: String,
+    granularity: Granularity,
+    from: NaiveDate,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<ReportRow>, diesel::result::Error> {
+    use diesel::sql_types::{Date, Int4, Nullable, Text};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "WITH local_sessions AS (
+                SELECT t.activity_name,
+                       s.space_name,
+                       t.duration,
+                       date_trunc($3, t.start_time AT TIME ZONE $2)::date AS bucket
+                FROM time_tracking_sessions t
+                JOIN spaces s ON s.id = t.space_id
+                WHERE t.user_id = $1
+                  AND t.duration IS NOT NULL
+                  AND ($6::int4 IS NULL OR t.space_id = $6)
+                  AND t.start_time AT TIME ZONE $2 >= $4::timestamp
+                  AND t.start_time AT TIME ZONE $2 < $5::timestamp
+            )
+            SELECT CASE GROUPING(bucket, activity_name, space_name)
+                       WHEN 1 THEN 'bucket_activity'
+                       WHEN 3 THEN 'bucket'
+                       WHEN 5 THEN 'activity'
+                       WHEN 6 THEN 'space'
+                       ELSE 'total'
+                   END AS grouping,
+                   bucket,
+                   activity_name,
+                   space_name,
+                   COALESCE(SUM(duration), 0)::int8 AS total_seconds,
+                   COUNT(*)::int8 AS sessions
+            FROM local_sessions
+            GROUP BY GROUPING SETS ((bucket, activity_name), (bucket), (activity_name), (space_name), ())",
+        )
+        .bind::<Text, _>(user_id_param)
+        .bind::<Text, _>(time_zone)
+        .bind::<Text, _>(granularity.as_str())
+        .bind::<Date, _>(from)
+        .bind::<Date, _>(to_exclusive)
+        .bind::<Nullable<Int4>, _>(space_id_param)
+        .load::<ReportRow>(c)
+    })
+    .await
+}
+
+// Sums completed sessions per tag and per tag and activity, like `time_report_rows`
+// sessions are picked by their local start time. Untagged sessions get a NULL tag.
+pub async fn tag_report_rows(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+    activity: Option<String>,
+    time_zone: String,
+    from: NaiveDate,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<TagReportRow>, diesel::result::Error> {
+    use diesel::sql_types::{Date, Int4, Nullable, Text};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "WITH local_sessions AS (
+                SELECT t.activity_name, t.duration, t.tags
+                FROM time_tracking_sessions t
+                WHERE t.user_id = $1
+                  AND t.duration IS NOT NULL
+                  AND ($5::int4 IS NULL OR t.space_id = $5)
+                  AND ($6::text IS NULL OR lower(t.activity_name) = lower($6))
+                  AND t.start_time AT TIME ZONE $2 >= $3::timestamp
+                  AND t.start_time AT TIME ZONE $2 < $4::timestamp
+            ),
+            tagged AS (
+                SELECT activity_name,
+                       duration,
+                       unnest(CASE WHEN cardinality(tags) = 0 THEN ARRAY[NULL::text] ELSE tags END) AS tag
+                FROM local_sessions
+            )
+            SELECT CASE GROUPING(activity_name) WHEN 0 THEN 'tag_activity' ELSE 'tag' END AS grouping,
+                   tag,
+                   activity_name,
+                   COALESCE(SUM(duration), 0)::int8 AS total_seconds,
+                   COUNT(*)::int8 AS sessions
+            FROM tagged
+            GROUP BY GROUPING SETS ((tag, activity_name), (tag))
+            UNION ALL
+            SELECT 'total', NULL, NULL, COALESCE(SUM(duration), 0)::int8, COUNT(*)::int8
+            FROM local_sessions",
+        )
+        .bind::<Text, _>(user_id_param)
+        .bind::<Text, _>(time_zone)
+        .bind::<Date, _>(from)
+        .bind::<Date, _>(to_exclusive)
+        .bind::<Nullable<Int4>, _>(space_id_param)
+        .bind::<Nullable<Text>, _>(activity)
+        .load::<TagReportRow>(c)
+    })
+    .await
+}
+
+// pub async fn get_a_tracking_session(
+//     conn: &DbConn,
+//     session_id: Uuid,
+// ) -> Result<TimeTrackingSession, diesel::result::Error> {
+//     use crate::schema::time_tracking_sessions::dsl::*;
+
+//     conn.run(move |c| {
+//         time_tracking_sessions
+//             .filter(id.eq(session_id))
+//             .first::<TimeTrackingSession>(c)
+//     })
+//     .await
+// }
+
+
+pub async fn delete_time_tracking_session(
+    conn: &DbConn,
+    session_id: Uuid,
+    audit: SessionAuditEntry,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::session_audit_log;
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let deleted = diesel::delete(time_tracking_sessions.filter(id.eq(session_id)))
+                .execute(c)?;
+            diesel::insert_into(session_audit_log::table)
+                .values(&audit)
+                .execute(c)?;
+            Ok(deleted)
+        })
+    })
+    .await
+}
+
+// A session entered by hand, written with its single interval and the audit entry
+// Inserts a finished session (with one interval) once nothing of the user's overlaps its
+// range, an activity given by name is only created then
+pub async fn create_manual_session(
+    conn: &DbConn,
+    session: TimeTrackingSession,
+    activity: ActivityChoice,
+) -> Result<SessionSaveOutcome, diesel::result::Error> {
+    use crate::schema::{session_audit_log, session_intervals, time_tracking_sessions};
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let mut session = session;
+            lock_user_sessions(c, &session.user_id)?;
+            let end = session.end_time.unwrap_or_else(chrono::Utc::now);
+            if let Some(other) = first_overlapping_session(c, &session.user_id, None, session.start_time, end)? {
+                return Ok(SessionSaveOutcome::Overlaps(other));
+            }
+
+            let activity = resolve_activity_choice(c, &session.user_id, session.space_id, activity)?;
+            session.activity_id = Some(activity.id);
+            session.activity_name = activity.name;
+
+            let saved = diesel::insert_into(time_tracking_sessions::table)
+                .values(&session)
+                .get_result(c)?;
+            diesel::insert_into(session_intervals::table)
+                .values(&SessionInterval {
+                    id: Uuid::new_v4(),
+                    session_id: session.id,
+                    start_time: session.start_time,
+                    end_time: session.end_time,
+                })
+                .execute(c)?;
+            diesel::insert_into(session_audit_log::table)
+                .values(&SessionAuditEntry::new("create", None, Some(&session)))
+                .execute(c)?;
+            Ok(SessionSaveOutcome::Saved(saved))
+        })
+    })
+    .await
+}
+
+// Holds back other writers that check the user's sessions for overlaps until the
+// transaction ends, a row lock can't keep a new overlapping row out
+fn lock_user_sessions(c: &mut PgConnection, user_id_param: &str) -> QueryResult<()> {
+    diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind::<diesel::sql_types::Text, _>(user_id_param)
+        .execute(c)
+        .map(|_| ())
+}
+
+// The earliest of the user's sessions (other than `except`) running into `start`..`end`
+fn first_overlapping_session(
+    c: &mut PgConnection,
+    user_id_param: &str,
+    except: Option<Uuid>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> QueryResult<Option<TimeTrackingSession>> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    let mut query = time_tracking_sessions
+        .filter(user_id.eq(user_id_param))
+        .filter(start_time.lt(end))
+        .filter(end_time.gt(start).or(end_time.is_null()))
+        .into_boxed();
+    if let Some(except) = except {
+        query = query.filter(id.ne(except));
+    }
+    query.order(start_time.asc()).first::<TimeTrackingSession>(c).optional()
+}
+
+fn resolve_activity_choice(c: &mut PgConnection, owner: &str, space: i32, choice: ActivityChoice) -> QueryResult<Activity> {
+    match choice {
+        ActivityChoice::Existing(activity) => Ok(activity),
+        ActivityChoice::Named(name) => find_or_create_activity(c, owner, space, &name),
+    }
+}
+
+pub async fn save_session_edit(
+    conn: &DbConn,
+    user_id_param: String,
+    session_id: Uuid,
+    changes: SessionChanges,
+) -> Result<SessionSaveOutcome, diesel::result::Error> {
+    use crate::schema::{session_audit_log, session_intervals};
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let now = chrono::Utc::now();
+            lock_user_sessions(c, &user_id_param)?;
+            let before: TimeTrackingSession = time_tracking_sessions
+                .filter(id.eq(session_id))
+                .filter(user_id.eq(&user_id_param))
+                .for_update()
+                .first(c)?;
+
+            let end = changes.end_time.unwrap_or(now);
+            if let Some(other) = first_overlapping_session(c, &user_id_param, Some(session_id), changes.start_time, end)? {
+                return Ok(SessionSaveOutcome::Overlaps(other));
+            }
+
+            let mut after = before.clone();
+            if let Some(choice) = changes.activity {
+                let activity = resolve_activity_choice(c, &user_id_param, before.space_id, choice)?;
+                after.activity_id = Some(activity.id);
+                after.activity_name = activity.name;
+            }
+            if let Some(new_notes) = changes.notes {
+                after.notes = new_notes;
+            }
+            if let Some(new_tags) = changes.tags {
+                after.tags = new_tags;
+            }
+            // correcting an auto stopped session counts as reviewing it
+            if after.auto_stopped && after.reviewed_at.is_none() {
+                after.reviewed_at = Some(now);
+            }
+
+            let old_intervals: Vec<SessionInterval> = session_intervals::table
+                .filter(session_intervals::session_id.eq(session_id))
+                .load(c)?;
+            let intervals = fit_intervals(&old_intervals, session_id, changes.start_time, changes.end_time);
+            after.start_time = changes.start_time;
+            after.end_time = changes.end_time;
+            after.duration = changes.end_time.map(|end| active_seconds(&intervals, end));
+
+            let saved = diesel::update(time_tracking_sessions.filter(id.eq(session_id)))
+                .set((
+                    activity_id.eq(after.activity_id),
+                    activity_name.eq(&after.activity_name),
+                    start_time.eq(after.start_time),
+                    end_time.eq(after.end_time),
+                    duration.eq(after.duration),
+                    notes.eq(&after.notes),
+                    tags.eq(&after.tags),
+                    reviewed_at.eq(after.reviewed_at),
+                ))
+                .get_result(c)?;
+
+            diesel::delete(session_intervals::table.filter(session_intervals::session_id.eq(session_id)))
+                .execute(c)?;
+            diesel::insert_into(session_intervals::table)
+                .values(&intervals)
+                .execute(c)?;
+            diesel::insert_into(session_audit_log::table)
+                .values(&SessionAuditEntry::new("update", Some(&before), Some(&after)))
+                .execute(c)?;
+
+            Ok(SessionSaveOutcome::Saved(saved))
+        })
+    })
+    .await
+}
+
+pub async fn get_session_audit_log(
+    conn: &DbConn,
+    user_id_param: String,
+    session_id_param: Uuid,
+) -> Result<Vec<SessionAuditEntry>, diesel::result::Error> {
+    use crate::schema::session_audit_log::dsl::*;
+
+    conn.run(move |c| {
+        session_audit_log
+            .filter(session_id.eq(session_id_param))
+            .filter(user_id.eq(user_id_param))
+            .order(created_at.asc())
+            .load::<SessionAuditEntry>(c)
+    })
+    .await
+}
+
+
+// auto stop
+
+// Marks the user's running sessions (or just `session_id`) as still being worked on
+pub async fn record_heartbeat(
+    conn: &DbConn,
+    user_id_param: String,
+    session_id: Option<Uuid>,
+    at: DateTime<Utc>,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = diesel::update(time_tracking_sessions)
+            .filter(user_id.eq(user_id_param))
+            .filter(end_time.is_null())
+            .into_boxed();
+        if let Some(session_id) = session_id {
+            query = query.filter(id.eq(session_id));
+        }
+        query.set(last_heartbeat_at.eq(Some(at))).execute(c)
+    })
+    .await
+}
+
+// Every running regular session, pomodoro sessions are ended by their run
+pub async fn get_all_open_sessions(conn: &DbConn) -> Result<Vec<TimeTrackingSession>, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        time_tracking_sessions
+            .filter(end_time.is_null())
+            .filter(session_type.eq(SESSION_TYPE_REGULAR))
+            .load::<TimeTrackingSession>(c)
+    })
+    .await
+}
+
+// Closes a forgotten session at `at` and flags it for review, None when it was
+// stopped in the meantime
+pub async fn auto_stop_session(
+    conn: &DbConn,
+    session_id: Uuid,
+    at: DateTime<Utc>,
+    reason: String,
+) -> Result<Option<TimeTrackingSession>, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    use crate::schema::session_intervals;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let running = time_tracking_sessions
+                .filter(id.eq(session_id))
+                .filter(end_time.is_null())
+                .for_update()
+                .first::<TimeTrackingSession>(c)
+                .optional()?;
+            let Some(session) = running else { return Ok(None) };
+
+            // never before the session was last paused or resumed
+            let last_change = session_intervals::table
+                .filter(session_intervals::session_id.eq(session_id))
+                .load::<SessionInterval>(c)?
+                .into_iter()
+                .map(|interval| interval.end_time.unwrap_or(interval.start_time))
+                .max();
+            let at = last_change.map_or(at, |last_change| at.max(last_change)).max(session.start_time);
+
+            close_open_session(c, session_id, at)?;
+            diesel::update(time_tracking_sessions.filter(id.eq(session_id)))
+                .set((auto_stopped.eq(true), auto_stop_reason.eq(Some(reason))))
+                .get_result(c)
+                .map(Some)
+        })
+    })
+    .await
+}
+
+// Auto stopped sessions the user has not looked at yet
+pub async fn get_sessions_to_review(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+) -> Result<Vec<TimeTrackingSession>, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = time_tracking_sessions
+            .filter(user_id.eq(user_id_param))
+            .filter(auto_stopped.eq(true))
+            .filter(reviewed_at.is_null())
+            .into_boxed();
+        if let Some(space) = space_id_param {
+            query = query.filter(space_id.eq(space));
+        }
+        query.order(start_time.desc()).load::<TimeTrackingSession>(c)
+    })
+    .await
+}
+
+// Accepts an auto stopped session as it is
+pub async fn mark_session_reviewed(
+    conn: &DbConn,
+    user_id_param: String,
+    session_id: Uuid,
+    at: DateTime<Utc>,
+) -> Result<TimeTrackingSession, diesel::result::Error> {
+    use crate::schema::time_tracking_sessions::dsl::*;
+
+    conn.run(move |c| {
+        diesel::update(
+            time_tracking_sessions
+                .filter(id.eq(session_id))
+                .filter(user_id.eq(user_id_param))
+                .filter(auto_stopped.eq(true)),
+        )
+        .set(reviewed_at.eq(Some(at)))
+        .get_result(c)
+    })
+    .await
+}
+
+
+// budgets
+
+// Budgets with their activity, for one space or (`space` None) for everyone
+pub async fn get_budgets(
+    conn: &DbConn,
+    space: Option<(String, i32)>,
+) -> Result<Vec<(ActivityBudget, Activity)>, diesel::result::Error> {
+    use crate::schema::{activities, activity_budgets};
+
+    conn.run(move |c| {
+        let mut query = activity_budgets::table
+            .inner_join(activities::table)
+            .into_boxed();
+        if let Some((owner, space_id)) = space {
+            query = query
+                .filter(activity_budgets::user_id.eq(owner))
+                .filter(activities::space_id.eq(space_id));
+        }
+        query
+            .order((activities::name.asc(), activity_budgets::created_at.asc()))
+            .load::<(ActivityBudget, Activity)>(c)
+    })
+    .await
+}
+
+pub async fn create_budget(conn: &DbConn, budget: ActivityBudget) -> Result<ActivityBudget, diesel::result::Error> {
+    use crate::schema::activity_budgets::dsl::*;
+
+    conn.run(move |c| diesel::insert_into(activity_budgets).values(&budget).get_result(c)).await
+}
+
+pub async fn delete_budget(
+    conn: &DbConn,
+    user_id_param: String,
+    budget_id: Uuid,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::activity_budgets::dsl::*;
+
+    conn.run(move |c| {
+        diesel::delete(activity_budgets.filter(id.eq(budget_id)).filter(user_id.eq(user_id_param))).execute(c)
+    })
+    .await
+}
+
+#[derive(QueryableByName)]
+struct TrackedSeconds {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total_seconds: i64,
+}
+
+// Active seconds of the activity between `from` and `to`, intervals are cut to the
+// range and one that is still running counts up to `now`
+pub async fn tracked_seconds(
+    conn: &DbConn,
+    activity_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<i64, diesel::result::Error> {
+    use diesel::sql_types::{Timestamptz, Uuid as SqlUuid};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT COALESCE(SUM(EXTRACT(EPOCH FROM
+                       LEAST(COALESCE(i.end_time, $4), $3) - GREATEST(i.start_time, $2))), 0)::BIGINT AS total_seconds
+            FROM session_intervals i
+            JOIN time_tracking_sessions t ON t.id = i.session_id
+            WHERE t.activity_id = $1
+              AND i.start_time < $3
+              AND COALESCE(i.end_time, $4) > $2",
+        )
+        .bind::<SqlUuid, _>(activity_id)
+        .bind::<Timestamptz, _>(from)
+        .bind::<Timestamptz, _>(to)
+        .bind::<Timestamptz, _>(now)
+        .get_result::<TrackedSeconds>(c)
+        .map(|row| row.total_seconds)
+    })
+    .await
+}
+
+#[derive(QueryableByName)]
+struct NoteTimeRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    note_id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    note_line_id: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    note_total: bool,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total_seconds: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    sessions: i64,
+}
+
+// Time the user tracked against each of the notes, a running interval counts up
+// to `now`. Notes nobody tracked time on are left out.
+pub async fn get_note_times(
+    conn: &DbConn,
+    user_id: String,
+    note_ids: Vec<Uuid>,
+    now: DateTime<Utc>,
+) -> Result<Vec<NoteTime>, diesel::result::Error> {
+    use diesel::sql_types::{Array, Text, Timestamptz, Uuid as SqlUuid};
+
+    let rows = conn
+        .run(move |c| {
+            diesel::sql_query(
+                "SELECT t.note_id, t.note_line_id, GROUPING(t.note_line_id) = 1 AS note_total,
+                        COALESCE(SUM(EXTRACT(EPOCH FROM COALESCE(i.end_time, $3) - i.start_time)), 0)::BIGINT AS total_seconds,
+                        COUNT(DISTINCT t.id) AS sessions
+                FROM time_tracking_sessions t
+                JOIN session_intervals i ON i.session_id = t.id
+                WHERE t.note_id = ANY($1)
+                  AND t.user_id = $2
+                GROUP BY GROUPING SETS ((t.note_id), (t.note_id, t.note_line_id))",
+            )
+            .bind::<Array<SqlUuid>, _>(note_ids)
+            .bind::<Text, _>(user_id)
+            .bind::<Timestamptz, _>(now)
+            .load::<NoteTimeRow>(c)
+        })
+        .await?;
+
+    let mut times: Vec<NoteTime> = rows
+        .iter()
+        .filter(|row| row.note_total)
+        .map(|row| NoteTime {
+            note_id: row.note_id,
+            total_seconds: row.total_seconds,
+            sessions: row.sessions,
+            lines: Vec::new(),
+        })
+        .collect();
+
+    for row in rows.into_iter().filter(|row| !row.note_total) {
+        let (Some(line_id), Some(time)) = (row.note_line_id, times.iter_mut().find(|time| time.note_id == row.note_id)) else {
+            continue;
+        };
+        time.lines.push(NoteLineTime {
+            line_id,
+            line: None,
+            text: None,
+            total_seconds: row.total_seconds,
+            sessions: row.sessions,
+        });
+    }
+    Ok(times)
+}
+
+// Remembers that the alert for this budget and period went out, false when it already had
+pub async fn record_budget_notification(
+    conn: &DbConn,
+    notification: BudgetNotification,
+) -> Result<bool, diesel::result::Error> {
+    use crate::schema::budget_notifications::dsl::*;
+
+    conn.run(move |c| {
+        diesel::insert_into(budget_notifications)
+            .values(&notification)
+            .on_conflict_do_nothing()
+            .execute(c)
+            .map(|inserted| inserted > 0)
+    })
+    .await
+}
+
+pub async fn get_budget_notifications(
+    conn: &DbConn,
+    budget_ids: Vec<Uuid>,
+) -> Result<Vec<BudgetNotification>, diesel::result::Error> {
+    use crate::schema::budget_notifications::dsl::*;
+
+    conn.run(move |c| {
+        budget_notifications
+            .filter(budget_id.eq_any(budget_ids))
+            .order(period_start.desc())
+            .load::<BudgetNotification>(c)
+    })
+    .await
+}
+
+
+// pomodoro
+
+// Starts `run` with its first work session, `stop_running` as for `create_time_tracking_session`
+pub async fn start_pomodoro_run(
+    conn: &DbConn,
+    run: PomodoroRun,
+    activity: ActivityChoice,
+    stop_running: bool,
+) -> Result<TimerStart<PomodoroRun>, diesel::result::Error> {
+    use crate::schema::pomodoro_runs;
+
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let mut run = run;
+            start_timer(c, &run.user_id.clone(), run.started_at, stop_running, |c| {
+                let activity = resolve_activity_choice(c, &run.user_id, run.space_id, activity)?;
+                run.activity_id = activity.id;
+                let session = pomodoro::work_session(&run, &activity.name, run.started_at);
+                run.session_id = Some(session.id);
+
+                insert_open_session(c, &session)?;
+                diesel::insert_into(pomodoro_runs::table).values(&run).get_result(c)
+            })
+        })
+    })
+    .await
+}
+
+pub async fn get_active_pomodoro_run(
+    conn: &DbConn,
+    user_id_param: String,
+) -> Result<Option<PomodoroRun>, diesel::result::Error> {
+    use crate::schema::pomodoro_runs::dsl::*;
+
+    conn.run(move |c| {
+        pomodoro_runs
+            .filter(user_id.eq(user_id_param))
+            .filter(ended_at.is_null())
+            .first::<PomodoroRun>(c)
+            .optional()
+    })
+    .await
+}
+
+// Runs whose current phase is over
+pub async fn get_due_pomodoro_runs(
+    conn: &DbConn,
+    now: DateTime<Utc>,
+) -> Result<Vec<PomodoroRun>, diesel::result::Error> {
+    use crate::schema::pomodoro_runs::dsl::*;
+
+    conn.run(move |c| {
+        pomodoro_runs
+            .filter(ended_at.is_null())
+            .filter(phase_ends_at.le(now))
+            .load::<PomodoroRun>(c)
+    })
+    .await
+}
+
+// Moves a run on to its next phase, see `pomodoro::transition`. Ok(false) when the run
+// was moved on by someone else since it was read.
+pub async fn save_pomodoro_transition(conn: &DbConn, transition: Transition) -> Result<bool, diesel::result::Error> {
+    conn.run(move |c| {
+        c.transaction(|c| {
+            if !apply_pomodoro_transition(c, &transition)? {
+                return Err(diesel::result::Error::RollbackTransaction);
+            }
+            Ok(())
+        })
+    })
+    .await
+    .map(|_| true)
+    .or_else(|e| match e {
+        diesel::result::Error::RollbackTransaction => Ok(false),
+        e => Err(e),
+    })
+}
+
+// Closes the work session, opens the next one and records the pomodoro where needed.
+// Ok(false) when the run is no longer in the phase the transition leaves, the caller
+// rolls back what was written.
+fn apply_pomodoro_transition(c: &mut PgConnection, transition: &Transition) -> QueryResult<bool> {
+    use crate::schema::pomodoro_runs::dsl::*;
+    use crate::schema::pomodoros;
+
+    // close before opening, a user can have just one open session
+    if let Some((session, at)) = transition.close_at {
+        close_open_session(c, session, at)?;
+    }
+    if let Some(session) = &transition.open {
+        insert_open_session(c, session)?;
+    }
+
+    let updated = diesel::update(
+        pomodoro_runs
+            .filter(id.eq(transition.run.id))
+            .filter(ended_at.is_null())
+            .filter(phase_started_at.eq(transition.previous_phase_started_at)),
+    )
+    .set(&transition.run)
+    .execute(c)?;
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    if let Some(pomodoro) = &transition.pomodoro {
+        diesel::insert_into(pomodoros::table).values(pomodoro).execute(c)?;
+    }
+    Ok(true)
+}
+
+// Completed pomodoros per activity of the space, finished between `from` and `to`
+pub async fn count_pomodoros(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: i32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<PomodoroCount>, diesel::result::Error> {
+    use diesel::sql_types::{Integer, Text, Timestamptz};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT a.id AS activity_id,
+                   a.name AS activity_name,
+                   COUNT(*) AS pomodoros,
+                   COALESCE(SUM(EXTRACT(EPOCH FROM p.completed_at - p.started_at)), 0)::BIGINT AS focus_seconds
+            FROM pomodoros p
+            JOIN activities a ON a.id = p.activity_id
+            WHERE p.user_id = $1
+              AND p.space_id = $2
+              AND p.completed_at >= $3
+              AND p.completed_at < $4
+            GROUP BY a.id, a.name
+            ORDER BY pomodoros DESC, lower(a.name)",
+        )
+        .bind::<Text, _>(user_id_param)
+        .bind::<Integer, _>(space_id_param)
+        .bind::<Timestamptz, _>(from)
+        .bind::<Timestamptz, _>(to)
+        .load::<PomodoroCount>(c)
+    })
+    .await
+}
+
+
+// habits
+
+pub async fn get_habits(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+) -> Result<Vec<Habit>, diesel::result::Error> {
+    use crate::schema::habits::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = habits.filter(user_id.eq(user_id_param)).into_boxed();
+        if let Some(space) = space_id_param {
+            query = query.filter(space_id.eq(space));
+        }
+        query.order(created_at.asc()).load::<Habit>(c)
+    })
+    .await
+}
+
+pub async fn get_habit(conn: &DbConn, user_id_param: String, habit_id: Uuid) -> Result<Habit, diesel::result::Error> {
+    use crate::schema::habits::dsl::*;
+
+    conn.run(move |c| habits.filter(id.eq(habit_id)).filter(user_id.eq(user_id_param)).first::<Habit>(c)).await
+}
+
+pub async fn create_habit(conn: &DbConn, habit: Habit) -> Result<Habit, diesel::result::Error> {
+    use crate::schema::habits::dsl::*;
+
+    conn.run(move |c| diesel::insert_into(habits).values(&habit).get_result(c)).await
+}
+
+pub async fn delete_habit(conn: &DbConn, user_id_param: String, habit_id: Uuid) -> Result<usize, diesel::result::Error> {
+    use crate::schema::habits::dsl::*;
+
+    conn.run(move |c| diesel::delete(habits.filter(id.eq(habit_id)).filter(user_id.eq(user_id_param))).execute(c))
+        .await
+}
+
+// Checks the habit off for a day, false when it already was
+pub async fn record_habit_completion(conn: &DbConn, completion: HabitCompletion) -> Result<bool, diesel::result::Error> {
+    use crate::schema::habit_completions::dsl::*;
+
+    conn.run(move |c| {
+        diesel::insert_into(habit_completions)
+            .values(&completion)
+            .on_conflict_do_nothing()
+            .execute(c)
+            .map(|inserted| inserted > 0)
+    })
+    .await
+}
+
+pub async fn delete_habit_completion(
+    conn: &DbConn,
+    habit_id_param: Uuid,
+    day_param: NaiveDate,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::habit_completions::dsl::*;
+
+    conn.run(move |c| {
+        diesel::delete(habit_completions.filter(habit_id.eq(habit_id_param)).filter(day.eq(day_param))).execute(c)
+    })
+    .await
+}
+
+pub async fn get_habit_completion_days(
+    conn: &DbConn,
+    habit_id_param: Uuid,
+    from: Option<NaiveDate>,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<NaiveDate>, diesel::result::Error> {
+    use crate::schema::habit_completions::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = habit_completions
+            .filter(habit_id.eq(habit_id_param))
+            .filter(day.lt(to_exclusive))
+            .select(day)
+            .into_boxed();
+        if let Some(from) = from {
+            query = query.filter(day.ge(from));
+        }
+        query.order(day.asc()).load::<NaiveDate>(c)
+    })
+    .await
+}
+
+// Active seconds per local day (by the day an interval starts in), a running
+// interval counts up to now
+pub async fn daily_tracked_seconds(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+    activity_id_param: Option<Uuid>,
+    time_zone: String,
+    from: Option<NaiveDate>,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<DayTotal>, diesel::result::Error> {
+    use diesel::sql_types::{Date, Int4, Nullable, Text, Timestamptz, Uuid as SqlUuid};
+
+    let now = chrono::Utc::now();
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT (i.start_time AT TIME ZONE $2)::date AS day,
+                    COALESCE(SUM(EXTRACT(EPOCH FROM COALESCE(i.end_time, $7) - i.start_time)), 0)::BIGINT AS total
+            FROM session_intervals i
+            JOIN time_tracking_sessions t ON t.id = i.session_id
+            WHERE t.user_id = $1
+              AND ($3::int4 IS NULL OR t.space_id = $3)
+              AND ($4::uuid IS NULL OR t.activity_id = $4)
+              AND ($5::date IS NULL OR i.start_time AT TIME ZONE $2 >= $5::timestamp)
+              AND i.start_time AT TIME ZONE $2 < $6::timestamp
+            GROUP BY 1
+            ORDER BY 1",
+        )
+        .bind::<Text, _>(user_id_param)
+        .bind::<Text, _>(time_zone)
+        .bind::<Nullable<Int4>, _>(space_id_param)
+        .bind::<Nullable<SqlUuid>, _>(activity_id_param)
+        .bind::<Nullable<Date>, _>(from)
+        .bind::<Date, _>(to_exclusive)
+        .bind::<Timestamptz, _>(now)
+        .load::<DayTotal>(c)
+    })
+    .await
+}
+
+// How many lines got checked per local day
+pub async fn daily_line_checks(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+    time_zone: String,
+    from: NaiveDate,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<DayTotal>, diesel::result::Error> {
+    use diesel::sql_types::{Date, Int4, Nullable, Text};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT (created_at AT TIME ZONE $2)::date AS day, COUNT(*)::BIGINT AS total
+            FROM line_check_events
+            WHERE user_id = $1
+              AND checked
+              AND ($3::int4 IS NULL OR space_id = $3)
+              AND created_at AT TIME ZONE $2 >= $4::timestamp
+              AND created_at AT TIME ZONE $2 < $5::timestamp
+            GROUP BY 1
+            ORDER BY 1",
+        )
+        .bind::<Text, _>(user_id_param)
+        .bind::<Text, _>(time_zone)
+        .bind::<Nullable<Int4>, _>(space_id_param)
+        .bind::<Date, _>(from)
+        .bind::<Date, _>(to_exclusive)
+        .load::<DayTotal>(c)
+    })
+    .await
+}
+
+#[derive(QueryableByName)]
+struct LocalDay {
+    #[diesel(sql_type = diesel::sql_types::Date)]
+    day: NaiveDate,
+}
+
+// Local days on which the line ended up checked, the last check event of the day wins.
+// `line` is the line's id, or its text for lines from before lines had ids.
+pub async fn checked_line_days(
+    conn: &DbConn,
+    note_id: Uuid,
+    line: String,
+    time_zone: String,
+    from: Option<NaiveDate>,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<NaiveDate>, diesel::result::Error> {
+    use diesel::sql_types::{Date, Nullable, Text, Uuid as SqlUuid};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT day FROM (
+                SELECT DISTINCT ON (day) day, checked
+                FROM (
+                    SELECT (created_at AT TIME ZONE $3)::date AS day, checked, created_at
+                    FROM line_check_events
+                    WHERE note_id = $1 AND COALESCE(line_id, line_text) = $2
+                ) events
+                WHERE ($4::date IS NULL OR day >= $4) AND day < $5
+                ORDER BY day, created_at DESC
+            ) last_of_day
+            WHERE checked
+            ORDER BY day",
+        )
+        .bind::<SqlUuid, _>(note_id)
+        .bind::<Text, _>(line)
+        .bind::<Text, _>(time_zone)
+        .bind::<Nullable<Date>, _>(from)
+        .bind::<Date, _>(to_exclusive)
+        .load::<LocalDay>(c)
+        .map(|days| days.into_iter().map(|row| row.day).collect())
+    })
+    .await
+}
+
+
+// goals
+
+pub async fn get_goals(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: Option<i32>,
+) -> Result<Vec<Goal>, diesel::result::Error> {
+    use crate::schema::goals::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = goals.filter(user_id.eq(user_id_param)).into_boxed();
+        if let Some(space) = space_id_param {
+            query = query.filter(space_id.eq(space));
+        }
+        query.order((end_date.asc(), created_at.asc())).load::<Goal>(c)
+    })
+    .await
+}
+
+// Goals whose dates are around `day`, a day of slack on each side covers every time zone
+pub async fn get_goals_around(conn: &DbConn, day: NaiveDate) -> Result<Vec<Goal>, diesel::result::Error> {
+    use crate::schema::goals::dsl::*;
+
+    let slack = chrono::Duration::days(1);
+    conn.run(move |c| {
+        goals
+            .filter(start_date.le(day + slack))
+            .filter(end_date.ge(day - slack - slack))
+            .load::<Goal>(c)
+    })
+    .await
+}
+
+pub async fn get_goal(conn: &DbConn, user_id_param: String, goal_id: Uuid) -> Result<Goal, diesel::result::Error> {
+    use crate::schema::goals::dsl::*;
+
+    conn.run(move |c| goals.filter(id.eq(goal_id)).filter(user_id.eq(user_id_param)).first::<Goal>(c)).await
+}
+
+pub async fn create_goal(conn: &DbConn, goal: Goal) -> Result<Goal, diesel::result::Error> {
+    use crate::schema::goals::dsl::*;
+
+    conn.run(move |c| diesel::insert_into(goals).values(&goal).get_result(c)).await
+}
+
+pub async fn delete_goal(conn: &DbConn, user_id_param: String, goal_id: Uuid) -> Result<usize, diesel::result::Error> {
+    use crate::schema::goals::dsl::*;
+
+    conn.run(move |c| diesel::delete(goals.filter(id.eq(goal_id)).filter(user_id.eq(user_id_param))).execute(c))
+        .await
+}
+
+// One snapshot per goal and day, a later one on the same day replaces it
+pub async fn save_goal_snapshot(conn: &DbConn, snapshot: GoalSnapshot) -> Result<usize, diesel::result::Error> {
+    use crate::schema::goal_snapshots::dsl::*;
+
+    conn.run(move |c| {
+        diesel::insert_into(goal_snapshots)
+            .values(&snapshot)
+            .on_conflict((goal_id, day))
+            .do_update()
+            .set(&snapshot)
+            .execute(c)
+    })
+    .await
+}
+
+pub async fn get_goal_snapshots(
+    conn: &DbConn,
+    goal_id_param: Uuid,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<Vec<GoalSnapshot>, diesel::result::Error> {
+    use crate::schema::goal_snapshots::dsl::*;
+
+    conn.run(move |c| {
+        let mut query = goal_snapshots.filter(goal_id.eq(goal_id_param)).into_boxed();
+        if let Some(from) = from {
+            query = query.filter(day.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(day.le(to));
+        }
+        query.order(day.asc()).load::<GoalSnapshot>(c)
+    })
+    .await
+}
+
+#[derive(QueryableByName)]
+struct LineCount {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total: i64,
+}
+
+// Lines checked between `from` and `to` that were still checked at the end of it,
+// in one note or in every note of the space
+pub async fn checked_lines_count(
+    conn: &DbConn,
+    space_id_param: i32,
+    note_id_param: Option<Uuid>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<i64, diesel::result::Error> {
+    use diesel::sql_types::{Int4, Nullable, Timestamptz, Uuid as SqlUuid};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT COUNT(*)::BIGINT AS total FROM (
+                SELECT DISTINCT ON (note_id, COALESCE(line_id, line_text)) checked
+                FROM line_check_events
+                WHERE space_id = $1
+                  AND ($2::uuid IS NULL OR note_id = $2)
+                  AND created_at >= $3
+                  AND created_at < $4
+                ORDER BY note_id, COALESCE(line_id, line_text), created_at DESC
+            ) last_state
+            WHERE checked",
+        )
+        .bind::<Int4, _>(space_id_param)
+        .bind::<Nullable<SqlUuid>, _>(note_id_param)
+        .bind::<Timestamptz, _>(from)
+        .bind::<Timestamptz, _>(to)
+        .get_result::<LineCount>(c)
+        .map(|row| row.total)
+    })
+    .await
+}
+
+
+// weekly reviews
+
+// Lines whose last check event between `from` and `to` checked them, with their note
+pub async fn checked_lines_between(
+    conn: &DbConn,
+    space_id_param: i32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<CheckedLineRow>, diesel::result::Error> {
+    use diesel::sql_types::{Int4, Timestamptz};
+
+    conn.run(move |c| {
+        diesel::sql_query(
+            "SELECT note_id, note_title, line_text, checked_at FROM (
+                SELECT DISTINCT ON (e.note_id, COALESCE(e.line_id, e.line_text))
+                       e.note_id, n.title AS note_title, e.line_text, e.checked, e.created_at AS checked_at
+                FROM line_check_events e
+                JOIN sticky_notes n ON n.id = e.note_id
+                WHERE e.space_id = $1
+                  AND e.created_at >= $2
+                  AND e.created_at < $3
+                ORDER BY e.note_id, COALESCE(e.line_id, e.line_text), e.created_at DESC
+            ) last_state
+            WHERE checked
+            ORDER BY checked_at",
+        )
+        .bind::<Int4, _>(space_id_param)
+        .bind::<Timestamptz, _>(from)
+        .bind::<Timestamptz, _>(to)
+        .load::<CheckedLineRow>(c)
+    })
+    .await
+}
+
+pub async fn get_notes_created_between(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: i32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<StickyNote>, diesel::result::Error> {
+    use crate::schema::sticky_notes::dsl::*;
+
+    conn.run(move |c| {
+        sticky_notes
+            .filter(user_id.eq(user_id_param))
+            .filter(space_id.eq(space_id_param))
+            .filter(created_at.ge(from))
+            .filter(created_at.lt(to))
+            .order(created_at.asc())
+            .load::<StickyNote>(c)
+    })
+    .await
+}
+
+pub async fn get_note_deletions(
+    conn: &DbConn,
+    space_id_param: i32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<NoteDeletion>, diesel::result::Error> {
+    use crate::schema::note_deletions::dsl::*;
+
+    conn.run(move |c| {
+        note_deletions
+            .filter(space_id.eq(space_id_param))
+            .filter(deleted_at.ge(from))
+            .filter(deleted_at.lt(to))
+            .order(deleted_at.asc())
+            .load::<NoteDeletion>(c)
+    })
+    .await
+}
+
+// One saved review per space and week, saving the week again replaces it
+pub async fn save_weekly_review(conn: &DbConn, saved: SavedReview) -> Result<SavedReview, diesel::result::Error> {
+    use crate::schema::weekly_reviews::dsl::*;
+
+    conn.run(move |c| {
+        diesel::insert_into(weekly_reviews)
+            .values(&saved)
+            .on_conflict((space_id, week_start))
+            .do_update()
+            .set((review.eq(&saved.review), created_at.eq(saved.created_at)))
+            .get_result(c)
+    })
+    .await
+}
+
+pub async fn get_weekly_reviews(
+    conn: &DbConn,
+    user_id_param: String,
+    space_id_param: i32,
+) -> Result<Vec<SavedReview>, diesel::result::Error> {
+    use crate::schema::weekly_reviews::dsl::*;
+
+    conn.run(move |c| {
+        weekly_reviews
+            .filter(user_id.eq(user_id_param))
+            .filter(space_id.eq(space_id_param))
+            .order(week_start.desc())
+            .load::<SavedReview>(c)
+    })
+    .await
+}
+
+pub async fn get_weekly_review(
+    conn: &DbConn,
+    user_id_param: String,
+    review_id: Uuid,
+) -> Result<SavedReview, diesel::result::Error> {
+    use crate::schema::weekly_reviews::dsl::*;
+
+    conn.run(move |c| weekly_reviews.filter(id.eq(review_id)).filter(user_id.eq(user_id_param)).first::<SavedReview>(c))
+        .await
+}
+
+pub async fn delete_weekly_review(
+    conn: &DbConn,
+    user_id_param: String,
+    review_id: Uuid,
+) -> Result<usize, diesel::result::Error> {
+    use crate::schema::weekly_reviews::dsl::*;
+
+    conn.run(move |c| diesel::delete(weekly_reviews.filter(id.eq(review_id)).filter(user_id.eq(user_id_param))).execute(c))
+        .await
+}
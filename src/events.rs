@@ -0,0 +1,65 @@
+// Events pushed to the user while time is being tracked. Whatever produces them
+// only talks to a `Notifier`, the `EventHub` hands them to the `/track/events` stream.
+
+use rocket::tokio::sync::broadcast;
+use serde::Serialize;
+use crate::budgets::BudgetAlert;
+use crate::autostop::AutoStopped;
+use crate::pomodoro::PhaseChange;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TrackingEvent {
+    BudgetAlert(BudgetAlert),
+    PomodoroPhase(PhaseChange),
+    SessionAutoStopped(AutoStopped),
+}
+
+impl TrackingEvent {
+    // SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrackingEvent::BudgetAlert(_) => "budget_alert",
+            TrackingEvent::PomodoroPhase(_) => "pomodoro_phase",
+            TrackingEvent::SessionAutoStopped(_) => "session_auto_stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserEvent {
+    pub user_id: String,
+    pub event: TrackingEvent,
+}
+
+#[rocket::async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, user_id: &str, event: TrackingEvent);
+}
+
+// Fans events out to every open event stream, each stream keeps its own user's
+#[derive(Clone)]
+pub struct EventHub {
+    sender: broadcast::Sender<UserEvent>,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(256);
+        EventHub { sender }
+    }
+}
+
+impl EventHub {
+    pub fn subscribe(&self) -> broadcast::Receiver<UserEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[rocket::async_trait]
+impl Notifier for EventHub {
+    async fn notify(&self, user_id: &str, event: TrackingEvent) {
+        // an error only means nobody is listening right now
+        let _ = self.sender.send(UserEvent { user_id: user_id.to_string(), event });
+    }
+}
@@ -0,0 +1,217 @@
+// Row formatting for time tracking exports. Each function turns one session
+// into a chunk of bytes so the routes can stream large histories page by page.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rocket::http::ContentType;
+use crate::models::TimeTrackingSession;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+    ICalendar,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "csv" => Some(ExportFormat::Csv),
+            "jsonl" | "json" => Some(ExportFormat::JsonLines),
+            "ics" | "ical" => Some(ExportFormat::ICalendar),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            ExportFormat::Csv => ContentType::CSV,
+            ExportFormat::JsonLines => ContentType::new("application", "x-ndjson"),
+            ExportFormat::ICalendar => ContentType::Calendar,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::ICalendar => "ics",
+        }
+    }
+
+    // calendars only make sense for finished sessions
+    pub fn completed_only(&self) -> bool {
+        matches!(self, ExportFormat::ICalendar)
+    }
+
+    pub fn header(&self, calendar_name: &str) -> Vec<u8> {
+        match self {
+            ExportFormat::Csv => csv_header(),
+            ExportFormat::JsonLines => Vec::new(),
+            ExportFormat::ICalendar => ics_header(calendar_name),
+        }
+    }
+
+    pub fn row(&self, session: &TimeTrackingSession) -> Vec<u8> {
+        match self {
+            ExportFormat::Csv => csv_row(session),
+            ExportFormat::JsonLines => {
+                let mut line = serde_json::to_vec(session).unwrap_or_default();
+                line.push(b'\n');
+                line
+            }
+            ExportFormat::ICalendar => session_event(session).unwrap_or_default(),
+        }
+    }
+
+    pub fn footer(&self) -> Vec<u8> {
+        match self {
+            ExportFormat::ICalendar => ics_footer(),
+            _ => Vec::new(),
+        }
+    }
+    // Ends an export that broke off. Calendars simply lack their footer, the line based
+    // formats have none, so they get a last line saying the file is incomplete.
+    pub fn error(&self, message: &str) -> Vec<u8> {
+        match self {
+            ExportFormat::Csv => csv_record(["ERROR", message]),
+            ExportFormat::JsonLines => {
+                let mut line = serde_json::to_vec(&serde_json::json!({ "error": message })).unwrap_or_default();
+                line.push(b'\n');
+                line
+            }
+            ExportFormat::ICalendar => Vec::new(),
+        }
+    }
+}
+
+// same layout as the table dump in extras/database
+const CSV_COLUMNS: [&str; 7] = ["id", "user_id", "space_id", "activity_name", "start_time", "end_time", "duration"];
+const CSV_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub fn csv_record<I, T>(fields: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // writing to a Vec cannot fail
+    let _ = writer.write_record(fields);
+    writer.into_inner().unwrap_or_default()
+}
+
+pub fn csv_header() -> Vec<u8> {
+    csv_record(CSV_COLUMNS)
+}
+
+pub fn csv_row(session: &TimeTrackingSession) -> Vec<u8> {
+    csv_record([
+        session.id.to_string(),
+        session.user_id.clone(),
+        session.space_id.to_string(),
+        session.activity_name.clone(),
+        session.start_time.format(CSV_TIME_FORMAT).to_string(),
+        session.end_time.map(|t| t.format(CSV_TIME_FORMAT).to_string()).unwrap_or_default(),
+        session.duration.map(|d| d.to_string()).unwrap_or_default(),
+    ])
+}
+
+// iCalendar (RFC 5545)
+
+fn ics_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+pub fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// content lines longer than 75 octets are folded onto continuation lines
+fn ics_line(line: &str) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+
+    for ch in line.chars() {
+        let len = ch.len_utf8();
+        if width + len > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(ch);
+        width += len;
+    }
+
+    out.push_str("\r\n");
+    out
+}
+
+pub fn ics_header(calendar_name: &str) -> Vec<u8> {
+    [
+        ics_line("BEGIN:VCALENDAR"),
+        ics_line("VERSION:2.0"),
+        ics_line("PRODID:-//RustySpaces//Time Tracking//EN"),
+        ics_line("CALSCALE:GREGORIAN"),
+        ics_line(&format!("X-WR-CALNAME:{}", ics_escape(calendar_name))),
+    ]
+    .concat()
+    .into_bytes()
+}
+
+pub fn ics_footer() -> Vec<u8> {
+    ics_line("END:VCALENDAR").into_bytes()
+}
+
+pub fn ics_event(uid: &str, start: DateTime<Utc>, end: Option<DateTime<Utc>>, summary: &str, description: Option<&str>) -> Vec<u8> {
+    let mut lines = vec![
+        ics_line("BEGIN:VEVENT"),
+        ics_line(&format!("UID:{}@rustyspaces", uid)),
+        ics_line(&format!("DTSTAMP:{}", ics_time(chrono::Utc::now()))),
+        ics_line(&format!("DTSTART:{}", ics_time(start))),
+    ];
+    if let Some(end) = end {
+        lines.push(ics_line(&format!("DTEND:{}", ics_time(end))));
+    }
+    lines.push(ics_line(&format!("SUMMARY:{}", ics_escape(summary))));
+    if let Some(description) = description {
+        lines.push(ics_line(&format!("DESCRIPTION:{}", ics_escape(description))));
+    }
+    lines.push(ics_line("END:VEVENT"));
+
+    lines.concat().into_bytes()
+}
+
+// an event spanning the whole of `date`, DTEND is the day after (exclusive)
+pub fn ics_all_day_event(uid: &str, date: NaiveDate, summary: &str, description: Option<&str>) -> Vec<u8> {
+    let mut lines = vec![
+        ics_line("BEGIN:VEVENT"),
+        ics_line(&format!("UID:{}@rustyspaces", uid)),
+        ics_line(&format!("DTSTAMP:{}", ics_time(chrono::Utc::now()))),
+        ics_line(&format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d"))),
+        ics_line(&format!("DTEND;VALUE=DATE:{}", (date + chrono::Duration::days(1)).format("%Y%m%d"))),
+        ics_line(&format!("SUMMARY:{}", ics_escape(summary))),
+    ];
+    if let Some(description) = description {
+        lines.push(ics_line(&format!("DESCRIPTION:{}", ics_escape(description))));
+    }
+    lines.push(ics_line("TRANSP:TRANSPARENT"));
+    lines.push(ics_line("END:VEVENT"));
+
+    lines.concat().into_bytes()
+}
+
+// one VEVENT per completed session, None while the session is still running
+pub fn session_event(session: &TimeTrackingSession) -> Option<Vec<u8>> {
+    let end_time = session.end_time?;
+    let minutes = session.duration.unwrap_or(0) / 60;
+
+    Some(ics_event(
+        &session.id.to_string(),
+        session.start_time,
+        Some(end_time),
+        &session.activity_name,
+        Some(&format!("Tracked {}h {:02}m", minutes / 60, minutes % 60)),
+    ))
+}
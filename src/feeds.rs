@@ -0,0 +1,51 @@
+// A calendar app subscribes to `/feeds/ical/<token>.ics` and gets the user's tracked
+// sessions and due notes, built fresh on every fetch. The token is the only thing that
+// guards the feed, so it is random, shown once and stored as a hash. Making a new one
+// replaces the old one, deleting the feed turns the url off.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use crate::export;
+use crate::models::{note_completion, StickyNote};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+pub fn token_hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+// All day events for a note's due date and for each line with one of its own
+pub fn due_events(note: &StickyNote, space_name: &str) -> Vec<u8> {
+    let lines = note.sticky_lines();
+    let mut events = Vec::new();
+
+    if let Some(due) = note.due_date {
+        let done = !lines.is_empty() && note_completion(&lines) >= 100.0;
+        events.extend(export::ics_all_day_event(
+            &format!("note-{}", note.id),
+            due,
+            &format!("{}{}", if done { "✓ " } else { "" }, note.title),
+            Some(&format!("Note due in {}", space_name)),
+        ));
+    }
+    // keyed by the line id, so an event stays the same one when lines are reordered
+    for line in &lines {
+        let Some(due) = line.due else { continue };
+        events.extend(export::ics_all_day_event(
+            &format!("note-{}-line-{}", note.id, line.id),
+            due,
+            &format!("{}{}", if line.is_checked { "✓ " } else { "" }, line.text),
+            Some(&format!("{} in {}", note.title, space_name)),
+        ));
+    }
+
+    events
+}
@@ -0,0 +1,211 @@
+// Goals per space, like "30 hours of Rust this month" or "5 blog posts this month".
+// Progress is counted whenever it is asked for, from the tracked time of an activity
+// or from note lines checked within the goal's dates. Snapshots keep one value per
+// local day, written on every read and by a background task on an interval.
+
+use std::time::Duration;
+use chrono::{DateTime, Days, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use crate::budgets;
+use crate::db::{self, DbConn, DbPool};
+use crate::models::{Goal, GoalSnapshot};
+use crate::reports::local_midnight_utc;
+
+// a century of seconds, far more lines than anyone checks off
+pub const MAX_TARGET: i64 = 100 * 366 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoalKind {
+    Checklist,
+    Time,
+}
+
+impl GoalKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "checklist" => Some(GoalKind::Checklist),
+            "time" => Some(GoalKind::Time),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Projection {
+    pub expected_progress: i64, // where an even pace from start to end would be by now
+    pub pace_per_day: f64,
+    pub projected_total: i64, // at the end date, if the pace so far holds
+    pub projected_completion: Option<NaiveDate>, // None before any progress and once the target is reached
+    pub on_track: bool,
+}
+
+#[derive(Serialize)]
+pub struct GoalProgress {
+    #[serde(flatten)]
+    pub goal: Goal,
+    pub activity_name: Option<String>,
+    pub progress: i64,
+    pub remaining: i64,
+    pub percent: f64,
+    pub completed: bool,
+    pub time_zone: String,
+    #[serde(flatten)]
+    pub projection: Projection,
+}
+
+// The goal's dates as UTC instants, the end is exclusive
+pub fn goal_bounds(goal: &Goal, time_zone: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+    (
+        local_midnight_utc(goal.start_date, time_zone),
+        local_midnight_utc(goal.end_date.checked_add_days(Days::new(1)).unwrap_or(goal.end_date), time_zone),
+    )
+}
+
+// Extrapolates `progress` made between `from` and `now` to the whole `from`..`to`
+pub fn project(
+    target: i64,
+    progress: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    now: DateTime<Utc>,
+    time_zone: Tz,
+) -> Projection {
+    let length = (to - from).num_seconds().max(1);
+    let elapsed = (now - from).num_seconds().clamp(0, length);
+    if elapsed == 0 {
+        return Projection {
+            expected_progress: 0,
+            pace_per_day: 0.0,
+            projected_total: progress,
+            projected_completion: None,
+            on_track: true,
+        };
+    }
+
+    let pace = progress as f64 / elapsed as f64; // per second
+    let projected_total = (pace * length as f64).round() as i64;
+    // None as well when the pace is too slow to ever get there
+    let projected_completion = if progress > 0 && progress < target {
+        TimeDelta::try_seconds((target as f64 / pace) as i64)
+            .and_then(|needed| from.checked_add_signed(needed))
+            .map(|at| at.with_timezone(&time_zone).date_naive())
+    } else {
+        None
+    };
+
+    Projection {
+        expected_progress: (target as f64 * elapsed as f64 / length as f64).round() as i64,
+        pace_per_day: pace * 86400.0,
+        projected_total,
+        projected_completion,
+        on_track: progress >= target || projected_total >= target,
+    }
+}
+
+// Seconds tracked for a time goal, lines checked for a checklist goal, up to `now`
+pub async fn current_progress(
+    conn: &DbConn,
+    goal: &Goal,
+    time_zone: Tz,
+    now: DateTime<Utc>,
+) -> Result<i64, diesel::result::Error> {
+    let (from, to) = goal_bounds(goal, time_zone);
+    let to = to.min(now);
+    if to <= from {
+        return Ok(0);
+    }
+
+    match (GoalKind::parse(&goal.kind), goal.activity_id) {
+        (Some(GoalKind::Time), Some(activity_id)) => db::tracked_seconds(conn, activity_id, from, to, now).await,
+        (Some(GoalKind::Time), None) => Ok(0),
+        _ => db::checked_lines_count(conn, goal.space_id, goal.note_id, from, to).await,
+    }
+}
+
+// Keeps today's value, or the last day's for a goal that ended yesterday
+async fn record_snapshot(
+    conn: &DbConn,
+    goal: &Goal,
+    progress: i64,
+    time_zone: Tz,
+    now: DateTime<Utc>,
+) -> Result<(), diesel::result::Error> {
+    let today = now.with_timezone(&time_zone).date_naive();
+    let day_after = goal.end_date.checked_add_days(Days::new(1));
+    if today < goal.start_date || day_after.is_some_and(|day_after| today > day_after) {
+        return Ok(());
+    }
+
+    let snapshot = GoalSnapshot {
+        goal_id: goal.id,
+        day: today.min(goal.end_date),
+        progress,
+        recorded_at: now,
+    };
+    db::save_goal_snapshot(conn, snapshot).await.map(|_| ())
+}
+
+pub async fn goal_progress(conn: &DbConn, goal: Goal, time_zone: Tz) -> Result<GoalProgress, diesel::result::Error> {
+    let now = chrono::Utc::now();
+    let progress = current_progress(conn, &goal, time_zone, now).await?;
+    record_snapshot(conn, &goal, progress, time_zone, now).await?;
+
+    let activity_name = match goal.activity_id {
+        Some(activity_id) => db::get_activity(conn, goal.user_id.clone(), activity_id).await.ok().map(|activity| activity.name),
+        None => None,
+    };
+    let (from, to) = goal_bounds(&goal, time_zone);
+
+    Ok(GoalProgress {
+        activity_name,
+        progress,
+        remaining: (goal.target - progress).max(0),
+        percent: (progress as f64 * 100.0 / goal.target as f64).min(100.0),
+        completed: progress >= goal.target,
+        time_zone: time_zone.name().to_string(),
+        projection: project(goal.target, progress, from, to, now, time_zone),
+        goal,
+    })
+}
+
+// Snapshots every goal that is running, so the history has no gaps on days nobody looked
+pub async fn snapshot_goals(conn: &DbConn) -> Result<(), diesel::result::Error> {
+    let now = chrono::Utc::now();
+
+    let mut time_zones: Vec<(String, i32, Tz)> = Vec::new();
+    for goal in db::get_goals_around(conn, now.date_naive()).await? {
+        let known = time_zones
+            .iter()
+            .find(|(user_id, space_id, _)| *user_id == goal.user_id && *space_id == goal.space_id);
+        let time_zone = match known {
+            Some((_, _, time_zone)) => *time_zone,
+            None => {
+                let time_zone = budgets::user_time_zone(conn, &goal.user_id, goal.space_id).await;
+                time_zones.push((goal.user_id.clone(), goal.space_id, time_zone));
+                time_zone
+            }
+        };
+
+        let progress = current_progress(conn, &goal, time_zone, now).await?;
+        record_snapshot(conn, &goal, progress, time_zone, now).await?;
+    }
+
+    Ok(())
+}
+
+// Runs for as long as the server does, see `time_tracking.goal_snapshot_interval_secs`
+pub async fn run_snapshots(pool: DbPool, interval: Duration) {
+    let mut ticker = rocket::tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let Some(conn) = db::checkout(&pool).await else {
+            eprintln!("Goal snapshots skipped, no database connection available");
+            continue;
+        };
+        if let Err(e) = snapshot_goals(&conn).await {
+            eprintln!("Error taking goal snapshots: {:?}", e);
+        }
+    }
+}
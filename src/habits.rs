@@ -0,0 +1,249 @@
+// Daily habits and their streaks. Nothing is stored per day for a time habit, its
+// days come out of the tracked sessions. A checklist habit is done on the days it
+// was checked off by hand or its note line was (last) checked.
+
+use chrono::{Days, Duration, NaiveDate};
+use chrono_tz::Tz;
+use diesel::sql_types::{BigInt, Date};
+use diesel::QueryableByName;
+use serde::Serialize;
+use crate::db::{self, DbConn};
+use crate::models::Habit;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HabitKind {
+    Checklist,
+    Time,
+}
+
+impl HabitKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "checklist" => Some(HabitKind::Checklist),
+            "time" => Some(HabitKind::Time),
+            _ => None,
+        }
+    }
+}
+
+// A per day sum, in seconds for tracked time and as a count for line checks
+#[derive(QueryableByName)]
+pub struct DayTotal {
+    #[diesel(sql_type = Date)]
+    pub day: NaiveDate,
+    #[diesel(sql_type = BigInt)]
+    pub total: i64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Streaks {
+    pub current: i64,
+    pub longest: i64,
+}
+
+#[derive(Serialize)]
+pub struct HabitStatus {
+    #[serde(flatten)]
+    pub habit: Habit,
+    pub activity_name: Option<String>,
+    pub done_today: bool,
+    pub today_seconds: Option<i64>, // time habits only
+    pub current_streak: i64,
+    pub longest_streak: i64,
+    pub completed_days: i64,
+}
+
+#[derive(Serialize)]
+pub struct HabitDay {
+    pub date: NaiveDate,
+    pub completed: bool,
+    pub tracked_seconds: Option<i64>, // time habits only
+}
+
+#[derive(Serialize)]
+pub struct HeatmapDay {
+    pub date: NaiveDate,
+    pub tracked_seconds: i64,
+    pub line_checks: i64,
+    pub habits_completed: i64,
+    pub level: u8, // 0 for an empty day, otherwise 1 - 4 by tracked time against the busiest day
+}
+
+pub fn local_today(time_zone: Tz) -> NaiveDate {
+    chrono::Utc::now().with_timezone(&time_zone).date_naive()
+}
+
+// `days` sorted and without duplicates. The current streak still counts while
+// today is open, it only breaks once a whole day was missed.
+pub fn streaks(days: &[NaiveDate], today: NaiveDate) -> Streaks {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in days.iter().filter(|day| **day <= today) {
+        run = match previous {
+            Some(previous) if *day - previous == Duration::days(1) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(*day);
+    }
+
+    let current = match previous {
+        Some(last) if today - last <= Duration::days(1) => run,
+        _ => 0,
+    };
+
+    Streaks { current, longest }
+}
+
+// Seconds of the habit's activity per local day, only for time habits
+async fn tracked_days(
+    conn: &DbConn,
+    habit: &Habit,
+    time_zone: Tz,
+    from: Option<NaiveDate>,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<DayTotal>, diesel::result::Error> {
+    if habit.activity_id.is_none() {
+        return Ok(Vec::new());
+    }
+    db::daily_tracked_seconds(
+        conn,
+        habit.user_id.clone(),
+        Some(habit.space_id),
+        habit.activity_id,
+        time_zone.name().to_string(),
+        from,
+        to_exclusive,
+    )
+    .await
+}
+
+// Local days the habit was done on, sorted. `from` None goes back to the beginning.
+pub async fn completed_days(
+    conn: &DbConn,
+    habit: &Habit,
+    time_zone: Tz,
+    from: Option<NaiveDate>,
+    to_exclusive: NaiveDate,
+) -> Result<Vec<NaiveDate>, diesel::result::Error> {
+    let mut days = match HabitKind::parse(&habit.kind) {
+        Some(HabitKind::Time) => {
+            let target = habit.target_seconds.unwrap_or(0);
+            tracked_days(conn, habit, time_zone, from, to_exclusive)
+                .await?
+                .into_iter()
+                .filter(|day| day.total >= target)
+                .map(|day| day.day)
+                .collect()
+        }
+        _ => {
+            let mut days = db::get_habit_completion_days(conn, habit.id, from, to_exclusive).await?;
+            if let (Some(note_id), Some(line)) = (habit.note_id, habit.line_id.clone().or_else(|| habit.line_text.clone())) {
+                days.extend(
+                    db::checked_line_days(conn, note_id, line, time_zone.name().to_string(), from, to_exclusive).await?,
+                );
+            }
+            days
+        }
+    };
+    days.sort();
+    days.dedup();
+
+    Ok(days)
+}
+
+pub async fn habit_status(conn: &DbConn, habit: Habit, time_zone: Tz) -> Result<HabitStatus, diesel::result::Error> {
+    let today = local_today(time_zone);
+    let tomorrow = today + Duration::days(1);
+
+    let days = completed_days(conn, &habit, time_zone, None, tomorrow).await?;
+    let streaks = streaks(&days, today);
+
+    let (activity_name, today_seconds) = match habit.activity_id {
+        Some(activity_id) => {
+            let activity_name = db::get_activity(conn, habit.user_id.clone(), activity_id).await.ok().map(|activity| activity.name);
+            let today_seconds = tracked_days(conn, &habit, time_zone, Some(today), tomorrow)
+                .await?
+                .into_iter()
+                .map(|day| day.total)
+                .sum();
+            (activity_name, Some(today_seconds))
+        }
+        None => (None, None),
+    };
+
+    Ok(HabitStatus {
+        activity_name,
+        done_today: days.last() == Some(&today),
+        today_seconds,
+        current_streak: streaks.current,
+        longest_streak: streaks.longest,
+        completed_days: days.len() as i64,
+        habit,
+    })
+}
+
+// Every day from `from` to `to` (inclusive) with whether the habit was done
+pub async fn habit_days(
+    conn: &DbConn,
+    habit: &Habit,
+    time_zone: Tz,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<HabitDay>, diesel::result::Error> {
+    // `to` comes from `date_range` and is far from chrono's limits, this only guards
+    let to_exclusive = to.checked_add_days(Days::new(1)).unwrap_or(to);
+    let completed = completed_days(conn, habit, time_zone, Some(from), to_exclusive).await?;
+    let tracked = match HabitKind::parse(&habit.kind) {
+        Some(HabitKind::Time) => Some(tracked_days(conn, habit, time_zone, Some(from), to_exclusive).await?),
+        _ => None,
+    };
+
+    Ok(from
+        .iter_days()
+        .take_while(|date| *date <= to)
+        .map(|date| HabitDay {
+            date,
+            completed: completed.contains(&date),
+            tracked_seconds: tracked
+                .as_ref()
+                .map(|tracked| tracked.iter().find(|day| day.day == date).map_or(0, |day| day.total)),
+        })
+        .collect())
+}
+
+fn day_total(totals: &[DayTotal], date: NaiveDate) -> i64 {
+    totals.iter().find(|day| day.day == date).map_or(0, |day| day.total)
+}
+
+// One entry per day from `from` to `to` (inclusive), `habit_days` holds the
+// completed days of each habit
+pub fn heatmap(
+    from: NaiveDate,
+    to: NaiveDate,
+    tracked: &[DayTotal],
+    line_checks: &[DayTotal],
+    habit_days: &[Vec<NaiveDate>],
+) -> Vec<HeatmapDay> {
+    let busiest = tracked.iter().map(|day| day.total).max().unwrap_or(0);
+
+    from.iter_days()
+        .take_while(|date| *date <= to)
+        .map(|date| {
+            let tracked_seconds = day_total(tracked, date);
+            let line_checks = day_total(line_checks, date);
+            let habits_completed = habit_days.iter().filter(|days| days.contains(&date)).count() as i64;
+
+            let level = if tracked_seconds > 0 && busiest > 0 {
+                ((tracked_seconds * 4 + busiest - 1) / busiest).clamp(1, 4) as u8
+            } else if line_checks > 0 || habits_completed > 0 {
+                1
+            } else {
+                0
+            };
+
+            HeatmapDay { date, tracked_seconds, line_checks, habits_completed, level }
+        })
+        .collect()
+}
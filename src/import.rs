@@ -0,0 +1,454 @@
+// Importing time entries from CSV. Rows are parsed and checked here, the route
+// decides whether the clean ones get written (see `db::insert_completed_sessions`).
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::models::{TimeTrackingSession, SESSION_TYPE_REGULAR};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    Generic,
+    Toggl,
+    Clockify,
+}
+
+impl ImportSource {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "csv" | "generic" => Some(ImportSource::Generic),
+            "toggl" => Some(ImportSource::Toggl),
+            "clockify" => Some(ImportSource::Clockify),
+            _ => None,
+        }
+    }
+}
+
+// Column names to read from a generic CSV, defaults match our own export
+#[derive(Debug, Clone, FromForm)]
+pub struct ColumnMapping {
+    pub activity: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub duration: Option<String>, // seconds, used when there is no end column
+}
+
+// Where each field lives in the file once the header has been matched up
+struct Columns {
+    activity: Vec<usize>, // first non empty one wins
+    start: DateColumns,
+    end: Option<DateColumns>,
+    duration: Option<usize>,
+}
+
+enum DateColumns {
+    Combined(usize),
+    Split { date: usize, time: usize },
+}
+
+fn find(headers: &csv::StringRecord, name: &str) -> Option<usize> {
+    headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name))
+}
+
+fn require(headers: &csv::StringRecord, name: &str) -> Result<usize, String> {
+    find(headers, name).ok_or_else(|| format!("Missing column \"{}\"", name))
+}
+
+impl Columns {
+    fn resolve(source: ImportSource, mapping: &ColumnMapping, headers: &csv::StringRecord) -> Result<Self, String> {
+        match source {
+            ImportSource::Generic => {
+                let end = match &mapping.end {
+                    Some(name) => Some(DateColumns::Combined(require(headers, name)?)),
+                    None => find(headers, "end_time").map(DateColumns::Combined),
+                };
+                let duration = match &mapping.duration {
+                    Some(name) => Some(require(headers, name)?),
+                    None => find(headers, "duration"),
+                };
+                if end.is_none() && duration.is_none() {
+                    return Err("Either an end or a duration column is needed".to_string());
+                }
+
+                Ok(Columns {
+                    activity: vec![require(headers, mapping.activity.as_deref().unwrap_or("activity_name"))?],
+                    start: DateColumns::Combined(require(headers, mapping.start.as_deref().unwrap_or("start_time"))?),
+                    end,
+                    duration,
+                })
+            }
+            // Toggl: ...,Project,Task,Description,...,Start date,Start time,End date,End time,Duration,...
+            ImportSource::Toggl => Ok(Columns {
+                activity: ["Description", "Task", "Project"].iter().filter_map(|name| find(headers, name)).collect(),
+                start: DateColumns::Split { date: require(headers, "Start date")?, time: require(headers, "Start time")? },
+                end: Some(DateColumns::Split { date: require(headers, "End date")?, time: require(headers, "End time")? }),
+                duration: None,
+            }),
+            // Clockify: Project,Client,Description,Task,...,Start Date,Start Time,End Date,End Time,...
+            ImportSource::Clockify => Ok(Columns {
+                activity: ["Description", "Task", "Project"].iter().filter_map(|name| find(headers, name)).collect(),
+                start: DateColumns::Split { date: require(headers, "Start Date")?, time: require(headers, "Start Time")? },
+                end: Some(DateColumns::Split { date: require(headers, "End Date")?, time: require(headers, "End Time")? }),
+                duration: None,
+            }),
+        }
+    }
+}
+
+const DATETIME_FORMATS: [&str; 6] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%m/%d/%Y %I:%M:%S %p",
+    "%m/%d/%Y %H:%M:%S",
+    "%d.%m.%Y %H:%M:%S",
+];
+const DATE_FORMATS: [&str; 4] = ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y", "%d/%m/%Y"];
+const TIME_FORMATS: [&str; 4] = ["%H:%M:%S", "%H:%M", "%I:%M:%S %p", "%I:%M %p"];
+
+// local wall clock time in `time_zone` to UTC
+fn local_to_utc(local: NaiveDateTime, time_zone: Tz) -> Option<DateTime<Utc>> {
+    time_zone.from_local_datetime(&local).earliest().map(|time| time.with_timezone(&Utc))
+}
+
+fn parse_datetime(value: &str, time_zone: Tz) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    // an explicit offset wins over the import time zone
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .and_then(|local| local_to_utc(local, time_zone))
+}
+
+fn parse_split(date: &str, time: &str, time_zone: Tz) -> Option<DateTime<Utc>> {
+    let date = DATE_FORMATS.iter().find_map(|format| NaiveDate::parse_from_str(date.trim(), format).ok())?;
+    let time = TIME_FORMATS.iter().find_map(|format| NaiveTime::parse_from_str(time.trim(), format).ok())?;
+    local_to_utc(date.and_time(time), time_zone)
+}
+
+fn read_time(record: &csv::StringRecord, columns: &DateColumns, time_zone: Tz) -> Option<DateTime<Utc>> {
+    match columns {
+        DateColumns::Combined(index) => parse_datetime(record.get(*index)?, time_zone),
+        DateColumns::Split { date, time } => parse_split(record.get(*date)?, record.get(*time)?, time_zone),
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Ok,
+    Invalid,
+    Duplicate,
+    Overlap,
+}
+
+#[derive(Serialize)]
+pub struct ImportRow {
+    pub line: usize,
+    pub status: RowStatus,
+    pub activity_name: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub duration: Option<i64>,
+    pub message: Option<String>,
+}
+
+impl ImportRow {
+    fn invalid(line: usize, message: impl Into<String>) -> Self {
+        ImportRow {
+            line,
+            status: RowStatus::Invalid,
+            activity_name: None,
+            start_time: None,
+            end_time: None,
+            duration: None,
+            message: Some(message.into()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub importable: usize,
+    pub imported: usize,
+    pub skipped: usize,
+    pub rows: Vec<ImportRow>,
+}
+
+// Reads every row into an ImportRow, only checking the row on its own
+pub fn parse_rows(
+    data: &[u8],
+    source: ImportSource,
+    mapping: &ColumnMapping,
+    time_zone: Tz,
+) -> Result<Vec<ImportRow>, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(data);
+
+    let headers = reader.headers().map_err(|e| format!("Could not read the CSV header: {}", e))?.clone();
+    let columns = Columns::resolve(source, mapping, &headers)?;
+    let now = chrono::Utc::now();
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let line = index + 2; // 1 based, after the header
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                rows.push(ImportRow::invalid(line, format!("Unreadable row: {}", e)));
+                continue;
+            }
+        };
+
+        let activity_name = columns
+            .activity
+            .iter()
+            .filter_map(|index| record.get(*index))
+            .find(|value| !value.is_empty())
+            .map(|value| value.to_string());
+        let Some(activity_name) = activity_name else {
+            rows.push(ImportRow::invalid(line, "Activity is empty"));
+            continue;
+        };
+
+        let Some(start_time) = read_time(&record, &columns.start, time_zone) else {
+            rows.push(ImportRow::invalid(line, "Start time could not be read"));
+            continue;
+        };
+
+        let end_time = match &columns.end {
+            Some(end) => read_time(&record, end, time_zone),
+            None => None,
+        };
+        let seconds = columns.duration.and_then(|index| record.get(index)).and_then(|value| value.parse::<i64>().ok());
+        let end_time = match (end_time, seconds) {
+            (Some(end_time), _) => Some(end_time),
+            (None, Some(seconds)) => {
+                let end_time = chrono::TimeDelta::try_seconds(seconds).and_then(|duration| start_time.checked_add_signed(duration));
+                if end_time.is_none() {
+                    rows.push(ImportRow::invalid(line, "Duration is out of range"));
+                    continue;
+                }
+                end_time
+            }
+            (None, None) => None,
+        };
+        let Some(end_time) = end_time else {
+            rows.push(ImportRow::invalid(line, "End time could not be read"));
+            continue;
+        };
+
+        let mut row = ImportRow {
+            line,
+            status: RowStatus::Ok,
+            activity_name: Some(activity_name),
+            start_time: Some(start_time),
+            end_time: Some(end_time),
+            duration: Some(end_time.signed_duration_since(start_time).num_seconds()),
+            message: None,
+        };
+
+        if end_time <= start_time {
+            row.status = RowStatus::Invalid;
+            row.message = Some("End time must be after the start time".to_string());
+        } else if end_time > now {
+            row.status = RowStatus::Invalid;
+            row.message = Some("Entry ends in the future".to_string());
+        }
+
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn overlaps(a_start: DateTime<Utc>, a_end: DateTime<Utc>, b_start: DateTime<Utc>, b_end: DateTime<Utc>) -> bool {
+    a_start < b_end && b_start < a_end
+}
+
+// Marks rows that repeat an existing session (or an earlier row) as duplicates and
+// rows that cross one as overlaps, unless `allow_overlaps` is set. `existing` are the
+// user's sessions in the same time range.
+pub fn check_against(rows: &mut [ImportRow], existing: &[TimeTrackingSession], allow_overlaps: bool) {
+    let now = chrono::Utc::now();
+    let mut accepted: Vec<(String, DateTime<Utc>, DateTime<Utc>)> = Vec::new();
+
+    for row in rows.iter_mut().filter(|row| row.status == RowStatus::Ok) {
+        let (Some(activity), Some(start), Some(end)) = (row.activity_name.clone(), row.start_time, row.end_time) else {
+            continue;
+        };
+
+        let same = |other_activity: &str, other_start: DateTime<Utc>, other_end: DateTime<Utc>| {
+            other_activity == activity
+                && other_start.timestamp() == start.timestamp()
+                && other_end.timestamp() == end.timestamp()
+        };
+
+        let existing_duplicate = existing
+            .iter()
+            .any(|session| same(&session.activity_name, session.start_time, session.end_time.unwrap_or(now)));
+        let file_duplicate = accepted.iter().any(|(a, s, e)| same(a, *s, *e));
+
+        if existing_duplicate || file_duplicate {
+            row.status = RowStatus::Duplicate;
+            row.message = Some(if existing_duplicate { "Already tracked" } else { "Repeats an earlier row" }.to_string());
+            continue;
+        }
+
+        if allow_overlaps {
+            accepted.push((activity, start, end));
+            continue;
+        }
+
+        let existing_overlap = existing
+            .iter()
+            .find(|session| overlaps(start, end, session.start_time, session.end_time.unwrap_or(now)));
+        if let Some(session) = existing_overlap {
+            row.status = RowStatus::Overlap;
+            row.message = Some(format!("Overlaps \"{}\" starting {}", session.activity_name, session.start_time));
+            continue;
+        }
+        if accepted.iter().any(|(_, s, e)| overlaps(start, end, *s, *e)) {
+            row.status = RowStatus::Overlap;
+            row.message = Some("Overlaps an earlier row".to_string());
+            continue;
+        }
+
+        accepted.push((activity, start, end));
+    }
+}
+
+// The rows that passed every check, ready to insert
+pub fn sessions_to_insert(rows: &[ImportRow], user_id: &str, space_id: i32) -> Vec<TimeTrackingSession> {
+    rows.iter()
+        .filter(|row| row.status == RowStatus::Ok)
+        .filter_map(|row| {
+            Some(TimeTrackingSession {
+                id: Uuid::new_v4(),
+                user_id: user_id.to_string(),
+                space_id,
+                activity_name: row.activity_name.clone()?,
+                start_time: row.start_time?,
+                end_time: row.end_time,
+                duration: row.duration,
+                activity_id: None,
+                notes: None,
+                session_type: SESSION_TYPE_REGULAR.to_string(),
+                last_heartbeat_at: None,
+                auto_stopped: false,
+                auto_stop_reason: None,
+                reviewed_at: None,
+                note_id: None,
+                note_line_id: None,
+                tags: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generic() -> ColumnMapping {
+        ColumnMapping { activity: None, start: None, end: None, duration: None }
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn generic_rows_are_read_in_the_import_time_zone() {
+        let data = "activity_name,start_time,end_time\nWriting,2026-01-05 09:00:00,2026-01-05 10:30:00\n";
+        let rows = parse_rows(data.as_bytes(), ImportSource::Generic, &generic(), chrono_tz::Europe::Berlin).unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].status, RowStatus::Ok);
+        assert_eq!(rows[0].activity_name.as_deref(), Some("Writing"));
+        assert_eq!(rows[0].start_time, Some(utc("2026-01-05T08:00:00Z")));
+        assert_eq!(rows[0].duration, Some(90 * 60));
+    }
+
+    #[test]
+    fn an_explicit_offset_wins_over_the_time_zone() {
+        let data = "activity_name,start_time,end_time\nWriting,2026-01-05T09:00:00+02:00,2026-01-05T10:00:00+02:00\n";
+        let rows = parse_rows(data.as_bytes(), ImportSource::Generic, &generic(), chrono_tz::Europe::Berlin).unwrap();
+
+        assert_eq!(rows[0].start_time, Some(utc("2026-01-05T07:00:00Z")));
+    }
+
+    #[test]
+    fn mapped_columns_and_a_duration_stand_in_for_the_end() {
+        let mapping = ColumnMapping {
+            activity: Some("Task".to_string()),
+            start: Some("From".to_string()),
+            end: None,
+            duration: Some("Seconds".to_string()),
+        };
+        let data = "Task,From,Seconds\nReview,2026-01-05 09:00,600\n";
+        let rows = parse_rows(data.as_bytes(), ImportSource::Generic, &mapping, chrono_tz::UTC).unwrap();
+
+        assert_eq!(rows[0].status, RowStatus::Ok);
+        assert_eq!(rows[0].end_time, Some(utc("2026-01-05T09:10:00Z")));
+    }
+
+    #[test]
+    fn toggl_falls_back_to_the_project_for_the_activity() {
+        let data = "Project,Task,Description,Start date,Start time,End date,End time\n\
+                    Website,,,2026-01-05,09:00:00,2026-01-05,09:45:00\n";
+        let rows = parse_rows(data.as_bytes(), ImportSource::Toggl, &generic(), chrono_tz::UTC).unwrap();
+
+        assert_eq!(rows[0].status, RowStatus::Ok);
+        assert_eq!(rows[0].activity_name.as_deref(), Some("Website"));
+        assert_eq!(rows[0].duration, Some(45 * 60));
+    }
+
+    #[test]
+    fn bad_rows_are_reported_with_their_line() {
+        let data = "activity_name,start_time,end_time\n\
+                    ,2026-01-05 09:00,2026-01-05 10:00\n\
+                    Writing,yesterday,2026-01-05 10:00\n\
+                    Writing,2026-01-05 10:00,2026-01-05 09:00\n\
+                    Writing,2026-01-05 10:00,2999-01-01 10:00\n";
+        let rows = parse_rows(data.as_bytes(), ImportSource::Generic, &generic(), chrono_tz::UTC).unwrap();
+
+        let errors: Vec<(usize, RowStatus, Option<&str>)> =
+            rows.iter().map(|row| (row.line, row.status, row.message.as_deref())).collect();
+        assert_eq!(
+            errors,
+            vec![
+                (2, RowStatus::Invalid, Some("Activity is empty")),
+                (3, RowStatus::Invalid, Some("Start time could not be read")),
+                (4, RowStatus::Invalid, Some("End time must be after the start time")),
+                (5, RowStatus::Invalid, Some("Entry ends in the future")),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_file_without_an_end_or_duration_column_is_refused() {
+        let data = "activity_name,start_time\nWriting,2026-01-05 09:00\n";
+        let result = parse_rows(data.as_bytes(), ImportSource::Generic, &generic(), chrono_tz::UTC);
+
+        assert_eq!(result.err().as_deref(), Some("Either an end or a duration column is needed"));
+    }
+
+    #[test]
+    fn repeated_and_overlapping_rows_are_marked() {
+        let data = "activity_name,start_time,end_time\n\
+                    Writing,2026-01-05 09:00,2026-01-05 10:00\n\
+                    Writing,2026-01-05 09:00,2026-01-05 10:00\n\
+                    Review,2026-01-05 09:30,2026-01-05 11:00\n";
+        let mut rows = parse_rows(data.as_bytes(), ImportSource::Generic, &generic(), chrono_tz::UTC).unwrap();
+        check_against(&mut rows, &[], false);
+
+        let statuses: Vec<RowStatus> = rows.iter().map(|row| row.status).collect();
+        assert_eq!(statuses, vec![RowStatus::Ok, RowStatus::Duplicate, RowStatus::Overlap]);
+    }
+}
@@ -0,0 +1,493 @@
+// Invoice drafts from tracked time. A session is billable when its activity is, or
+// when its space is marked billable as a whole. The rate is the activity's, else the
+// space's. Each session is rounded on its own (e.g. up to 15 minutes) before the time
+// is summed into one line per client and activity.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::export;
+use crate::models::{Activity, Space, SpaceSettings, TimeTrackingSession};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvoiceFormat {
+    Json,
+    Csv,
+    Html,
+}
+
+impl InvoiceFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(InvoiceFormat::Json),
+            "csv" => Some(InvoiceFormat::Csv),
+            "html" => Some(InvoiceFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    Up,
+    Down,
+    Nearest,
+}
+
+impl RoundingMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "up" => Some(RoundingMode::Up),
+            "down" => Some(RoundingMode::Down),
+            "nearest" => Some(RoundingMode::Nearest),
+            _ => None,
+        }
+    }
+}
+
+// a day, longer intervals make no sense for a single session
+pub const MAX_ROUNDING_MINUTES: i64 = 24 * 60;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rounding {
+    pub minutes: i64,
+    pub mode: RoundingMode,
+}
+
+impl Rounding {
+    // what the space settings ask for, None without an interval
+    pub fn from_settings(settings: &SpaceSettings) -> Option<Self> {
+        let minutes = settings.rounding_minutes.filter(|minutes| (1..=MAX_ROUNDING_MINUTES as i32).contains(minutes))?;
+        let mode = settings.rounding_mode.as_deref().and_then(RoundingMode::parse).unwrap_or(RoundingMode::Up);
+        Some(Rounding { minutes: minutes as i64, mode })
+    }
+
+    pub fn apply(&self, seconds: i64) -> i64 {
+        let step = self.minutes * 60;
+        let steps = match self.mode {
+            RoundingMode::Up => (seconds + step - 1) / step,
+            RoundingMode::Down => seconds / step,
+            RoundingMode::Nearest => (seconds + step / 2) / step,
+        };
+        steps * step
+    }
+}
+
+// A space as a client, see `SpaceSettings`
+pub struct Client {
+    pub space: Space,
+    pub settings: SpaceSettings,
+}
+
+#[derive(Serialize)]
+pub struct InvoiceLine {
+    pub client: String,
+    pub space_id: i32,
+    pub space_name: String,
+    pub activity_name: String,
+    pub sessions: i64,
+    pub tracked_seconds: i64,
+    pub billed_seconds: i64, // after rounding
+    pub billed_hours: f64,
+    pub hourly_rate_cents: Option<i64>, // None when neither the activity nor the space has one
+    pub amount_cents: i64,
+    pub hourly_rate: String, // the same as decimals, e.g. "85.00"
+    pub amount: String,
+    pub currency: String,
+}
+
+#[derive(Serialize)]
+pub struct ClientTotal {
+    pub client: String,
+    pub space_id: i32,
+    pub space_name: String,
+    pub currency: String,
+    pub rounding: Option<Rounding>,
+    pub tracked_seconds: i64,
+    pub billed_seconds: i64,
+    pub amount_cents: i64,
+    pub amount: String,
+}
+
+#[derive(Serialize)]
+pub struct CurrencyTotal {
+    pub currency: String,
+    pub billed_seconds: i64,
+    pub amount_cents: i64,
+    pub amount: String,
+}
+
+#[derive(Serialize)]
+pub struct InvoiceDraft {
+    pub from: NaiveDate,
+    pub to: NaiveDate, // inclusive
+    pub time_zone: String,
+    pub generated_at: DateTime<Utc>,
+    pub lines: Vec<InvoiceLine>,
+    pub clients: Vec<ClientTotal>,
+    pub totals: Vec<CurrencyTotal>, // one per currency, amounts in different ones are not added up
+    pub non_billable_seconds: i64,
+    pub missing_rates: Vec<String>, // billable activities that were billed at 0
+}
+
+pub const DEFAULT_CURRENCY: &str = "EUR";
+
+pub fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+// the rate is per hour, the result is rounded to whole cents
+pub fn amount_cents(hourly_rate_cents: i64, seconds: i64) -> i64 {
+    ((hourly_rate_cents as i128 * seconds as i128 + 1800) / 3600) as i64
+}
+
+fn session_activity<'a>(session: &TimeTrackingSession, activities: &'a [Activity]) -> Option<&'a Activity> {
+    match session.activity_id {
+        Some(activity_id) => activities.iter().find(|activity| activity.id == activity_id),
+        None => activities.iter().find(|activity| {
+            activity.space_id == session.space_id && activity.name.to_lowercase() == session.activity_name.to_lowercase()
+        }),
+    }
+}
+
+// `rounding` wins over the rounding of every client. Sessions of spaces missing from
+// `clients` are left out.
+pub fn build_invoice(
+    from: NaiveDate,
+    to: NaiveDate,
+    time_zone: String,
+    sessions: &[TimeTrackingSession],
+    activities: &[Activity],
+    clients: &[Client],
+    rounding: Option<Rounding>,
+) -> InvoiceDraft {
+    struct Entry {
+        client: usize,
+        activity_id: Option<Uuid>,
+        activity_name: String,
+        hourly_rate_cents: Option<i64>,
+        sessions: i64,
+        tracked_seconds: i64,
+        billed_seconds: i64,
+    }
+
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut non_billable_seconds = 0;
+    for session in sessions {
+        let Some(client) = clients.iter().position(|client| client.space.id == session.space_id) else { continue };
+        let settings = &clients[client].settings;
+        let seconds = session.duration.unwrap_or(0).max(0);
+        let activity = session_activity(session, activities);
+
+        if !(settings.billable || activity.is_some_and(|activity| activity.billable)) {
+            non_billable_seconds += seconds;
+            continue;
+        }
+
+        let billed = match rounding.or_else(|| Rounding::from_settings(settings)) {
+            Some(rounding) => rounding.apply(seconds),
+            None => seconds,
+        };
+        let activity_id = activity.map(|activity| activity.id);
+        let activity_name = activity.map_or(session.activity_name.clone(), |activity| activity.name.clone());
+        let entry = entries.iter_mut().find(|entry| {
+            entry.client == client && entry.activity_id == activity_id && (activity_id.is_some() || entry.activity_name == activity_name)
+        });
+        match entry {
+            Some(entry) => {
+                entry.sessions += 1;
+                entry.tracked_seconds += seconds;
+                entry.billed_seconds += billed;
+            }
+            None => entries.push(Entry {
+                client,
+                activity_id,
+                activity_name,
+                hourly_rate_cents: activity.and_then(|activity| activity.hourly_rate_cents).or(settings.hourly_rate_cents),
+                sessions: 1,
+                tracked_seconds: seconds,
+                billed_seconds: billed,
+            }),
+        }
+    }
+
+    let client_name = |client: &Client| {
+        client.settings.client_name.clone().filter(|name| !name.trim().is_empty()).unwrap_or_else(|| client.space.space_name.clone())
+    };
+    let currency = |client: &Client| client.settings.currency.clone().unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+
+    entries.sort_by_key(|entry| {
+        let client = &clients[entry.client];
+        (client_name(client).to_lowercase(), client.space.id, entry.activity_name.to_lowercase())
+    });
+
+    let mut lines = Vec::new();
+    let mut client_totals: Vec<ClientTotal> = Vec::new();
+    let mut totals: Vec<CurrencyTotal> = Vec::new();
+    let mut missing_rates = Vec::new();
+    for entry in entries {
+        let client = &clients[entry.client];
+        let amount = entry.hourly_rate_cents.map_or(0, |rate| amount_cents(rate, entry.billed_seconds));
+        if entry.hourly_rate_cents.is_none() {
+            missing_rates.push(entry.activity_name.clone());
+        }
+
+        let line = InvoiceLine {
+            client: client_name(client),
+            space_id: client.space.id,
+            space_name: client.space.space_name.clone(),
+            activity_name: entry.activity_name,
+            sessions: entry.sessions,
+            tracked_seconds: entry.tracked_seconds,
+            billed_seconds: entry.billed_seconds,
+            billed_hours: (entry.billed_seconds as f64 / 36.0).round() / 100.0,
+            hourly_rate_cents: entry.hourly_rate_cents,
+            amount_cents: amount,
+            hourly_rate: entry.hourly_rate_cents.map(format_cents).unwrap_or_default(),
+            amount: format_cents(amount),
+            currency: currency(client),
+        };
+
+        match client_totals.iter_mut().find(|total| total.space_id == line.space_id) {
+            Some(total) => {
+                total.tracked_seconds += line.tracked_seconds;
+                total.billed_seconds += line.billed_seconds;
+                total.amount_cents += amount;
+            }
+            None => client_totals.push(ClientTotal {
+                client: line.client.clone(),
+                space_id: line.space_id,
+                space_name: line.space_name.clone(),
+                currency: line.currency.clone(),
+                rounding: rounding.or_else(|| Rounding::from_settings(&client.settings)),
+                tracked_seconds: line.tracked_seconds,
+                billed_seconds: line.billed_seconds,
+                amount_cents: amount,
+                amount: String::new(),
+            }),
+        }
+        match totals.iter_mut().find(|total| total.currency == line.currency) {
+            Some(total) => {
+                total.billed_seconds += line.billed_seconds;
+                total.amount_cents += amount;
+            }
+            None => totals.push(CurrencyTotal {
+                currency: line.currency.clone(),
+                billed_seconds: line.billed_seconds,
+                amount_cents: amount,
+                amount: String::new(),
+            }),
+        }
+        lines.push(line);
+    }
+    for total in &mut client_totals {
+        total.amount = format_cents(total.amount_cents);
+    }
+    for total in &mut totals {
+        total.amount = format_cents(total.amount_cents);
+    }
+
+    InvoiceDraft {
+        from,
+        to,
+        time_zone,
+        generated_at: chrono::Utc::now(),
+        lines,
+        clients: client_totals,
+        totals,
+        non_billable_seconds,
+        missing_rates,
+    }
+}
+
+const CSV_COLUMNS: [&str; 9] = [
+    "client", "activity", "sessions", "tracked_hours", "billed_hours", "hourly_rate", "amount", "currency", "space",
+];
+
+fn hours(seconds: i64) -> String {
+    format!("{:.2}", seconds as f64 / 3600.0)
+}
+
+// The line items, then one total row per currency
+pub fn invoice_csv(draft: &InvoiceDraft) -> Vec<u8> {
+    let mut out = export::csv_record(CSV_COLUMNS);
+    for line in &draft.lines {
+        out.extend(export::csv_record([
+            line.client.clone(),
+            line.activity_name.clone(),
+            line.sessions.to_string(),
+            hours(line.tracked_seconds),
+            hours(line.billed_seconds),
+            line.hourly_rate.clone(),
+            line.amount.clone(),
+            line.currency.clone(),
+            line.space_name.clone(),
+        ]));
+    }
+    for total in &draft.totals {
+        out.extend(export::csv_record([
+            "Total".to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            hours(total.billed_seconds),
+            String::new(),
+            total.amount.clone(),
+            total.currency.clone(),
+            String::new(),
+        ]));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(id: i32, name: &str, currency: &str) -> Client {
+        Client {
+            space: Space { id, user_id: "u".to_string(), space_name: name.to_string() },
+            settings: SpaceSettings { space_id: id, currency: Some(currency.to_string()), ..Default::default() },
+        }
+    }
+
+    fn activity(space_id: i32, name: &str, billable: bool, hourly_rate_cents: Option<i64>) -> Activity {
+        Activity {
+            id: Uuid::new_v4(),
+            space_id,
+            user_id: "u".to_string(),
+            name: name.to_string(),
+            color: None,
+            category: None,
+            billable,
+            hourly_rate_cents,
+            archived: false,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    fn session(activity: &Activity, seconds: i64) -> TimeTrackingSession {
+        let start = chrono::Utc::now() - chrono::Duration::days(1);
+        TimeTrackingSession {
+            id: Uuid::new_v4(),
+            user_id: "u".to_string(),
+            space_id: activity.space_id,
+            activity_name: activity.name.clone(),
+            start_time: start,
+            end_time: Some(start + chrono::Duration::seconds(seconds)),
+            duration: Some(seconds),
+            activity_id: Some(activity.id),
+            notes: None,
+            session_type: crate::models::SESSION_TYPE_REGULAR.to_string(),
+            last_heartbeat_at: None,
+            auto_stopped: false,
+            auto_stop_reason: None,
+            reviewed_at: None,
+            note_id: None,
+            note_line_id: None,
+            tags: Vec::new(),
+        }
+    }
+
+    fn invoice(sessions: &[TimeTrackingSession], activities: &[Activity], clients: &[Client], rounding: Option<Rounding>) -> InvoiceDraft {
+        let day = NaiveDate::from_ymd_opt(2026, 1, 5).unwrap();
+        build_invoice(day, day, "UTC".to_string(), sessions, activities, clients, rounding)
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let quarter = |mode| Rounding { minutes: 15, mode };
+        assert_eq!(quarter(RoundingMode::Up).apply(1), 900);
+        assert_eq!(quarter(RoundingMode::Up).apply(900), 900);
+        assert_eq!(quarter(RoundingMode::Down).apply(1799), 900);
+        assert_eq!(quarter(RoundingMode::Nearest).apply(449), 0);
+        assert_eq!(quarter(RoundingMode::Nearest).apply(450), 900);
+    }
+
+    #[test]
+    fn rounding_from_settings_needs_a_sane_interval() {
+        let mut settings = SpaceSettings::default();
+        assert_eq!(Rounding::from_settings(&settings), None);
+
+        settings.rounding_minutes = Some(0);
+        assert_eq!(Rounding::from_settings(&settings), None);
+
+        settings.rounding_minutes = Some(6);
+        assert_eq!(Rounding::from_settings(&settings), Some(Rounding { minutes: 6, mode: RoundingMode::Up }));
+
+        settings.rounding_mode = Some("nearest".to_string());
+        assert_eq!(Rounding::from_settings(&settings), Some(Rounding { minutes: 6, mode: RoundingMode::Nearest }));
+    }
+
+    #[test]
+    fn amounts_round_to_whole_cents() {
+        assert_eq!(amount_cents(8500, 1800), 4250);
+        assert_eq!(amount_cents(100, 17), 0);
+        assert_eq!(amount_cents(100, 18), 1);
+        assert_eq!(format_cents(4250), "42.50");
+        assert_eq!(format_cents(-5), "-0.05");
+    }
+
+    #[test]
+    fn sessions_are_rounded_one_by_one_then_summed() {
+        let clients = [client(1, "acme", "EUR")];
+        let design = activity(1, "Design", true, Some(6000));
+        let sessions = [session(&design, 60), session(&design, 16 * 60)];
+
+        let draft = invoice(&sessions, &[design], &clients, Some(Rounding { minutes: 15, mode: RoundingMode::Up }));
+
+        assert_eq!(draft.lines.len(), 1);
+        assert_eq!(draft.lines[0].sessions, 2);
+        assert_eq!(draft.lines[0].tracked_seconds, 17 * 60);
+        assert_eq!(draft.lines[0].billed_seconds, 45 * 60);
+        assert_eq!(draft.lines[0].amount, "45.00");
+        assert_eq!(draft.clients[0].amount_cents, 4500);
+    }
+
+    #[test]
+    fn only_billable_time_is_invoiced() {
+        let clients = [client(1, "acme", "EUR")];
+        let design = activity(1, "Design", true, None);
+        let admin = activity(1, "Admin", false, Some(6000));
+        let sessions = [session(&design, 3600), session(&admin, 1200)];
+
+        let draft = invoice(&sessions, &[design, admin], &clients, None);
+
+        assert_eq!(draft.lines.len(), 1);
+        assert_eq!(draft.non_billable_seconds, 1200);
+        assert_eq!(draft.missing_rates, vec!["Design".to_string()]);
+        assert_eq!(draft.lines[0].amount_cents, 0);
+    }
+
+    #[test]
+    fn a_billable_space_uses_its_own_rate() {
+        let mut acme = client(1, "acme", "EUR");
+        acme.settings.billable = true;
+        acme.settings.hourly_rate_cents = Some(10000);
+        let admin = activity(1, "Admin", false, None);
+
+        let draft = invoice(&[session(&admin, 1800)], &[admin], &[acme], None);
+
+        assert_eq!(draft.lines[0].hourly_rate, "100.00");
+        assert_eq!(draft.lines[0].amount_cents, 5000);
+    }
+
+    #[test]
+    fn totals_are_kept_per_currency() {
+        let clients = [client(1, "acme", "EUR"), client(2, "globex", "USD"), client(3, "initech", "EUR")];
+        let activities = [
+            activity(1, "Design", true, Some(6000)),
+            activity(2, "Design", true, Some(12000)),
+            activity(3, "Support", true, Some(3000)),
+        ];
+        let sessions: Vec<TimeTrackingSession> = activities.iter().map(|activity| session(activity, 3600)).collect();
+
+        let draft = invoice(&sessions, &activities, &clients, None);
+
+        let totals: Vec<(&str, i64)> = draft.totals.iter().map(|total| (total.currency.as_str(), total.amount_cents)).collect();
+        assert_eq!(totals, vec![("EUR", 9000), ("USD", 12000)]);
+        assert_eq!(draft.clients.len(), 3);
+    }
+}
@@ -29,26 +29,82 @@ use tokio_stream::StreamExt;
 use rocket::tokio::sync::RwLock;
 use std::sync::Arc;
 use rocket::State;
+use rocket::form::Form;
+use rocket::fs::TempFile;
+use rocket::tokio::io::AsyncReadExt;
+use rocket::http::Header;
+use rocket::fairing::AdHoc;
+use rocket::response::stream::{Event, EventStream};
+use rocket::Shutdown;
 
 mod db;
 mod models;
 mod schema;
+mod storage;
+mod tracking;
+mod reports;
+mod export;
+mod import;
+mod events;
+mod budgets;
+mod pomodoro;
+mod autostop;
+mod habits;
+mod goals;
+mod review;
+mod calendar;
+mod feeds;
+mod invoices;
 
 #[launch]
 fn rocket() -> _ {
     std::env::set_var("DISABLE_PREPARED_STATEMENTS", "true");
 
-    rocket::build()
+    let rocket = rocket::build();
+    let attachment_config: storage::AttachmentConfig = rocket.figment()
+        .extract_inner("attachments")
+        .unwrap_or_default();
+    let attachment_storage = storage::Storage::from_config(attachment_config)
+        .expect("Invalid attachment storage configuration");
+    let tracking_config: tracking::TrackingConfig = rocket.figment()
+        .extract_inner("time_tracking")
+        .unwrap_or_default();
+    let event_hub = events::EventHub::default();
+    let task_notifier: Arc<dyn events::Notifier> = Arc::new(event_hub.clone());
+    let budget_check_interval = std::time::Duration::from_secs(tracking_config.budget_check_interval_secs.max(1));
+    let pomodoro_check_interval = std::time::Duration::from_secs(tracking_config.pomodoro.check_interval_secs.max(1));
+    let goal_snapshot_interval = std::time::Duration::from_secs(tracking_config.goal_snapshot_interval_secs.max(1));
+    let auto_stop_config = tracking_config.clone();
+
+    rocket
         // .attach(cors)
         .attach(db::DbConn::fairing())
+        .attach(AdHoc::on_liftoff("Time tracking tasks", move |rocket| Box::pin(async move {
+            if let Some(pool) = db::DbConn::pool(rocket).cloned() {
+                rocket::tokio::spawn(budgets::run_checker(pool.clone(), task_notifier.clone(), budget_check_interval));
+                rocket::tokio::spawn(pomodoro::run_ticker(pool.clone(), task_notifier.clone(), pomodoro_check_interval));
+                rocket::tokio::spawn(goals::run_snapshots(pool.clone(), goal_snapshot_interval));
+                rocket::tokio::spawn(autostop::run_checker(pool, auto_stop_config, task_notifier));
+            }
+        })))
         .mount("/", rocket::fs::FileServer::from("static"))
-        .mount("/", routes![index, get_spaces, create_space, view_space, silent_auth, get_other_active_spaces])
-        .mount("/notes", routes![create_sticky_note, get_sticky_notes, update_sticky_note, update_header, delete_sticky_note])
-        .mount("/track", routes![start_time_tracking, get_all_time_tracking, delete_time_tracking, complete_time_tracking])
+        .mount("/", routes![index, get_spaces, create_space, view_space, silent_auth, get_other_active_spaces, get_space_settings, update_space_settings, get_billing_settings, update_billing_settings, get_user_settings, update_user_settings])
+        .mount("/notes", routes![create_sticky_note, get_sticky_notes, update_sticky_note, update_header, delete_sticky_note, bulk_notes, upload_attachment, list_attachments, get_note_links, create_note_link, get_backlinks, delete_note_link, track_note, note_time, set_note_due_date])
+        .mount("/attachments", routes![get_attachment, get_attachment_thumbnail, delete_attachment])
+        .mount("/track", routes![get_activities, autocomplete_activities, create_activity, update_activity, merge_activity, get_budgets, create_budget, delete_budget, tracking_events, start_pomodoro, current_pomodoro, skip_pomodoro_phase, stop_pomodoro, pomodoro_stats, start_time_tracking, stop_time_tracking, pause_time_tracking, resume_time_tracking, current_time_tracking, time_tracking_report, time_tracking_tag_report, export_time_tracking, import_time_tracking, get_all_time_tracking, create_manual_time_tracking, edit_time_tracking, time_tracking_history, time_tracking_heartbeat, sessions_to_review, review_time_tracking, delete_time_tracking, complete_time_tracking])
+        .mount("/habits", routes![get_habits, create_habit, delete_habit, check_habit, uncheck_habit, get_habit_days, habit_heatmap])
+        .mount("/goals", routes![get_goals, create_goal, get_goal, delete_goal, goal_history])
+        .mount("/calendar", routes![calendar_view])
+        .mount("/feeds", routes![get_ical_feed, create_ical_feed, delete_ical_feed, ical_feed])
+        .mount("/invoices", routes![invoice_draft])
+        .mount("/reviews", routes![weekly_review, save_weekly_review, get_weekly_reviews, get_weekly_review, delete_weekly_review])
         .mount("/music", routes![stream_random_music, next_song, play_test, get_metadata])
         .attach(Template::fairing())
         .manage(Spaces::default())
         .manage(MusicState::default())
+        .manage(attachment_storage)
+        .manage(tracking_config)
+        .manage(event_hub)
         .manage(CurrentFileName(Arc::new(RwLock::new(None))))
 }
 
@@ -171,6 +227,196 @@ async fn view_space(space_name: String, jar: &CookieJar<'_>, conn: db::DbConn, s
 
 
 
+#[get("/spaces/<space_name>/settings")]
+async fn get_space_settings(
+    space_name: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::SpaceSettings>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match db::get_space_id(&conn, user_id, space_name).await {
+        Ok(id) => id,
+        Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+    };
+
+    match db::get_space_settings(&conn, space_id).await {
+        Ok(settings) => Ok(Json(settings.unwrap_or(models::SpaceSettings {
+            space_id,
+            updated_at: chrono::Utc::now(),
+            ..Default::default()
+        }))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load space settings".to_string()))),
+    }
+}
+
+#[put("/spaces/<space_name>/settings", data = "<settings>")]
+async fn update_space_settings(
+    space_name: String,
+    settings: Json<models::UpdateSpaceSettings>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::SpaceSettings>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match db::get_space_id(&conn, user_id, space_name).await {
+        Ok(id) => id,
+        Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+    };
+
+    let settings = settings.into_inner();
+
+    if let Some(tz) = &settings.time_zone {
+        if tz.parse::<chrono_tz::Tz>().is_err() {
+            return Err(status::Custom(Status::BadRequest, Json(format!("Unknown time zone: {}", tz))));
+        }
+    }
+
+    // the billing fields have their own endpoint and stay as they are
+    let current = db::get_space_settings(&conn, space_id)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load space settings".to_string())))?
+        .unwrap_or_default();
+
+    let settings = models::SpaceSettings {
+        space_id,
+        description: settings.description,
+        icon: settings.icon,
+        default_color: settings.default_color,
+        default_text_color: settings.default_text_color,
+        background: settings.background,
+        time_zone: settings.time_zone,
+        default_playlist: settings.default_playlist,
+        updated_at: chrono::Utc::now(),
+        ..current
+    };
+
+    match db::save_space_settings(&conn, settings).await {
+        Ok(saved) => Ok(Json(saved)),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to save space settings".to_string()))),
+    }
+}
+
+#[get("/spaces/<space_name>/billing")]
+async fn get_billing_settings(
+    space_name: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::BillingSettings>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match db::get_space_id(&conn, user_id, space_name).await {
+        Ok(id) => id,
+        Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+    };
+
+    match db::get_space_settings(&conn, space_id).await {
+        Ok(settings) => Ok(Json(settings.as_ref().map(models::BillingSettings::from).unwrap_or_default())),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load billing settings".to_string()))),
+    }
+}
+
+// Replaces the billing settings of the space, the rest of its settings stay as they are
+#[put("/spaces/<space_name>/billing", data = "<billing>")]
+async fn update_billing_settings(
+    space_name: String,
+    billing: Json<models::BillingSettings>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::BillingSettings>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match db::get_space_id(&conn, user_id, space_name).await {
+        Ok(id) => id,
+        Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+    };
+
+    let billing = billing.into_inner();
+    if billing.hourly_rate_cents.is_some_and(|rate| rate < 0) {
+        return Err(status::Custom(Status::BadRequest, Json("Hourly rate must not be negative".to_string())));
+    }
+    if billing.rounding_minutes.is_some_and(|minutes| !(1..=invoices::MAX_ROUNDING_MINUTES as i32).contains(&minutes)) {
+        return Err(status::Custom(
+            Status::BadRequest,
+            Json(format!("rounding_minutes must be between 1 and {}", invoices::MAX_ROUNDING_MINUTES)),
+        ));
+    }
+    if billing.rounding_mode.as_deref().is_some_and(|mode| invoices::RoundingMode::parse(mode).is_none()) {
+        return Err(status::Custom(Status::BadRequest, Json("rounding_mode must be up, down or nearest".to_string())));
+    }
+    let currency = billing.currency.map(|currency| currency.trim().to_uppercase());
+    if currency.as_deref().is_some_and(|currency| currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic())) {
+        return Err(status::Custom(Status::BadRequest, Json("currency must be a three letter code like EUR".to_string())));
+    }
+
+    let current = db::get_space_settings(&conn, space_id)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load billing settings".to_string())))?
+        .unwrap_or_default();
+    let settings = models::SpaceSettings {
+        space_id,
+        updated_at: chrono::Utc::now(),
+        client_name: billing.client_name,
+        billable: billing.billable,
+        hourly_rate_cents: billing.hourly_rate_cents,
+        currency,
+        rounding_minutes: billing.rounding_minutes,
+        rounding_mode: billing.rounding_mode,
+        ..current
+    };
+
+    match db::save_space_settings(&conn, settings).await {
+        Ok(saved) => Ok(Json(models::BillingSettings::from(&saved))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to save billing settings".to_string()))),
+    }
+}
+
+#[get("/settings")]
+async fn get_user_settings(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::UserSettings>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    match db::get_user_settings(&conn, user_id.clone()).await {
+        Ok(settings) => Ok(Json(settings.unwrap_or(models::UserSettings {
+            user_id,
+            updated_at: chrono::Utc::now(),
+            ..Default::default()
+        }))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load settings".to_string()))),
+    }
+}
+
+#[put("/settings", data = "<settings>")]
+async fn update_user_settings(
+    settings: Json<models::UpdateUserSettings>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::UserSettings>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    // an empty time zone clears it, the space time zone applies again
+    let time_zone = settings.into_inner().time_zone.filter(|tz| !tz.trim().is_empty());
+    if let Some(tz) = &time_zone {
+        if tz.parse::<chrono_tz::Tz>().is_err() {
+            return Err(status::Custom(Status::BadRequest, Json(format!("Unknown time zone: {}", tz))));
+        }
+    }
+
+    let settings = models::UserSettings {
+        user_id,
+        time_zone,
+        updated_at: chrono::Utc::now(),
+    };
+
+    match db::save_user_settings(&conn, settings).await {
+        Ok(saved) => Ok(Json(saved)),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to save settings".to_string()))),
+    }
+}
+
+
 #[get("/others")]
 async fn get_other_active_spaces(jar: &CookieJar<'_>, spaces: &rocket::State<Spaces>) -> Json<Vec<String>> {
     let user_id = get_user_id(jar);
@@ -209,34 +455,32 @@ async fn create_sticky_note(
         }
     };
 
-    let sticky_lines: Option<Vec<StickyLine>> = note_data.lines.as_ref().map(|lines| {
-        lines.iter().map(|line| StickyLine::from_string(line)).collect()
-    });
+    // colors left out of the request come from the space settings
+    let mut note_data = note_data.into_inner();
+    let space_settings = db::get_space_settings(&conn, space_id).await.ok().flatten().unwrap_or_default();
+    note_data.color = note_data.color.or(space_settings.default_color);
+    note_data.text_color = note_data.text_color.or(space_settings.default_text_color);
 
-    let new_note = db::create_sticky_note(
-        &conn,
-        &user_id,
-        &note_data.title,
-        space_id,
-        &note_data.color,
-        &note_data.text_color,
-        note_data.tags.clone(),
-        sticky_lines,
-    )
-    .await
-    .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to create sticky note".to_string())))?;
+    let new_note = db::create_sticky_note(&conn, &user_id, space_id, note_data)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to create sticky note".to_string())))?;
+
+    if let Err(e) = db::sync_wiki_links(&conn, new_note.id).await {
+        eprintln!("Error syncing note links: {:?}", e);
+    }
 
     Ok(Json(new_note))
 }
 
 
 
-#[get("/notes?<space_name>")]
+#[get("/notes?<space_name>&<include_archived>")]
 async fn get_sticky_notes(
     jar: &CookieJar<'_>,
     conn: db::DbConn,
     space_name: Option<String>,
-) -> Json<Vec<StickyNote>> {
+    include_archived: Option<bool>,
+) -> Json<Vec<models::StickyNoteWithProgress>> {
     let user_id = get_user_id(jar);
 
     let space_name = match space_name {
@@ -251,14 +495,25 @@ async fn get_sticky_notes(
         }
     };
 
-    let notes = match db::get_sticky_notes(&conn, user_id, space_id).await {
+    let notes = match db::get_sticky_notes(&conn, user_id.clone(), space_id, include_archived.unwrap_or(false)).await {
         Ok(notes) => notes,
         Err(_) => {
             Vec::new()
         }
     };
 
-    Json(notes)
+    let note_ids = notes.iter().map(|note| note.id).collect();
+    let times = db::get_note_times(&conn, user_id, note_ids, chrono::Utc::now()).await.unwrap_or_default();
+
+    Json(
+        notes
+            .into_iter()
+            .map(|note| {
+                let tracked_seconds = times.iter().find(|time| time.note_id == note.id).map_or(0, |time| time.total_seconds);
+                models::StickyNoteWithProgress { tracked_seconds, ..models::StickyNoteWithProgress::from(note) }
+            })
+            .collect(),
+    )
 }
 
 #[post("/header?<space_name>", data = "<note>")]
@@ -288,6 +543,7 @@ async fn update_header(
         note.title.clone(),
     ).await {
         Ok(updated_note) => Ok(Json(updated_note)),
+        Err(diesel::result::Error::NotFound) => Err(status::Custom(Status::NotFound, Json("Note not found".to_string()))),
         Err(e) => Err(status::Custom(Status::InternalServerError, Json(format!("Error updating sticky note header: {:?}", e)))),
     }
 }
@@ -334,7 +590,13 @@ async fn update_sticky_note(
         note.tags.clone(),
         sticky_lines,
     ).await {
-        Ok(updated_note) => Ok(Json(updated_note)),
+        Ok(updated_note) => {
+            if let Err(e) = db::sync_wiki_links(&conn, updated_note.id).await {
+                eprintln!("Error syncing note links: {:?}", e);
+            }
+            Ok(Json(updated_note))
+        }
+        Err(diesel::result::Error::NotFound) => Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string()))),
         Err(e) => {
             // Create an error message JSON response
             let error_message = Json(format!("Error updating sticky note: {:?}", e));
@@ -346,208 +608,2788 @@ async fn update_sticky_note(
 
 
 #[delete("/<note_id>")]
-async fn delete_sticky_note(note_id: String, conn: db::DbConn) -> Json<String> {
-    let note_uuid = Uuid::parse_str(&note_id).expect("Invalid UUID");
-    let deleted_rows = db::delete_sticky_note(&conn, note_uuid)
-        .await
-        .expect("Failed to delete sticky note");
+async fn delete_sticky_note(
+    note_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    storage: &State<storage::Storage>,
+) -> Result<Json<String>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
 
-    if deleted_rows > 0 {
-        Json("Sticky note deleted successfully".to_string())
-    } else {
-        Json("Sticky note not found".to_string())
+    let note_id = parse_note_id(&note_id)?;
+
+    // attachment rows go with the note, their files are removed once the delete went through
+    match db::delete_sticky_note(&conn, user_id, note_id).await {
+        Ok(Some(attachments)) => {
+            remove_attachment_files(storage, &attachments).await;
+            Ok(Json("Sticky note deleted successfully".to_string()))
+        }
+        Ok(None) => Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string()))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to delete sticky note".to_string()))),
     }
 }
 
 
-// time tracking
-
-#[derive(Debug, Deserialize)]
-pub struct RequestQueryTwo {
-    pub session_id: String,
-}
-
-#[post("/start?<space_name>", data = "<new_session>")]
-async fn start_time_tracking(
+#[post("/bulk?<space_name>", data = "<request>")]
+async fn bulk_notes(
     jar: &CookieJar<'_>,
     conn: db::DbConn,
-    new_session: Json<models::NewTimeTrackingSession>,
+    storage: &State<storage::Storage>,
+    request: Json<models::BulkRequest>,
     space_name: Option<String>,
-) -> Result<Json<models::TimeTrackingSession>, status::Custom<Json<String>>> {
-
+) -> Result<Json<Vec<models::BulkItemResult>>, status::Custom<Json<String>>> {
     let user_id = get_user_id(jar);
 
-    // Ensure space_name is provided
     let space_name = match space_name {
         Some(name) => name,
-        
         None => return Err(status::Custom(Status::BadRequest, Json("Missing space_name".to_string()))),
     };
 
-    println!("i got to check space-name: {}", space_name);
     let space_id = match db::get_space_id(&conn, user_id.clone(), space_name).await {
         Ok(id) => id,
-        Err(_) => {
-            return Err(status::Custom(Status::NotFound, Json("Space not found".to_string())));
-        }
+        Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+    };
+
+    let request = request.into_inner();
+
+    let target_space_id = match &request.action {
+        models::BulkAction::Move { space_name } => match db::get_space_id(&conn, user_id.clone(), space_name.clone()).await {
+            Ok(id) => Some(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Target space not found".to_string()))),
+        },
+        _ => None,
     };
-    println!("i got to check space-id: {}", space_id);
 
-    let session = db::create_time_tracking_session(
+    let (results, removed_attachments) = db::bulk_update_notes(
         &conn,
         user_id,
         space_id,
-        new_session.activity_name.clone(),
-        new_session.start_time,
-    ).await.expect("Failed to create time tracking session");
+        request.note_ids,
+        request.action,
+        target_space_id,
+    )
+    .await
+    .map_err(|e| status::Custom(Status::InternalServerError, Json(format!("Bulk operation failed: {:?}", e))))?;
 
-    Ok(Json(session))
-}
+    remove_attachment_files(storage, &removed_attachments).await;
 
-#[post("/complete?<session_id>&<end_time>")]
-async fn complete_time_tracking(
-    // jar: &CookieJar<'_>,
-    conn: db::DbConn,
-    session_id: String,
-    end_time: i64,
-) -> Result<Json<models::TimeTrackingSession>, Status> {
+    Ok(Json(results))
+}
 
-    let session_id = Uuid::parse_str(&session_id).map_err(|_| Status::BadRequest)?;
 
-    let session = db::complete_time_tracking_session(
-        &conn,
-        session_id,
-        end_time,
-    ).await.map_err(|e| {
-        error!("Failed to complete time tracking session: {:?}", e);
-        Status::InternalServerError
-    })?;
+// note links
 
-    Ok(Json(session))
+fn parse_note_id(note_id: &str) -> Result<Uuid, status::Custom<Json<String>>> {
+    Uuid::parse_str(note_id).map_err(|_| status::Custom(Status::BadRequest, Json("Invalid note id".to_string())))
 }
 
-#[get("/time_tracking?<space_name>")]
-async fn get_all_time_tracking(
+#[get("/<note_id>/links")]
+async fn get_note_links(
+    note_id: String,
     jar: &CookieJar<'_>,
     conn: db::DbConn,
-    space_name: Option<String>,
-) -> Json<Vec<models::TimeTrackingSession>> {
+) -> Result<Json<Vec<models::NoteLinkView>>, status::Custom<Json<String>>> {
     let user_id = get_user_id(jar);
+    let note_id = parse_note_id(&note_id)?;
 
-    let space_name = match space_name {
-        Some(name) => name,
-        None => return Json(Vec::new()), // Return an empty list if space_name is missing
-    };
+    if db::get_sticky_note(&conn, user_id, note_id).await.is_err() {
+        return Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string())));
+    }
 
-    let space_id = match db::get_space_id(&conn, user_id.clone(), space_name).await {
-        Ok(id) => id,
-        Err(_) => {
-            return Json(Vec::new());
-        }
-    };
+    match db::get_note_links(&conn, note_id).await {
+        Ok(links) => Ok(Json(links.into_iter().map(models::NoteLinkView::from).collect())),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load note links".to_string()))),
+    }
+}
 
-    let sessions = match db::get_all_time_tracking_sessions(&conn, user_id, space_id).await {
-        Ok(sessions) => sessions,
-        Err(_) => {
-            Vec::new()
-        }
-    };
+#[post("/<note_id>/links", data = "<link>")]
+async fn create_note_link(
+    note_id: String,
+    link: Json<models::NewNoteLink>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::NoteLinkView>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let note_id = parse_note_id(&note_id)?;
 
-    Json(sessions)
+    if link.target_note_id == note_id {
+        return Err(status::Custom(Status::BadRequest, Json("A note cannot link to itself".to_string())));
+    }
+
+    if db::get_sticky_note(&conn, user_id.clone(), note_id).await.is_err() {
+        return Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string())));
+    }
+
+    match db::create_note_link(&conn, user_id, note_id, link.target_note_id).await {
+        Ok(link) => Ok(Json(models::NoteLinkView::from(link))),
+        Err(diesel::result::Error::NotFound) => Err(status::Custom(Status::NotFound, Json("Target note not found".to_string()))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to create note link".to_string()))),
+    }
 }
 
+#[get("/<note_id>/backlinks")]
+async fn get_backlinks(
+    note_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::Backlink>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let note_id = parse_note_id(&note_id)?;
 
+    if db::get_sticky_note(&conn, user_id, note_id).await.is_err() {
+        return Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string())));
+    }
 
+    match db::get_backlinks(&conn, note_id).await {
+        Ok(backlinks) => Ok(Json(
+            backlinks
+                .into_iter()
+                .map(|(link, source_title)| models::Backlink { link, source_title })
+                .collect(),
+        )),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load backlinks".to_string()))),
+    }
+}
 
-#[delete("/delete?<session_id>")]
-async fn delete_time_tracking(
-    // jar: &CookieJar<'_>,
+#[delete("/links/<link_id>")]
+async fn delete_note_link(
+    link_id: String,
+    jar: &CookieJar<'_>,
     conn: db::DbConn,
-    session_id: String,
 ) -> Result<Status, Status> {
-    // Parse the session_id from a String to a Uuid
-    let session_id = match Uuid::parse_str(&session_id) {
-        Ok(uuid) => uuid,
-        Err(_) => return Err(Status::BadRequest),
-    };
-    
-    match db::delete_time_tracking_session(&conn, session_id).await {
+    let user_id = get_user_id(jar);
+    let link_id = Uuid::parse_str(&link_id).map_err(|_| Status::BadRequest)?;
+
+    match db::delete_note_link(&conn, user_id, link_id).await {
+        Ok(0) => Err(Status::NotFound),
         Ok(_) => Ok(Status::Ok),
         Err(_) => Err(Status::InternalServerError),
     }
 }
 
-#[derive(Default)]
-struct MusicState {
-    playlist: Mutex<Vec<String>>, // Store file names or paths
-    current_song: Mutex<Option<String>>, // Current song being played
-}
 
-struct CurrentFileName(Arc<RwLock<Option<String>>>);
+// time tracked against notes
+
+// The note (and line) a session may be linked to, it has to be the user's and live in the session's space
+async fn check_note_link(
+    conn: &db::DbConn,
+    user_id: &str,
+    space_id: i32,
+    note_id: Uuid,
+    line_id: Option<String>,
+    line: Option<i32>,
+) -> Result<(models::StickyNote, Option<String>), status::Custom<Json<String>>> {
+    let note = match db::get_sticky_note(conn, user_id.to_string(), note_id).await {
+        Ok(note) if note.space_id == space_id => note,
+        _ => return Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string()))),
+    };
+    let line_id = note_line_id(&note, line_id, line)?;
 
-#[get("/play")]
-async fn stream_random_music(state: &State<CurrentFileName>) -> Result<(ContentType, ByteStream<impl futures::Stream<Item = Vec<u8>>>), Status> {
-    // List all files in the 'music' directory
-    let music_dir = Path::new("music/");
-    println!("Checking if music directory exists: {:?}", music_dir.display());
+    Ok((note, line_id))
+}
 
-    let read_dir = match rocket::tokio::fs::read_dir(music_dir).await {
-        Ok(read_dir) => {
-            println!("Successfully read the music directory.");
-            read_dir
-        },
-        Err(e) => {
-            println!("Error reading music directory: {:?}", e);
-            return Err(Status::InternalServerError);
-        },
+// The id of the line a session is tracked against, given by id or (older clients) by index
+fn note_line_id(note: &models::StickyNote, line_id: Option<String>, line: Option<i32>) -> Result<Option<String>, status::Custom<Json<String>>> {
+    let lines = note.sticky_lines();
+    let found = match (line_id, line) {
+        (Some(line_id), _) => lines.into_iter().find(|note_line| !note_line.id.is_empty() && note_line.id == line_id),
+        (None, Some(line)) => usize::try_from(line).ok().and_then(|index| lines.into_iter().nth(index)),
+        (None, None) => return Ok(None),
     };
+    match found {
+        Some(line) if !line.id.is_empty() => Ok(Some(line.id)),
+        _ => Err(status::Custom(Status::BadRequest, Json("The note has no such line".to_string()))),
+    }
+}
 
-    // Convert ReadDir to a stream and collect entries
-    // Convert ReadDir to a stream and collect entries
-    let entries = ReadDirStream::new(read_dir)
-        .filter_map(|entry| match entry {
-            Ok(entry) => {
-                println!("Found entry: {:?}", entry.path().display());
-                Some(entry)
-            },
-            Err(e) => {
-                println!("Error reading directory entry: {:?}", e);
-                None
-            },
-        })
-        .collect::<Vec<_>>()
-        .await;
+// Starts a timer for the note or one of its lines
+#[post("/<note_id>/track", data = "<start>")]
+async fn track_note(
+    note_id: String,
+    start: Option<Json<models::NoteTimerStart>>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    tracking_config: &State<tracking::TrackingConfig>,
+    hub: &State<events::EventHub>,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let note_id = parse_note_id(&note_id)?;
+    let start = start.map(|start| start.into_inner()).unwrap_or_default();
 
-    // Filter entries to get file paths
-    let files: Vec<PathBuf> = entries
-        .iter()
-        .filter_map(|entry| {
-            let path = entry.path();
-            let is_file = rocket::tokio::task::block_in_place(|| {
-                let metadata = std::fs::metadata(&path);
-                match metadata {
-                    Ok(metadata) => metadata.is_file(),
-                    Err(e) => {
-                        println!("Error getting metadata for file {}: {:?}", path.display(), e);
-                        false
-                    },
-                }
-            });
-            if is_file {
-                println!("File found: {:?}", path.display());
-                Some(path)
-            } else {
-                None
-            }
-        })
-        .collect();
+    let note = db::get_sticky_note(&conn, user_id.clone(), note_id)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Sticky note not found".to_string())))?;
+    let line_id = note_line_id(&note, start.line_id, start.line)?;
 
-    if files.is_empty() {
-        println!("No files found in the music directory.");
-        return Err(Status::NotFound);
-    }
+    let start_time = match start.manual_start_time {
+        Some(manual) => {
+            check_manual_time(manual, "Start time")?;
+            manual
+        }
+        None => chrono::Utc::now(),
+    };
 
-    // Randomly select a file
+    let activity_name = start
+        .activity_name
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| note.title.clone());
+    let activity = session_activity_choice(&conn, &user_id, note.space_id, start.activity_id, &activity_name).await?;
+
+    let details = models::SessionDetails {
+        notes: clean_notes(start.notes),
+        tags: clean_tags(start.tags)?,
+        note_id: Some(note.id),
+        note_line_id: line_id,
+    };
+
+    let stop_running = tracking_config.active_timer_policy == tracking::ActiveTimerPolicy::AutoStop;
+    let started = db::create_time_tracking_session(&conn, user_id.clone(), note.space_id, activity, start_time, details, stop_running).await;
+    let session = timer_started(hub, &user_id, started, "Failed to start time tracking").await?;
+
+    with_session_intervals(&conn, session).await.map(Json)
+}
+
+// Time spent on the note, in total and per line
+#[get("/<note_id>/time")]
+async fn note_time(
+    note_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::NoteTime>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let note_id = parse_note_id(&note_id)?;
+
+    let note = db::get_sticky_note(&conn, user_id.clone(), note_id)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Sticky note not found".to_string())))?;
+
+    let mut time = db::get_note_times(&conn, user_id, vec![note_id], chrono::Utc::now())
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load the tracked time".to_string())))?
+        .pop()
+        .unwrap_or(models::NoteTime { note_id, ..Default::default() });
+
+    // lines are listed in the note's order, the ones that are gone last
+    let lines = note.sticky_lines();
+    for line_time in time.lines.iter_mut() {
+        let index = lines.iter().position(|line| line.id == line_time.line_id);
+        line_time.line = index.and_then(|index| i32::try_from(index).ok());
+        line_time.text = index.map(|index| lines[index].text.clone());
+    }
+    time.lines.sort_by_key(|line| (line.line.is_none(), line.line));
+
+    Ok(Json(time))
+}
+
+#[put("/<note_id>/due", data = "<due>")]
+async fn set_note_due_date(
+    note_id: String,
+    due: Json<models::NoteDueDate>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<StickyNote>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let note_id = Uuid::parse_str(&note_id).map_err(|_| status::Custom(Status::BadRequest, Json("Invalid note id".to_string())))?;
+
+    db::set_note_due_date(&conn, user_id, note_id, due.into_inner().due_date)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::NotFound, Json("Note not found".to_string())))
+}
+
+
+// attachments
+
+#[derive(FromForm)]
+struct AttachmentUpload<'r> {
+    file: TempFile<'r>,
+}
+
+async fn remove_attachment_files(storage: &storage::Storage, attachments: &[models::NoteAttachment]) {
+    for attachment in attachments {
+        let keys = std::iter::once(&attachment.storage_key).chain(attachment.thumbnail_key.as_ref());
+        for key in keys {
+            if let Err(e) = storage.backend.delete(key).await {
+                eprintln!("Error removing attachment file {}: {:?}", key, e);
+            }
+        }
+    }
+}
+
+#[post("/<note_id>/attachments", data = "<upload>")]
+async fn upload_attachment(
+    note_id: String,
+    upload: Form<AttachmentUpload<'_>>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    storage: &State<storage::Storage>,
+) -> Result<Json<models::NoteAttachment>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let note_id = parse_note_id(&note_id)?;
+
+    if db::get_sticky_note(&conn, user_id.clone(), note_id).await.is_err() {
+        return Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string())));
+    }
+
+    let file = &upload.file;
+
+    if file.len() == 0 {
+        return Err(status::Custom(Status::BadRequest, Json("File is empty".to_string())));
+    }
+    if file.len() > storage.config.max_bytes {
+        return Err(status::Custom(Status::PayloadTooLarge, Json(format!("File is larger than {} bytes", storage.config.max_bytes))));
+    }
+
+    let mut bytes = Vec::with_capacity(file.len() as usize);
+    file.open().await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to read upload".to_string())))?
+        .read_to_end(&mut bytes).await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to read upload".to_string())))?;
+
+    // the declared type is only trusted for telling text formats apart
+    let declared_type = file.content_type()
+        .map(|ct| format!("{}/{}", ct.top(), ct.sub()).to_lowercase())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let content_type = storage::sniff_content_type(&bytes, &declared_type);
+    if !storage.config.is_allowed(&content_type) {
+        return Err(status::Custom(Status::UnsupportedMediaType, Json(format!("File type {} is not allowed", content_type))));
+    }
+
+    // only keep printable characters, the name ends up in response headers
+    let file_name: String = file.raw_name()
+        .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str().to_string())
+        .unwrap_or_else(|| "attachment".to_string())
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"' && *c != '\\')
+        .collect();
+
+    let attachment_id = Uuid::new_v4();
+    let storage_key = format!("{}/{}", note_id, attachment_id);
+
+    let thumbnail = if content_type.starts_with("image/") {
+        let source = bytes.clone();
+        let size = storage.config.thumbnail_size;
+        rocket::tokio::task::spawn_blocking(move || storage::make_thumbnail(&source, size))
+            .await
+            .unwrap_or(None)
+    } else {
+        None
+    };
+
+    let size_bytes = bytes.len() as i64;
+    storage.backend.put(&storage_key, bytes).await.map_err(|e| {
+        eprintln!("Error storing attachment: {:?}", e);
+        status::Custom(Status::InternalServerError, Json("Failed to store attachment".to_string()))
+    })?;
+
+    let thumbnail_key = match thumbnail {
+        Some(thumbnail) => {
+            let key = format!("{}-thumb.png", storage_key);
+            match storage.backend.put(&key, thumbnail).await {
+                Ok(_) => Some(key),
+                Err(e) => {
+                    eprintln!("Error storing thumbnail: {:?}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    let attachment = models::NoteAttachment {
+        id: attachment_id,
+        note_id,
+        user_id,
+        file_name: if file_name.is_empty() { "attachment".to_string() } else { file_name },
+        content_type,
+        size_bytes,
+        storage_key,
+        thumbnail_key,
+        created_at: chrono::Utc::now(),
+    };
+
+    match db::create_note_attachment(&conn, attachment.clone()).await {
+        Ok(attachment) => Ok(Json(attachment)),
+        Err(_) => {
+            // nothing points at the stored files without the row
+            remove_attachment_files(storage, &[attachment]).await;
+            Err(status::Custom(Status::InternalServerError, Json("Failed to save attachment".to_string())))
+        }
+    }
+}
+
+#[get("/<note_id>/attachments")]
+async fn list_attachments(
+    note_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::NoteAttachment>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let note_id = parse_note_id(&note_id)?;
+
+    if db::get_sticky_note(&conn, user_id, note_id).await.is_err() {
+        return Err(status::Custom(Status::NotFound, Json("Sticky note not found".to_string())));
+    }
+
+    match db::get_note_attachments(&conn, note_id).await {
+        Ok(attachments) => Ok(Json(attachments)),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load attachments".to_string()))),
+    }
+}
+
+async fn find_attachment(
+    attachment_id: &str,
+    jar: &CookieJar<'_>,
+    conn: &db::DbConn,
+) -> Result<models::NoteAttachment, Status> {
+    let user_id = get_user_id(jar);
+    let attachment_id = Uuid::parse_str(attachment_id).map_err(|_| Status::BadRequest)?;
+
+    db::get_note_attachment(conn, user_id, attachment_id)
+        .await
+        .map_err(|_| Status::NotFound)
+}
+
+#[get("/<attachment_id>")]
+async fn get_attachment(
+    attachment_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    storage: &State<storage::Storage>,
+) -> Result<(ContentType, Vec<u8>), Status> {
+    let attachment = find_attachment(&attachment_id, jar, &conn).await?;
+
+    let bytes = storage.backend.get(&attachment.storage_key).await.map_err(|e| {
+        eprintln!("Error reading attachment {}: {:?}", attachment.id, e);
+        Status::NotFound
+    })?;
+
+    let content_type = ContentType::parse_flexible(&attachment.content_type).unwrap_or(ContentType::Binary);
+    Ok((content_type, bytes))
+}
+
+#[get("/<attachment_id>/thumbnail")]
+async fn get_attachment_thumbnail(
+    attachment_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    storage: &State<storage::Storage>,
+) -> Result<(ContentType, Vec<u8>), Status> {
+    let attachment = find_attachment(&attachment_id, jar, &conn).await?;
+    let thumbnail_key = attachment.thumbnail_key.ok_or(Status::NotFound)?;
+
+    let bytes = storage.backend.get(&thumbnail_key).await.map_err(|_| Status::NotFound)?;
+    Ok((ContentType::PNG, bytes))
+}
+
+#[delete("/<attachment_id>")]
+async fn delete_attachment(
+    attachment_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    storage: &State<storage::Storage>,
+) -> Result<Status, Status> {
+    let attachment = find_attachment(&attachment_id, jar, &conn).await?;
+
+    // the files go only once the row is gone, a failed delete leaves the attachment whole
+    match db::delete_note_attachment(&conn, attachment.id).await {
+        Ok(0) => Err(Status::NotFound),
+        Ok(_) => {
+            remove_attachment_files(storage, std::slice::from_ref(&attachment)).await;
+            Ok(Status::Ok)
+        }
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+
+// activities
+
+fn is_unique_violation(e: &diesel::result::Error) -> bool {
+    matches!(e, diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _))
+}
+
+fn check_activity_fields(name: Option<&str>, hourly_rate_cents: Option<i64>) -> Result<(), status::Custom<Json<String>>> {
+    if name.is_some_and(|name| name.trim().is_empty()) {
+        return Err(status::Custom(Status::BadRequest, Json("Activity name must not be empty".to_string())));
+    }
+    if hourly_rate_cents.is_some_and(|rate| rate < 0) {
+        return Err(status::Custom(Status::BadRequest, Json("Hourly rate must not be negative".to_string())));
+    }
+    Ok(())
+}
+
+#[get("/activities?<space_name>&<include_archived>")]
+async fn get_activities(
+    space_name: Option<String>,
+    include_archived: Option<bool>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::Activity>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    match db::get_activities(&conn, user_id, space_id, include_archived.unwrap_or(false)).await {
+        Ok(activities) => Ok(Json(activities)),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load activities".to_string()))),
+    }
+}
+
+// Suggestions for the start timer input, `q` matches anywhere in the name
+#[get("/activities/autocomplete?<space_name>&<q>&<limit>")]
+async fn autocomplete_activities(
+    space_name: Option<String>,
+    q: Option<String>,
+    limit: Option<i64>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::Activity>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    let limit = limit.unwrap_or(10).clamp(1, 50);
+    match db::autocomplete_activities(&conn, user_id, space_id, q.unwrap_or_default(), limit).await {
+        Ok(activities) => Ok(Json(activities)),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load activities".to_string()))),
+    }
+}
+
+#[post("/activities?<space_name>", data = "<new_activity>")]
+async fn create_activity(
+    space_name: Option<String>,
+    new_activity: Json<models::NewActivity>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::Activity>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    let new_activity = new_activity.into_inner();
+    check_activity_fields(Some(&new_activity.name), new_activity.hourly_rate_cents)?;
+
+    let activity = models::Activity {
+        id: Uuid::new_v4(),
+        space_id,
+        user_id,
+        name: new_activity.name.trim().to_string(),
+        color: new_activity.color,
+        category: new_activity.category,
+        billable: new_activity.billable,
+        hourly_rate_cents: new_activity.hourly_rate_cents,
+        archived: false,
+        created_at: chrono::Utc::now(),
+    };
+
+    match db::create_activity(&conn, activity).await {
+        Ok(activity) => Ok(Json(activity)),
+        Err(e) if is_unique_violation(&e) => {
+            Err(status::Custom(Status::Conflict, Json("An activity with that name already exists".to_string())))
+        }
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to create activity".to_string()))),
+    }
+}
+
+// Renaming also renames every session of the activity
+#[put("/activities/<activity_id>", data = "<changes>")]
+async fn update_activity(
+    activity_id: String,
+    changes: Json<models::UpdateActivity>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::Activity>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let activity_id = Uuid::parse_str(&activity_id)
+        .map_err(|_| status::Custom(Status::BadRequest, Json("Invalid activity id".to_string())))?;
+
+    let mut changes = changes.into_inner();
+    check_activity_fields(changes.name.as_deref(), changes.hourly_rate_cents)?;
+    changes.name = changes.name.map(|name| name.trim().to_string());
+
+    match db::update_activity(&conn, user_id, activity_id, changes).await {
+        Ok(activity) => Ok(Json(activity)),
+        Err(diesel::result::Error::NotFound) => Err(status::Custom(Status::NotFound, Json("Activity not found".to_string()))),
+        Err(e) if is_unique_violation(&e) => {
+            Err(status::Custom(Status::Conflict, Json("An activity with that name already exists".to_string())))
+        }
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to update activity".to_string()))),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct MergeResult {
+    activity: models::Activity,
+    moved_sessions: usize,
+}
+
+// Folds `activity_id` into `into`, both have to be in the same space
+#[post("/activities/<activity_id>/merge?<into>")]
+async fn merge_activity(
+    activity_id: String,
+    into: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<MergeResult>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let parse = |value: &str| {
+        Uuid::parse_str(value).map_err(|_| status::Custom(Status::BadRequest, Json("Invalid activity id".to_string())))
+    };
+
+    let source_id = parse(&activity_id)?;
+    let target_id = parse(&into.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing into".to_string())))?)?;
+    if source_id == target_id {
+        return Err(status::Custom(Status::BadRequest, Json("Cannot merge an activity into itself".to_string())));
+    }
+
+    match db::merge_activities(&conn, user_id, source_id, target_id).await {
+        Ok((activity, moved_sessions)) => Ok(Json(MergeResult { activity, moved_sessions })),
+        Err(diesel::result::Error::NotFound) => Err(status::Custom(Status::NotFound, Json("Activity not found".to_string()))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to merge activities".to_string()))),
+    }
+}
+
+
+// budgets
+
+// Every budget of the space with how much was tracked in its current period
+#[get("/budgets?<space_name>")]
+async fn get_budgets(
+    space_name: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<budgets::BudgetStatus>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+    let failed = |_| status::Custom(Status::InternalServerError, Json("Failed to load budgets".to_string()));
+
+    let space_budgets = db::get_budgets(&conn, Some((user_id.clone(), space_id))).await.map_err(failed)?;
+    let notifications = db::get_budget_notifications(&conn, space_budgets.iter().map(|(budget, _)| budget.id).collect())
+        .await
+        .map_err(failed)?;
+    let time_zone = budgets::user_time_zone(&conn, &user_id, space_id).await;
+
+    let mut statuses = Vec::new();
+    for (budget, activity) in space_budgets {
+        statuses.push(budgets::budget_status(&conn, budget, &activity, time_zone, &notifications).await.map_err(failed)?);
+    }
+    Ok(Json(statuses))
+}
+
+#[post("/budgets?<space_name>", data = "<new_budget>")]
+async fn create_budget(
+    space_name: Option<String>,
+    new_budget: Json<models::NewActivityBudget>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::ActivityBudget>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    let new_budget = new_budget.into_inner();
+    if reports::Granularity::parse(&new_budget.period).is_none() {
+        return Err(status::Custom(Status::BadRequest, Json("period must be day, week or month".to_string())));
+    }
+    if budgets::BudgetKind::parse(&new_budget.kind).is_none() {
+        return Err(status::Custom(Status::BadRequest, Json("kind must be max or min".to_string())));
+    }
+    if new_budget.limit_seconds <= 0 {
+        return Err(status::Custom(Status::BadRequest, Json("limit_seconds must be positive".to_string())));
+    }
+    match db::get_activity(&conn, user_id.clone(), new_budget.activity_id).await {
+        Ok(activity) if activity.space_id == space_id => {}
+        _ => return Err(status::Custom(Status::NotFound, Json("Activity not found".to_string()))),
+    }
+
+    let budget = models::ActivityBudget {
+        id: Uuid::new_v4(),
+        activity_id: new_budget.activity_id,
+        user_id,
+        period: new_budget.period,
+        kind: new_budget.kind,
+        limit_seconds: new_budget.limit_seconds,
+        created_at: chrono::Utc::now(),
+    };
+
+    match db::create_budget(&conn, budget).await {
+        Ok(budget) => Ok(Json(budget)),
+        Err(e) if is_unique_violation(&e) => {
+            Err(status::Custom(Status::Conflict, Json("The activity already has this budget".to_string())))
+        }
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to create budget".to_string()))),
+    }
+}
+
+#[delete("/budgets/<budget_id>")]
+async fn delete_budget(
+    budget_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Status, Status> {
+    let user_id = get_user_id(jar);
+    let budget_id = Uuid::parse_str(&budget_id).map_err(|_| Status::BadRequest)?;
+
+    match db::delete_budget(&conn, user_id, budget_id).await {
+        Ok(0) => Err(Status::NotFound),
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// Server-sent events for the signed in user, like budget alerts
+#[get("/events")]
+fn tracking_events(jar: &CookieJar<'_>, hub: &State<events::EventHub>, mut shutdown: Shutdown) -> EventStream![] {
+    use rocket::tokio::sync::broadcast::error::RecvError;
+
+    let user_id = get_user_id(jar);
+    let mut receiver = hub.subscribe();
+
+    EventStream! {
+        loop {
+            let message = rocket::tokio::select! {
+                message = receiver.recv() => message,
+                _ = &mut shutdown => break,
+            };
+            let user_event = match message {
+                Ok(user_event) => user_event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            if user_event.user_id != user_id {
+                continue;
+            }
+            yield Event::json(&user_event.event).event(user_event.event.name());
+        }
+    }
+}
+
+
+// time tracking
+
+#[derive(Debug, Deserialize)]
+pub struct RequestQueryTwo {
+    pub session_id: String,
+}
+
+// Checks a client supplied time that was sent as an explicit manual override
+fn check_manual_time(time: chrono::DateTime<chrono::Utc>, what: &str) -> Result<(), status::Custom<Json<String>>> {
+    if time > chrono::Utc::now() {
+        return Err(status::Custom(Status::BadRequest, Json(format!("{} cannot be in the future", what))));
+    }
+    Ok(())
+}
+
+// The activity a session is for, an id is checked now while a name is only looked up
+// (or created) when the session is saved
+async fn session_activity_choice(
+    conn: &db::DbConn,
+    user_id: &str,
+    space_id: i32,
+    activity_id: Option<Uuid>,
+    activity_name: &str,
+) -> Result<models::ActivityChoice, status::Custom<Json<String>>> {
+    match activity_id {
+        Some(activity_id) => match db::get_activity(conn, user_id.to_string(), activity_id).await {
+            Ok(activity) if activity.space_id == space_id => Ok(models::ActivityChoice::Existing(activity)),
+            _ => Err(status::Custom(Status::NotFound, Json("Activity not found".to_string()))),
+        },
+        None if activity_name.trim().is_empty() => {
+            Err(status::Custom(Status::BadRequest, Json("Missing activity_name".to_string())))
+        }
+        None => Ok(models::ActivityChoice::Named(activity_name.to_string())),
+    }
+}
+
+// The activity a session is for, picked by id or looked up (or created) by name
+async fn resolve_session_activity(
+    conn: &db::DbConn,
+    user_id: &str,
+    space_id: i32,
+    activity_id: Option<Uuid>,
+    activity_name: &str,
+) -> Result<models::Activity, status::Custom<Json<String>>> {
+    match activity_id {
+        Some(activity_id) => match db::get_activity(conn, user_id.to_string(), activity_id).await {
+            Ok(activity) if activity.space_id == space_id => Ok(activity),
+            _ => Err(status::Custom(Status::NotFound, Json("Activity not found".to_string()))),
+        },
+        None if activity_name.trim().is_empty() => {
+            Err(status::Custom(Status::BadRequest, Json("Missing activity_name".to_string())))
+        }
+        None => db::resolve_activity(conn, user_id.to_string(), space_id, activity_name.to_string())
+            .await
+            .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to save the activity".to_string()))),
+    }
+}
+
+// What a timer start answers. A running timer is stopped by the start under the auto stop
+// policy, a pomodoro run stopped that way is announced.
+async fn timer_started<T>(
+    hub: &events::EventHub,
+    user_id: &str,
+    started: Result<models::TimerStart<T>, diesel::result::Error>,
+    failed: &str,
+) -> Result<T, status::Custom<Json<String>>> {
+    match started {
+        Ok(models::TimerStart::Started(started, stopped)) => {
+            if let Some(change) = stopped {
+                events::Notifier::notify(hub, user_id, events::TrackingEvent::PomodoroPhase(change)).await;
+            }
+            Ok(started)
+        }
+        Ok(models::TimerStart::Running) => {
+            Err(status::Custom(Status::Conflict, Json("Another timer is already running".to_string())))
+        }
+        Ok(models::TimerStart::TooEarly) => Err(status::Custom(
+            Status::BadRequest,
+            Json("Start time cannot be before the running timer was last started".to_string()),
+        )),
+        Err(e) if is_unique_violation(&e) => {
+            Err(status::Custom(Status::Conflict, Json("Another timer is already running".to_string())))
+        }
+        Err(e) => {
+            eprintln!("{}: {:?}", failed, e);
+            Err(status::Custom(Status::InternalServerError, Json(failed.to_string())))
+        }
+    }
+}
+
+#[post("/start?<space_name>", data = "<new_session>")]
+async fn start_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    tracking_config: &State<tracking::TrackingConfig>,
+    hub: &State<events::EventHub>,
+    new_session: Json<models::NewTimeTrackingSession>,
+    space_name: Option<String>,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, status::Custom<Json<String>>> {
+
+    let user_id = get_user_id(jar);
+
+    // Ensure space_name is provided
+    let space_name = match space_name {
+        Some(name) => name,
+        
+        None => return Err(status::Custom(Status::BadRequest, Json("Missing space_name".to_string()))),
+    };
+
+    let space_id = match db::get_space_id(&conn, user_id.clone(), space_name).await {
+        Ok(id) => id,
+        Err(_) => {
+            return Err(status::Custom(Status::NotFound, Json("Space not found".to_string())));
+        }
+    };
+
+    // the server clock is the source of truth, a client time is only used when asked for explicitly
+    let start_time = match new_session.manual_start_time {
+        Some(manual) => {
+            check_manual_time(manual, "Start time")?;
+            manual
+        }
+        None => chrono::Utc::now(),
+    };
+
+    let new_session = new_session.into_inner();
+    let details = models::SessionDetails {
+        notes: clean_notes(new_session.notes),
+        tags: clean_tags(new_session.tags)?,
+        ..Default::default()
+    };
+
+    let activity = session_activity_choice(&conn, &user_id, space_id, new_session.activity_id, &new_session.activity_name).await?;
+
+    let stop_running = tracking_config.active_timer_policy == tracking::ActiveTimerPolicy::AutoStop;
+    let started = db::create_time_tracking_session(&conn, user_id.clone(), space_id, activity, start_time, details, stop_running).await;
+    let session = timer_started(hub, &user_id, started, "Failed to start time tracking").await?;
+
+    with_session_intervals(&conn, session).await.map(Json)
+}
+
+// Looks up one of the user's sessions that has not been stopped yet
+async fn find_open_session(
+    conn: &db::DbConn,
+    user_id: String,
+    session_id: &str,
+) -> Result<models::TimeTrackingSession, status::Custom<Json<String>>> {
+    let session_id = Uuid::parse_str(session_id)
+        .map_err(|_| status::Custom(Status::BadRequest, Json("Invalid session id".to_string())))?;
+
+    let session = db::get_time_tracking_session(conn, user_id, session_id)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Session not found".to_string())))?;
+
+    if session.end_time.is_some() {
+        return Err(status::Custom(Status::Conflict, Json("Session is already stopped".to_string())));
+    }
+
+    Ok(session)
+}
+
+async fn with_session_intervals(
+    conn: &db::DbConn,
+    session: models::TimeTrackingSession,
+) -> Result<models::TimeTrackingSessionWithIntervals, status::Custom<Json<String>>> {
+    db::with_intervals(conn, vec![session])
+        .await
+        .ok()
+        .and_then(|mut sessions| sessions.pop())
+        .ok_or_else(|| status::Custom(Status::InternalServerError, Json("Failed to load session intervals".to_string())))
+}
+
+// A running session cannot end before it started or was last resumed
+async fn check_close_time(
+    conn: &db::DbConn,
+    session: &models::TimeTrackingSession,
+    end_time: chrono::DateTime<chrono::Utc>,
+) -> Result<(), status::Custom<Json<String>>> {
+    if end_time < session.start_time {
+        return Err(status::Custom(Status::BadRequest, Json("End time cannot be before the start time".to_string())));
+    }
+
+    let running_since = db::get_session_intervals(conn, vec![session.id])
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .find(|interval| interval.end_time.is_none())
+        .map(|interval| interval.start_time);
+    if running_since.is_some_and(|since| end_time < since) {
+        return Err(status::Custom(Status::BadRequest, Json("End time cannot be before the session was last resumed".to_string())));
+    }
+    Ok(())
+}
+
+async fn close_session(
+    conn: &db::DbConn,
+    session: models::TimeTrackingSession,
+    end_time: chrono::DateTime<chrono::Utc>,
+) -> Result<models::TimeTrackingSession, status::Custom<Json<String>>> {
+    check_close_time(conn, &session, end_time).await?;
+
+    db::complete_time_tracking_session(conn, session.id, end_time).await.map_err(|e| {
+        error!("Failed to complete time tracking session: {:?}", e);
+        status::Custom(Status::InternalServerError, Json("Failed to stop time tracking".to_string()))
+    })
+}
+
+async fn stop_session(
+    conn: &db::DbConn,
+    user_id: String,
+    session_id: &str,
+    manual_end_time: Option<i64>,
+) -> Result<models::TimeTrackingSessionWithIntervals, status::Custom<Json<String>>> {
+    let session = find_open_session(conn, user_id, session_id).await?;
+
+    let end_time = match manual_end_time {
+        Some(timestamp) => {
+            let manual = chrono::DateTime::from_timestamp(timestamp, 0)
+                .ok_or_else(|| status::Custom(Status::BadRequest, Json("Invalid end_time".to_string())))?;
+            check_manual_time(manual, "End time")?;
+            manual
+        }
+        None => chrono::Utc::now(),
+    };
+
+    let session = close_session(conn, session, end_time).await?;
+    with_session_intervals(conn, session).await
+}
+
+// `end_time` is an optional manual override as a UNIX timestamp, left out the server time is used
+#[post("/stop?<session_id>&<end_time>")]
+async fn stop_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    session_id: String,
+    end_time: Option<i64>,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    stop_session(&conn, user_id, &session_id, end_time).await.map(Json)
+}
+
+// older clients also send their own clock as `end_time`, it is ignored in favour of the server time
+#[post("/complete?<session_id>")]
+async fn complete_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    session_id: String,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, Status> {
+    let user_id = get_user_id(jar);
+
+    match stop_session(&conn, user_id, &session_id, None).await {
+        Ok(session) => Ok(Json(session)),
+        Err(status::Custom(status, _)) => Err(status),
+    }
+}
+
+#[post("/pause?<session_id>")]
+async fn pause_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    session_id: String,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let session = find_open_session(&conn, user_id, &session_id).await?;
+
+    match db::pause_time_tracking_session(&conn, session.id, chrono::Utc::now()).await {
+        Ok(true) => with_session_intervals(&conn, session).await.map(Json),
+        Ok(false) => Err(status::Custom(Status::Conflict, Json("Session is already paused".to_string()))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to pause time tracking".to_string()))),
+    }
+}
+
+#[post("/resume?<session_id>")]
+async fn resume_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    session_id: String,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let session = find_open_session(&conn, user_id, &session_id).await?;
+
+    match db::resume_time_tracking_session(&conn, session.id, chrono::Utc::now()).await {
+        Ok(true) => with_session_intervals(&conn, session).await.map(Json),
+        Ok(false) => Err(status::Custom(Status::Conflict, Json("Session is not paused".to_string()))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to resume time tracking".to_string()))),
+    }
+}
+
+// The timer that is running right now in any of the user's spaces, null when none is
+#[get("/current")]
+async fn current_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Option<models::CurrentTimer>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let running = db::get_open_sessions(&conn, user_id)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load the current timer".to_string())))?;
+
+    // with auto stop there is at most one, older data may still hold more so take the newest
+    let Some(session) = running.into_iter().next() else {
+        return Ok(Json(None));
+    };
+
+    let space_name = db::get_space_name(&conn, session.space_id).await.unwrap_or_default();
+    let session = with_session_intervals(&conn, session).await?;
+
+    Ok(Json(Some(models::CurrentTimer { session, space_name })))
+}
+
+// Explicit `tz` first, then the user setting, then the space setting, then UTC
+async fn resolve_time_zone(
+    conn: &db::DbConn,
+    user_id: &str,
+    space_id: Option<i32>,
+    tz: Option<String>,
+) -> Result<chrono_tz::Tz, status::Custom<Json<String>>> {
+    let name = match tz {
+        Some(tz) => tz,
+        None => db::get_time_zone_name(conn, user_id.to_string(), space_id)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| "UTC".to_string()),
+    };
+
+    name.parse::<chrono_tz::Tz>()
+        .map_err(|_| status::Custom(Status::BadRequest, Json(format!("Unknown time zone: {}", name))))
+}
+
+// Dates from the query, kept within `reports::date_in_range` so the day arithmetic on
+// them cannot overflow
+fn parse_date(value: &str, what: &str) -> Result<chrono::NaiveDate, status::Custom<Json<String>>> {
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| status::Custom(Status::BadRequest, Json(format!("{} must be a date like 2024-08-25", what))))?;
+    if !reports::date_in_range(date) {
+        return Err(status::Custom(
+            Status::BadRequest,
+            Json(format!("{} must be between the years {} and {}", what, reports::MIN_YEAR, reports::MAX_YEAR)),
+        ));
+    }
+    Ok(date)
+}
+
+// The exclusive end of a range that ends with `date`
+fn day_after(date: chrono::NaiveDate) -> Result<chrono::NaiveDate, status::Custom<Json<String>>> {
+    date.checked_add_days(chrono::Days::new(1))
+        .ok_or_else(|| status::Custom(Status::BadRequest, Json("The date is out of range".to_string())))
+}
+
+#[derive(Responder)]
+struct Download<T> {
+    inner: T,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+#[derive(FromForm)]
+struct ExportQuery {
+    format: Option<String>,
+    space_name: Option<String>,
+    activity: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+}
+
+// Streams the user's sessions as csv, jsonl or ics. `from` and `to` are inclusive
+// dates in the space (or `tz`) time zone.
+#[get("/export?<query..>")]
+async fn export_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    query: ExportQuery,
+) -> Result<Download<ByteStream<impl futures::Stream<Item = Vec<u8>>>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let ExportQuery { format, space_name, activity, from, to, tz } = query;
+
+    let format = match format.as_deref() {
+        None => export::ExportFormat::Csv,
+        Some(value) => export::ExportFormat::parse(value)
+            .ok_or_else(|| status::Custom(Status::BadRequest, Json("format must be csv, jsonl or ics".to_string())))?,
+    };
+
+    let space_id = match &space_name {
+        Some(name) => match db::get_space_id(&conn, user_id.clone(), name.clone()).await {
+            Ok(id) => Some(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+        },
+        None => None,
+    };
+
+    let time_zone = resolve_time_zone(&conn, &user_id, space_id, tz).await?;
+
+    let from = match from {
+        Some(value) => Some(reports::local_midnight_utc(parse_date(&value, "from")?, time_zone)),
+        None => None,
+    };
+    let to = match to {
+        Some(value) => Some(reports::local_midnight_utc(day_after(parse_date(&value, "to")?)?, time_zone)),
+        None => None,
+    };
+
+    let filter = models::SessionFilter {
+        user_id,
+        space_id,
+        activity_name: activity,
+        from,
+        to,
+        completed_only: format.completed_only(),
+        ..Default::default()
+    };
+
+    let calendar_name = format!("RustySpaces {}", space_name.as_deref().unwrap_or("time tracking"));
+    let file_name = format!("time_tracking_{}.{}", space_name.as_deref().unwrap_or("all"), format.extension())
+        .replace(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '_' && c != '-', "_");
+
+    let stream = ByteStream! {
+        yield format.header(&calendar_name);
+
+        let mut after = None;
+        loop {
+            // a cut off file should not look complete, so it ends on an error instead of the footer
+            let page = match db::get_sessions_page(&conn, filter.clone(), after, EXPORT_PAGE_SIZE).await {
+                Ok(page) => page,
+                Err(e) => {
+                    eprintln!("Error reading sessions for export: {:?}", e);
+                    yield format.error("The export is incomplete, reading the sessions failed");
+                    return;
+                }
+            };
+
+            after = page.last().map(|session| (session.start_time, session.id));
+            for session in &page {
+                yield format.row(session);
+            }
+
+            if (page.len() as i64) < EXPORT_PAGE_SIZE {
+                break;
+            }
+        }
+
+        yield format.footer();
+    };
+
+    Ok(Download {
+        inner: stream,
+        content_type: format.content_type(),
+        disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", file_name)),
+    })
+}
+
+#[derive(FromForm)]
+struct ImportForm<'r> {
+    file: TempFile<'r>,
+    source: Option<String>, // csv (default), toggl or clockify
+    mapping: import::ColumnMapping,
+    tz: Option<String>, // zone of times without an offset
+    dry_run: Option<bool>,
+    allow_overlaps: Option<bool>,
+}
+
+// Checks every row of an uploaded CSV and reports on it. Nothing is written
+// unless `dry_run=false`, and then only the rows that came back ok.
+#[post("/import?<space_name>", data = "<upload>")]
+async fn import_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    upload: Form<ImportForm<'_>>,
+    space_name: Option<String>,
+) -> Result<Json<import::ImportReport>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = match space_name {
+        Some(name) => name,
+        None => return Err(status::Custom(Status::BadRequest, Json("Missing space_name".to_string()))),
+    };
+
+    let space_id = match db::get_space_id(&conn, user_id.clone(), space_name).await {
+        Ok(id) => id,
+        Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+    };
+
+    let source = match upload.source.as_deref() {
+        None => import::ImportSource::Generic,
+        Some(value) => import::ImportSource::parse(value)
+            .ok_or_else(|| status::Custom(Status::BadRequest, Json("source must be csv, toggl or clockify".to_string())))?,
+    };
+
+    let time_zone = resolve_time_zone(&conn, &user_id, Some(space_id), upload.tz.clone()).await?;
+    let dry_run = upload.dry_run.unwrap_or(true);
+
+    let mut data = Vec::new();
+    upload.file.open().await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to read upload".to_string())))?
+        .read_to_end(&mut data).await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to read upload".to_string())))?;
+
+    let mut rows = import::parse_rows(&data, source, &upload.mapping, time_zone)
+        .map_err(|e| status::Custom(Status::BadRequest, Json(e)))?;
+
+    let starts = rows.iter().filter_map(|row| row.start_time);
+    let ends = rows.iter().filter_map(|row| row.end_time);
+    if let (Some(from), Some(to)) = (starts.min(), ends.max()) {
+        let existing = db::get_sessions_overlapping(&conn, user_id.clone(), from, to)
+            .await
+            .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to check existing sessions".to_string())))?;
+
+        import::check_against(&mut rows, &existing, upload.allow_overlaps.unwrap_or(false));
+    }
+
+    let sessions = import::sessions_to_insert(&rows, &user_id, space_id);
+    let importable = sessions.len();
+
+    let imported = if dry_run || sessions.is_empty() {
+        0
+    } else {
+        db::insert_completed_sessions(&conn, sessions)
+            .await
+            .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to import sessions".to_string())))?
+    };
+
+    Ok(Json(import::ImportReport {
+        dry_run,
+        total_rows: rows.len(),
+        importable,
+        imported,
+        skipped: rows.len() - importable,
+        rows,
+    }))
+}
+
+// Totals per activity, per day/week/month bucket and per space for the inclusive
+// date range, compared with the same length of time right before it.
+// Leaving out `space_name` reports across every space.
+#[get("/reports?<space_name>&<from>&<to>&<group_by>&<tz>")]
+async fn time_tracking_report(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    space_name: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    group_by: Option<String>,
+    tz: Option<String>,
+) -> Result<Json<reports::TimeReport>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match space_name {
+        Some(name) => match db::get_space_id(&conn, user_id.clone(), name).await {
+            Ok(id) => Some(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+        },
+        None => None,
+    };
+
+    let granularity = match group_by.as_deref() {
+        None => reports::Granularity::Day,
+        Some(value) => reports::Granularity::parse(value)
+            .ok_or_else(|| status::Custom(Status::BadRequest, Json("group_by must be day, week or month".to_string())))?,
+    };
+
+    let time_zone = resolve_time_zone(&conn, &user_id, space_id, tz).await?;
+    let today = chrono::Utc::now().with_timezone(&time_zone).date_naive();
+
+    let to = match to {
+        Some(value) => parse_date(&value, "to")?,
+        None => today,
+    };
+    let from = match from {
+        Some(value) => parse_date(&value, "from")?,
+        None => to.checked_sub_days(chrono::Days::new(29)).unwrap_or(to),
+    };
+    if from > to {
+        return Err(status::Custom(Status::BadRequest, Json("from cannot be after to".to_string())));
+    }
+
+    let days = (to - from).num_days() + 1;
+    if days > reports::MAX_REPORT_DAYS {
+        return Err(status::Custom(
+            Status::BadRequest,
+            Json(format!("A report can cover at most {} days", reports::MAX_REPORT_DAYS)),
+        ));
+    }
+    let out_of_range = || status::Custom(Status::BadRequest, Json("The range is out of bounds".to_string()));
+    let previous_from = from.checked_sub_days(chrono::Days::new(days as u64)).ok_or_else(out_of_range)?;
+    let to_exclusive = to.checked_add_days(chrono::Days::new(1)).ok_or_else(out_of_range)?;
+
+    let load_error = |_| status::Custom(Status::InternalServerError, Json("Failed to build report".to_string()));
+
+    let current = db::time_report_rows(
+        &conn, user_id.clone(), space_id, time_zone.name().to_string(), granularity, from, to_exclusive,
+    ).await.map_err(load_error)?;
+    let previous = db::time_report_rows(
+        &conn, user_id, space_id, time_zone.name().to_string(), granularity, previous_from, from,
+    ).await.map_err(load_error)?;
+
+    Ok(Json(reports::build_report(from, to, granularity, time_zone.name().to_string(), current, previous)))
+}
+
+// Completed time per tag, each broken down by activity. `activity` narrows it down
+// to one activity, e.g. to see which projects "coding" went into.
+#[get("/reports/tags?<space_name>&<from>&<to>&<tz>&<activity>")]
+async fn time_tracking_tag_report(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    space_name: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+    activity: Option<String>,
+) -> Result<Json<reports::TagReport>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match space_name {
+        Some(name) => match db::get_space_id(&conn, user_id.clone(), name).await {
+            Ok(id) => Some(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+        },
+        None => None,
+    };
+
+    let time_zone = resolve_time_zone(&conn, &user_id, space_id, tz).await?;
+    let today = chrono::Utc::now().with_timezone(&time_zone).date_naive();
+
+    let (from, to) = date_range(from, to, today, 30, reports::MAX_REPORT_DAYS)?;
+    let activity = activity.map(|name| name.trim().to_string()).filter(|name| !name.is_empty());
+
+    let rows = db::tag_report_rows(
+        &conn, user_id, space_id, activity.clone(), time_zone.name().to_string(), from, day_after(to)?,
+    ).await.map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to build report".to_string())))?;
+
+    Ok(Json(reports::build_tag_report(from, to, time_zone.name().to_string(), activity, rows)))
+}
+
+// What `/track/time_tracking` lists, dates are local to `tz` (or the space's time
+// zone) and every `tag` has to be on a session
+#[derive(FromForm)]
+struct SessionQuery {
+    space_name: Option<String>,
+    q: Option<String>,
+    tag: Vec<String>,
+    activity: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+}
+
+#[get("/time_tracking?<query..>")]
+async fn get_all_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    query: SessionQuery,
+) -> Result<Json<Vec<models::TimeTrackingSessionWithIntervals>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let SessionQuery { space_name, q, tag, activity, from, to, tz } = query;
+
+    let space_name = match space_name {
+        Some(name) => name,
+        None => return Ok(Json(Vec::new())), // Return an empty list if space_name is missing
+    };
+
+    let space_id = match db::get_space_id(&conn, user_id.clone(), space_name).await {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(Json(Vec::new()));
+        }
+    };
+
+    let time_zone = resolve_time_zone(&conn, &user_id, Some(space_id), tz).await?;
+    let from = match from {
+        Some(value) => Some(reports::local_midnight_utc(parse_date(&value, "from")?, time_zone)),
+        None => None,
+    };
+    let to = match to {
+        Some(value) => Some(reports::local_midnight_utc(day_after(parse_date(&value, "to")?)?, time_zone)),
+        None => None,
+    };
+
+    let filter = models::SessionFilter {
+        user_id,
+        space_id: Some(space_id),
+        activity_name: activity,
+        from,
+        to,
+        completed_only: false,
+        search: q.map(|q| q.trim().to_string()).filter(|q| !q.is_empty()),
+        tags: clean_tags(tag)?,
+    };
+
+    let sessions = db::get_filtered_sessions(&conn, filter)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load sessions".to_string())))?;
+    db::with_intervals(&conn, sessions)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load session intervals".to_string())))
+}
+
+
+
+
+// pomodoro
+
+// Starts a run with its first work phase right away
+#[post("/pomodoro/start?<space_name>", data = "<new_run>")]
+async fn start_pomodoro(
+    space_name: Option<String>,
+    new_run: Json<models::NewPomodoroRun>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    tracking_config: &State<tracking::TrackingConfig>,
+    hub: &State<events::EventHub>,
+) -> Result<Json<pomodoro::PomodoroStatus>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    let new_run = new_run.into_inner();
+    let defaults = &tracking_config.pomodoro;
+    let work_seconds = new_run.work_seconds.unwrap_or(defaults.work_secs);
+    let short_break_seconds = new_run.short_break_seconds.unwrap_or(defaults.short_break_secs);
+    let long_break_seconds = new_run.long_break_seconds.unwrap_or(defaults.long_break_secs);
+    let long_break_every = new_run.long_break_every.unwrap_or(defaults.long_break_every);
+    if work_seconds <= 0 || short_break_seconds <= 0 || long_break_seconds <= 0 {
+        return Err(status::Custom(Status::BadRequest, Json("Phase lengths must be positive".to_string())));
+    }
+    if [work_seconds, short_break_seconds, long_break_seconds].iter().any(|length| *length > pomodoro::MAX_PHASE_SECONDS) {
+        return Err(status::Custom(Status::BadRequest, Json("Phase lengths can be at most a day".to_string())));
+    }
+    if long_break_every < 1 {
+        return Err(status::Custom(Status::BadRequest, Json("long_break_every must be at least 1".to_string())));
+    }
+
+    let activity = session_activity_choice(&conn, &user_id, space_id, new_run.activity_id, &new_run.activity_name).await?;
+
+    let now = chrono::Utc::now();
+    let run = models::PomodoroRun {
+        id: Uuid::new_v4(),
+        user_id: user_id.clone(),
+        space_id,
+        activity_id: Uuid::nil(), // set from `activity` when saved
+        work_seconds,
+        short_break_seconds,
+        long_break_seconds,
+        long_break_every,
+        phase: pomodoro::Phase::Work.as_str().to_string(),
+        phase_started_at: now,
+        phase_ends_at: now + chrono::Duration::seconds(work_seconds),
+        completed_pomodoros: 0,
+        session_id: None,
+        started_at: now,
+        ended_at: None,
+    };
+
+    let stop_running = tracking_config.active_timer_policy == tracking::ActiveTimerPolicy::AutoStop;
+    let started = db::start_pomodoro_run(&conn, run, activity, stop_running).await;
+    timer_started(hub, &user_id, started, "Failed to start the pomodoro")
+        .await
+        .map(|run| Json(pomodoro::PomodoroStatus::new(run)))
+}
+
+#[get("/pomodoro/current")]
+async fn current_pomodoro(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Option<pomodoro::PomodoroStatus>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    match db::get_active_pomodoro_run(&conn, user_id).await {
+        Ok(run) => Ok(Json(run.map(pomodoro::PomodoroStatus::new))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load the pomodoro".to_string()))),
+    }
+}
+
+async fn move_pomodoro_on(
+    conn: &db::DbConn,
+    hub: &events::EventHub,
+    user_id: String,
+    how: pomodoro::Advance,
+) -> Result<Json<pomodoro::PhaseChange>, status::Custom<Json<String>>> {
+    use events::Notifier;
+
+    let run = db::get_active_pomodoro_run(conn, user_id.clone())
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load the pomodoro".to_string())))?
+        .ok_or_else(|| status::Custom(Status::NotFound, Json("No pomodoro is running".to_string())))?;
+
+    let change = pomodoro::advance(conn, run, chrono::Utc::now(), how)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to update the pomodoro".to_string())))?
+        .ok_or_else(|| status::Custom(Status::Conflict, Json("The pomodoro moved on in the meantime".to_string())))?;
+
+    hub.notify(&user_id, events::TrackingEvent::PomodoroPhase(change.clone())).await;
+    Ok(Json(change))
+}
+
+// Ends the current phase early, a skipped work phase does not count as a pomodoro
+#[post("/pomodoro/skip")]
+async fn skip_pomodoro_phase(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    hub: &State<events::EventHub>,
+) -> Result<Json<pomodoro::PhaseChange>, status::Custom<Json<String>>> {
+    move_pomodoro_on(&conn, hub, get_user_id(jar), pomodoro::Advance::Skipped).await
+}
+
+#[post("/pomodoro/stop")]
+async fn stop_pomodoro(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    hub: &State<events::EventHub>,
+) -> Result<Json<pomodoro::PhaseChange>, status::Custom<Json<String>>> {
+    move_pomodoro_on(&conn, hub, get_user_id(jar), pomodoro::Advance::Stop).await
+}
+
+// Completed pomodoros per activity for the inclusive date range, defaults to today
+#[get("/pomodoro/stats?<space_name>&<from>&<to>&<tz>")]
+async fn pomodoro_stats(
+    space_name: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<pomodoro::PomodoroCount>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+    let time_zone = resolve_time_zone(&conn, &user_id, Some(space_id), tz).await?;
+
+    let today = chrono::Utc::now().with_timezone(&time_zone).date_naive();
+    let from = match from {
+        Some(value) => parse_date(&value, "from")?,
+        None => today,
+    };
+    let to = match to {
+        Some(value) => parse_date(&value, "to")?,
+        None => from.max(today),
+    };
+    if to < from {
+        return Err(status::Custom(Status::BadRequest, Json("to must not be before from".to_string())));
+    }
+
+    let from = reports::local_midnight_utc(from, time_zone);
+    let to = reports::local_midnight_utc(day_after(to)?, time_zone);
+    match db::count_pomodoros(&conn, user_id, space_id, from, to).await {
+        Ok(counts) => Ok(Json(counts)),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to count pomodoros".to_string()))),
+    }
+}
+
+fn clean_notes(notes: Option<String>) -> Option<String> {
+    notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty())
+}
+
+const MAX_TAG_LENGTH: usize = 50;
+
+// Tags are kept lower case without a leading #, empty ones and repeats are dropped
+fn clean_tags(tags: Vec<String>) -> Result<Vec<String>, status::Custom<Json<String>>> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().trim_start_matches('#').trim().to_lowercase();
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(status::Custom(Status::BadRequest, Json(format!("Tags can be at most {} characters long", MAX_TAG_LENGTH))));
+        }
+        if !tag.is_empty() && !cleaned.contains(&tag) {
+            cleaned.push(tag);
+        }
+    }
+    Ok(cleaned)
+}
+
+// Logs time after the fact, the range may not overlap any other session
+#[post("/sessions?<space_name>", data = "<manual>")]
+async fn create_manual_time_tracking(
+    space_name: Option<String>,
+    manual: Json<models::ManualSession>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    let manual = manual.into_inner();
+    if manual.end_time <= manual.start_time {
+        return Err(status::Custom(Status::BadRequest, Json("End time must be after the start time".to_string())));
+    }
+    check_manual_time(manual.end_time, "End time")?;
+
+    let activity = session_activity_choice(&conn, &user_id, space_id, manual.activity_id, &manual.activity_name).await?;
+    let note = match manual.note_id {
+        Some(note_id) => Some(check_note_link(&conn, &user_id, space_id, note_id, manual.note_line_id.clone(), manual.note_line).await?),
+        None if manual.note_line_id.is_some() || manual.note_line.is_some() => {
+            return Err(status::Custom(Status::BadRequest, Json("note_line needs a note_id".to_string())));
+        }
+        None => None,
+    };
+
+    let session = models::TimeTrackingSession {
+        id: Uuid::new_v4(),
+        user_id,
+        space_id,
+        activity_name: String::new(), // filled in from `activity` when saved
+        start_time: manual.start_time,
+        end_time: Some(manual.end_time),
+        duration: Some(manual.end_time.signed_duration_since(manual.start_time).num_seconds()),
+        activity_id: None,
+        notes: clean_notes(manual.notes),
+        session_type: models::SESSION_TYPE_REGULAR.to_string(),
+        last_heartbeat_at: None,
+        auto_stopped: false,
+        auto_stop_reason: None,
+        reviewed_at: None,
+        note_id: note.as_ref().map(|(note, _)| note.id),
+        note_line_id: note.and_then(|(_, line_id)| line_id),
+        tags: clean_tags(manual.tags)?,
+    };
+
+    match db::create_manual_session(&conn, session, activity).await {
+        Ok(models::SessionSaveOutcome::Saved(session)) => with_session_intervals(&conn, session).await.map(Json),
+        Ok(models::SessionSaveOutcome::Overlaps(session)) => Err(status::Custom(
+            Status::Conflict,
+            Json(format!("Overlaps \"{}\" starting {}", session.activity_name, session.start_time)),
+        )),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to save the session".to_string()))),
+    }
+}
+
+// Changes the activity, range or notes of a session. Setting `end_time` on a running
+// session stops it, pauses inside the new range are kept and `duration` is recounted.
+#[put("/sessions/<session_id>", data = "<edit>")]
+async fn edit_time_tracking(
+    session_id: String,
+    edit: Json<models::SessionEdit>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::TimeTrackingSessionWithIntervals>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let session_id = Uuid::parse_str(&session_id)
+        .map_err(|_| status::Custom(Status::BadRequest, Json("Invalid session id".to_string())))?;
+
+    let before = db::get_time_tracking_session(&conn, user_id.clone(), session_id)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Session not found".to_string())))?;
+
+    let edit = edit.into_inner();
+    let start_time = edit.start_time.unwrap_or(before.start_time);
+    let end_time = edit.end_time.or(before.end_time);
+
+    check_manual_time(start_time, "Start time")?;
+    if let Some(end_time) = end_time {
+        if end_time <= start_time {
+            return Err(status::Custom(Status::BadRequest, Json("End time must be after the start time".to_string())));
+        }
+        check_manual_time(end_time, "End time")?;
+    }
+
+    // a new name is only turned into an activity once the edit is known to fit
+    let activity = match (edit.activity_id, edit.activity_name) {
+        (None, None) => None,
+        (activity_id, name) => Some(
+            session_activity_choice(&conn, &user_id, before.space_id, activity_id, &name.unwrap_or_default()).await?,
+        ),
+    };
+    let changes = models::SessionChanges {
+        start_time,
+        end_time,
+        activity,
+        notes: edit.notes.map(|notes| clean_notes(Some(notes))),
+        tags: edit.tags.map(clean_tags).transpose()?,
+    };
+
+    match db::save_session_edit(&conn, user_id, session_id, changes).await {
+        Ok(models::SessionSaveOutcome::Saved(session)) => with_session_intervals(&conn, session).await.map(Json),
+        Ok(models::SessionSaveOutcome::Overlaps(session)) => Err(status::Custom(
+            Status::Conflict,
+            Json(format!("Overlaps \"{}\" starting {}", session.activity_name, session.start_time)),
+        )),
+        Err(diesel::result::Error::NotFound) => Err(status::Custom(Status::NotFound, Json("Session not found".to_string()))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to save the session".to_string()))),
+    }
+}
+
+// Sent by the client every few minutes while a timer runs, see `autostop`
+#[post("/heartbeat?<session_id>")]
+async fn time_tracking_heartbeat(
+    session_id: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Status, Status> {
+    let user_id = get_user_id(jar);
+    let session_id = match session_id {
+        Some(value) => Some(Uuid::parse_str(&value).map_err(|_| Status::BadRequest)?),
+        None => None,
+    };
+
+    match db::record_heartbeat(&conn, user_id, session_id, chrono::Utc::now()).await {
+        Ok(0) => Err(Status::NotFound),
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// Sessions the server stopped on its own that still wait for the user to look at them.
+// Fix one through `PUT /sessions/<id>` or accept it as it is with `/sessions/<id>/review`.
+#[get("/review?<space_name>")]
+async fn sessions_to_review(
+    space_name: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::TimeTrackingSessionWithIntervals>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match space_name {
+        Some(space_name) => Some(
+            db::get_space_id(&conn, user_id.clone(), space_name)
+                .await
+                .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?,
+        ),
+        None => None,
+    };
+
+    let sessions = db::get_sessions_to_review(&conn, user_id, space_id)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load sessions".to_string())))?;
+    db::with_intervals(&conn, sessions)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load session intervals".to_string())))
+}
+
+#[post("/sessions/<session_id>/review")]
+async fn review_time_tracking(
+    session_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::TimeTrackingSession>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let session_id = Uuid::parse_str(&session_id)
+        .map_err(|_| status::Custom(Status::BadRequest, Json("Invalid session id".to_string())))?;
+
+    match db::mark_session_reviewed(&conn, user_id, session_id, chrono::Utc::now()).await {
+        Ok(session) => Ok(Json(session)),
+        Err(diesel::result::Error::NotFound) => {
+            Err(status::Custom(Status::NotFound, Json("No auto stopped session with that id".to_string())))
+        }
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to update the session".to_string()))),
+    }
+}
+
+// Every manual change made to a session, oldest first
+#[get("/sessions/<session_id>/history")]
+async fn time_tracking_history(
+    session_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::SessionAuditEntry>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let session_id = Uuid::parse_str(&session_id)
+        .map_err(|_| status::Custom(Status::BadRequest, Json("Invalid session id".to_string())))?;
+
+    match db::get_session_audit_log(&conn, user_id, session_id).await {
+        Ok(entries) => Ok(Json(entries)),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load the session history".to_string()))),
+    }
+}
+
+#[delete("/delete?<session_id>")]
+async fn delete_time_tracking(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    session_id: String,
+) -> Result<Status, Status> {
+    let user_id = get_user_id(jar);
+
+    // Parse the session_id from a String to a Uuid
+    let session_id = match Uuid::parse_str(&session_id) {
+        Ok(uuid) => uuid,
+        Err(_) => return Err(Status::BadRequest),
+    };
+
+    let session = db::get_time_tracking_session(&conn, user_id, session_id)
+        .await
+        .map_err(|_| Status::NotFound)?;
+    let audit = models::SessionAuditEntry::new("delete", Some(&session), None);
+
+    match db::delete_time_tracking_session(&conn, session_id, audit).await {
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+
+// habits
+
+fn parse_habit_id(habit_id: &str) -> Result<Uuid, status::Custom<Json<String>>> {
+    Uuid::parse_str(habit_id).map_err(|_| status::Custom(Status::BadRequest, Json("Invalid habit id".to_string())))
+}
+
+async fn find_habit(conn: &db::DbConn, user_id: String, habit_id: &str) -> Result<models::Habit, status::Custom<Json<String>>> {
+    let habit_id = parse_habit_id(habit_id)?;
+    db::get_habit(conn, user_id, habit_id)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Habit not found".to_string())))
+}
+
+// `from`..`to` as local dates, ending today and going back `default_days` unless given,
+// covering at most `max_days`
+fn date_range(
+    from: Option<String>,
+    to: Option<String>,
+    today: chrono::NaiveDate,
+    default_days: i64,
+    max_days: i64,
+) -> Result<(chrono::NaiveDate, chrono::NaiveDate), status::Custom<Json<String>>> {
+    let to = match to {
+        Some(value) => parse_date(&value, "to")?,
+        None => today,
+    };
+    let from = match from {
+        Some(value) => parse_date(&value, "from")?,
+        None => to.checked_sub_days(chrono::Days::new((default_days - 1).max(0) as u64)).unwrap_or(to),
+    };
+    if from > to {
+        return Err(status::Custom(Status::BadRequest, Json("from cannot be after to".to_string())));
+    }
+    if (to - from).num_days() >= max_days {
+        return Err(status::Custom(Status::BadRequest, Json(format!("The range can cover at most {} days", max_days))));
+    }
+    Ok((from, to))
+}
+
+#[get("/?<space_name>&<tz>")]
+async fn get_habits(
+    space_name: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<habits::HabitStatus>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+    let time_zone = resolve_time_zone(&conn, &user_id, Some(space_id), tz).await?;
+    let failed = |_| status::Custom(Status::InternalServerError, Json("Failed to load habits".to_string()));
+
+    let mut statuses = Vec::new();
+    for habit in db::get_habits(&conn, user_id, Some(space_id)).await.map_err(failed)? {
+        statuses.push(habits::habit_status(&conn, habit, time_zone).await.map_err(failed)?);
+    }
+    Ok(Json(statuses))
+}
+
+#[post("/?<space_name>", data = "<new_habit>")]
+async fn create_habit(
+    space_name: Option<String>,
+    new_habit: Json<models::NewHabit>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<habits::HabitStatus>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    let new_habit = new_habit.into_inner();
+    let mut name = new_habit.name.trim().to_string();
+
+    let (activity_id, target_seconds, note_id, line) = match habits::HabitKind::parse(&new_habit.kind) {
+        Some(habits::HabitKind::Time) => {
+            let target_seconds = new_habit
+                .target_seconds
+                .filter(|target| *target > 0)
+                .ok_or_else(|| status::Custom(Status::BadRequest, Json("target_seconds must be positive".to_string())))?;
+            let activity_name = new_habit.activity_name.unwrap_or_default();
+            let activity = resolve_session_activity(&conn, &user_id, space_id, new_habit.activity_id, &activity_name).await?;
+            if name.is_empty() {
+                name = activity.name;
+            }
+            (Some(activity.id), Some(target_seconds), None, None)
+        }
+        Some(habits::HabitKind::Checklist) => match new_habit.note_id {
+            Some(note_id) => {
+                if new_habit.line_id.is_none() && new_habit.line.is_none() {
+                    return Err(status::Custom(Status::BadRequest, Json("Missing line".to_string())));
+                }
+                let (note, line_id) = check_note_link(&conn, &user_id, space_id, note_id, new_habit.line_id, new_habit.line).await?;
+                let line = note.sticky_lines().into_iter().find(|line| Some(&line.id) == line_id.as_ref());
+                if name.is_empty() {
+                    name = line.as_ref().map(|line| line.text.clone()).unwrap_or_default();
+                }
+                (None, None, Some(note.id), line)
+            }
+            None => (None, None, None, None),
+        },
+        None => return Err(status::Custom(Status::BadRequest, Json("kind must be checklist or time".to_string()))),
+    };
+    if name.is_empty() {
+        return Err(status::Custom(Status::BadRequest, Json("Missing name".to_string())));
+    }
+
+    let habit = models::Habit {
+        id: Uuid::new_v4(),
+        space_id,
+        user_id,
+        name,
+        kind: new_habit.kind,
+        activity_id,
+        target_seconds,
+        note_id,
+        line_text: line.as_ref().map(|line| line.text.clone()),
+        created_at: chrono::Utc::now(),
+        line_id: line.map(|line| line.id),
+    };
+    let habit = db::create_habit(&conn, habit)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to create habit".to_string())))?;
+
+    let time_zone = resolve_time_zone(&conn, &habit.user_id, Some(space_id), None).await?;
+    habits::habit_status(&conn, habit, time_zone)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load habit".to_string())))
+}
+
+#[delete("/<habit_id>")]
+async fn delete_habit(
+    habit_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Status, Status> {
+    let user_id = get_user_id(jar);
+    let habit_id = Uuid::parse_str(&habit_id).map_err(|_| Status::BadRequest)?;
+
+    match db::delete_habit(&conn, user_id, habit_id).await {
+        Ok(0) => Err(Status::NotFound),
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// The day a checklist habit is checked off or unchecked, today unless `date` is given
+async fn habit_check_day(
+    conn: &db::DbConn,
+    habit: &models::Habit,
+    date: Option<String>,
+    tz: Option<String>,
+) -> Result<(chrono::NaiveDate, chrono_tz::Tz), status::Custom<Json<String>>> {
+    if habits::HabitKind::parse(&habit.kind) == Some(habits::HabitKind::Time) {
+        return Err(status::Custom(Status::BadRequest, Json("A time habit is done by tracking time".to_string())));
+    }
+
+    let time_zone = resolve_time_zone(conn, &habit.user_id, Some(habit.space_id), tz).await?;
+    let today = habits::local_today(time_zone);
+    let day = match date {
+        Some(value) => parse_date(&value, "date")?,
+        None => today,
+    };
+    if day > today {
+        return Err(status::Custom(Status::BadRequest, Json("Cannot check off a day in the future".to_string())));
+    }
+    Ok((day, time_zone))
+}
+
+#[post("/<habit_id>/check?<date>&<tz>")]
+async fn check_habit(
+    habit_id: String,
+    date: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<habits::HabitStatus>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let habit = find_habit(&conn, user_id, &habit_id).await?;
+    let (day, time_zone) = habit_check_day(&conn, &habit, date, tz).await?;
+
+    let completion = models::HabitCompletion { habit_id: habit.id, day, completed_at: chrono::Utc::now() };
+    db::record_habit_completion(&conn, completion)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to check off the habit".to_string())))?;
+
+    habits::habit_status(&conn, habit, time_zone)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load habit".to_string())))
+}
+
+#[delete("/<habit_id>/check?<date>&<tz>")]
+async fn uncheck_habit(
+    habit_id: String,
+    date: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<habits::HabitStatus>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let habit = find_habit(&conn, user_id, &habit_id).await?;
+    let (day, time_zone) = habit_check_day(&conn, &habit, date, tz).await?;
+
+    db::delete_habit_completion(&conn, habit.id, day)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to uncheck the habit".to_string())))?;
+
+    habits::habit_status(&conn, habit, time_zone)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load habit".to_string())))
+}
+
+// Day by day completion of one habit, the last 30 days by default
+#[get("/<habit_id>/days?<from>&<to>&<tz>")]
+async fn get_habit_days(
+    habit_id: String,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<habits::HabitDay>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let habit = find_habit(&conn, user_id, &habit_id).await?;
+
+    let time_zone = resolve_time_zone(&conn, &habit.user_id, Some(habit.space_id), tz).await?;
+    let (from, to) = date_range(from, to, habits::local_today(time_zone), 30, 366)?;
+
+    habits::habit_days(&conn, &habit, time_zone, from, to)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load habit days".to_string())))
+}
+
+// Calendar heatmap of tracked time, checked lines and completed habits per day,
+// the last year by default. Without space_name it covers every space.
+#[get("/heatmap?<space_name>&<from>&<to>&<tz>")]
+async fn habit_heatmap(
+    space_name: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<habits::HeatmapDay>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match space_name {
+        Some(name) => match db::get_space_id(&conn, user_id.clone(), name).await {
+            Ok(id) => Some(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+        },
+        None => None,
+    };
+    let time_zone = resolve_time_zone(&conn, &user_id, space_id, tz).await?;
+    let (from, to) = date_range(from, to, habits::local_today(time_zone), 365, 366)?;
+    let to_exclusive = day_after(to)?;
+    let failed = |_| status::Custom(Status::InternalServerError, Json("Failed to build the heatmap".to_string()));
+
+    let tracked = db::daily_tracked_seconds(&conn, user_id.clone(), space_id, None, time_zone.name().to_string(), Some(from), to_exclusive)
+        .await
+        .map_err(failed)?;
+    let line_checks = db::daily_line_checks(&conn, user_id.clone(), space_id, time_zone.name().to_string(), from, to_exclusive)
+        .await
+        .map_err(failed)?;
+
+    let mut habit_days = Vec::new();
+    for habit in db::get_habits(&conn, user_id, space_id).await.map_err(failed)? {
+        habit_days.push(habits::completed_days(&conn, &habit, time_zone, Some(from), to_exclusive).await.map_err(failed)?);
+    }
+
+    Ok(Json(habits::heatmap(from, to, &tracked, &line_checks, &habit_days)))
+}
+
+// goals
+
+async fn find_goal(conn: &db::DbConn, user_id: String, goal_id: &str) -> Result<models::Goal, status::Custom<Json<String>>> {
+    let goal_id = Uuid::parse_str(goal_id).map_err(|_| status::Custom(Status::BadRequest, Json("Invalid goal id".to_string())))?;
+    db::get_goal(conn, user_id, goal_id)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Goal not found".to_string())))
+}
+
+#[get("/?<space_name>&<tz>")]
+async fn get_goals(
+    space_name: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<goals::GoalProgress>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+    let time_zone = resolve_time_zone(&conn, &user_id, Some(space_id), tz).await?;
+    let failed = |_| status::Custom(Status::InternalServerError, Json("Failed to load goals".to_string()));
+
+    let mut progress = Vec::new();
+    for goal in db::get_goals(&conn, user_id, Some(space_id)).await.map_err(failed)? {
+        progress.push(goals::goal_progress(&conn, goal, time_zone).await.map_err(failed)?);
+    }
+    Ok(Json(progress))
+}
+
+#[post("/?<space_name>", data = "<new_goal>")]
+async fn create_goal(
+    space_name: Option<String>,
+    new_goal: Json<models::NewGoal>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<goals::GoalProgress>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+    let time_zone = resolve_time_zone(&conn, &user_id, Some(space_id), None).await?;
+
+    let new_goal = new_goal.into_inner();
+    if new_goal.target <= 0 || new_goal.target > goals::MAX_TARGET {
+        return Err(status::Custom(Status::BadRequest, Json(format!("target must be between 1 and {}", goals::MAX_TARGET))));
+    }
+    let mut name = new_goal.name.trim().to_string();
+
+    let (activity_id, note_id) = match goals::GoalKind::parse(&new_goal.kind) {
+        Some(goals::GoalKind::Time) => {
+            let activity_name = new_goal.activity_name.unwrap_or_default();
+            let activity = resolve_session_activity(&conn, &user_id, space_id, new_goal.activity_id, &activity_name).await?;
+            if name.is_empty() {
+                name = activity.name;
+            }
+            (Some(activity.id), None)
+        }
+        Some(goals::GoalKind::Checklist) => match new_goal.note_id {
+            Some(note_id) => {
+                let (note, _) = check_note_link(&conn, &user_id, space_id, note_id, None, None).await?;
+                if name.is_empty() {
+                    name = note.title.clone();
+                }
+                (None, Some(note.id))
+            }
+            None => (None, None),
+        },
+        None => return Err(status::Custom(Status::BadRequest, Json("kind must be checklist or time".to_string()))),
+    };
+    if name.is_empty() {
+        return Err(status::Custom(Status::BadRequest, Json("Missing name".to_string())));
+    }
+
+    // explicit dates, otherwise the current day, week or month
+    let (start_date, end_date) = match (new_goal.start_date, new_goal.end_date) {
+        (Some(start), Some(end)) => (start, end),
+        (None, None) => {
+            let period = new_goal.period.unwrap_or_else(|| "month".to_string());
+            let granularity = reports::Granularity::parse(&period)
+                .ok_or_else(|| status::Custom(Status::BadRequest, Json("period must be day, week or month".to_string())))?;
+            let today = habits::local_today(time_zone);
+            (granularity.bucket_start(today), granularity.next_bucket_start(today) - chrono::Duration::days(1))
+        }
+        _ => return Err(status::Custom(Status::BadRequest, Json("Give both start_date and end_date, or neither".to_string()))),
+    };
+    if !reports::date_in_range(start_date) || !reports::date_in_range(end_date) {
+        return Err(status::Custom(
+            Status::BadRequest,
+            Json(format!("Dates must be between the years {} and {}", reports::MIN_YEAR, reports::MAX_YEAR)),
+        ));
+    }
+    if start_date > end_date {
+        return Err(status::Custom(Status::BadRequest, Json("start_date cannot be after end_date".to_string())));
+    }
+
+    let goal = models::Goal {
+        id: Uuid::new_v4(),
+        space_id,
+        user_id,
+        name,
+        kind: new_goal.kind,
+        target: new_goal.target,
+        activity_id,
+        note_id,
+        start_date,
+        end_date,
+        created_at: chrono::Utc::now(),
+    };
+    let goal = db::create_goal(&conn, goal)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to create goal".to_string())))?;
+
+    goals::goal_progress(&conn, goal, time_zone)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load goal".to_string())))
+}
+
+// Current progress and where the pace so far leads
+#[get("/<goal_id>?<tz>")]
+async fn get_goal(
+    goal_id: String,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<goals::GoalProgress>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let goal = find_goal(&conn, user_id, &goal_id).await?;
+    let time_zone = resolve_time_zone(&conn, &goal.user_id, Some(goal.space_id), tz).await?;
+
+    goals::goal_progress(&conn, goal, time_zone)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load goal".to_string())))
+}
+
+#[delete("/<goal_id>")]
+async fn delete_goal(
+    goal_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Status, Status> {
+    let user_id = get_user_id(jar);
+    let goal_id = Uuid::parse_str(&goal_id).map_err(|_| Status::BadRequest)?;
+
+    match db::delete_goal(&conn, user_id, goal_id).await {
+        Ok(0) => Err(Status::NotFound),
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+// Progress snapshots, one per local day
+#[get("/<goal_id>/history?<from>&<to>")]
+async fn goal_history(
+    goal_id: String,
+    from: Option<String>,
+    to: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::GoalSnapshot>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let goal = find_goal(&conn, user_id, &goal_id).await?;
+    let from = from.map(|value| parse_date(&value, "from")).transpose()?;
+    let to = to.map(|value| parse_date(&value, "to")).transpose()?;
+
+    db::get_goal_snapshots(&conn, goal.id, from, to)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load goal history".to_string())))
+}
+
+// calendar
+
+// Sessions and due notes and lines from `from` to `to` (inclusive, the current week by
+// default) in one space, or in all of them without `space_name`
+#[get("/?<space_name>&<from>&<to>&<tz>")]
+async fn calendar_view(
+    space_name: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<calendar::CalendarView>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_id = match space_name {
+        Some(name) => match db::get_space_id(&conn, user_id.clone(), name).await {
+            Ok(id) => Some(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+        },
+        None => None,
+    };
+    let time_zone = resolve_time_zone(&conn, &user_id, space_id, tz).await?;
+    let today = habits::local_today(time_zone);
+    let (from, to) = match (&from, &to) {
+        (None, None) => {
+            let week_start = reports::Granularity::Week.bucket_start(today);
+            (week_start, week_start + chrono::Duration::days(6))
+        }
+        _ => date_range(from, to, today, 7, 366)?,
+    };
+    let failed = |_| status::Custom(Status::InternalServerError, Json("Failed to load the calendar".to_string()));
+
+    let sessions = db::get_calendar_sessions(
+        &conn,
+        user_id.clone(),
+        space_id,
+        reports::local_midnight_utc(from, time_zone),
+        reports::local_midnight_utc(day_after(to)?, time_zone),
+    )
+    .await
+    .map_err(failed)?;
+    let notes = db::get_notes_with_due_dates(&conn, user_id.clone(), space_id).await.map_err(failed)?;
+
+    let mut space_names: Vec<(i32, String)> = Vec::new();
+    let space_ids = sessions.iter().map(|(session, _)| session.space_id).chain(notes.iter().map(|note| note.space_id));
+    for id in space_ids {
+        if !space_names.iter().any(|(known, _)| *known == id) {
+            space_names.push((id, db::get_space_name(&conn, id).await.unwrap_or_default()));
+        }
+    }
+
+    // the newest open session, like `/track/current`
+    let open = db::get_open_sessions(&conn, user_id)
+        .await
+        .map_err(failed)?
+        .into_iter()
+        .find(|session| space_id.is_none_or(|space_id| session.space_id == space_id));
+    let running = match open {
+        Some(session) => {
+            let space_name = db::get_space_name(&conn, session.space_id).await.unwrap_or_default();
+            let session = with_session_intervals(&conn, session).await?;
+            Some(models::CurrentTimer { session, space_name })
+        }
+        None => None,
+    };
+
+    Ok(Json(calendar::CalendarView {
+        from,
+        to,
+        time_zone: time_zone.name().to_string(),
+        running,
+        days: calendar::build_days(from, to, time_zone, sessions, notes, &space_names),
+    }))
+}
+
+const FEED_DAYS: i64 = 90;
+const MAX_FEED_DAYS: i64 = 3650;
+
+#[get("/ical")]
+async fn get_ical_feed(jar: &CookieJar<'_>, conn: db::DbConn) -> Result<Json<serde_json::Value>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    match db::get_calendar_feed(&conn, user_id).await {
+        Ok(Some(feed)) => Ok(Json(json!({ "enabled": true, "created_at": feed.created_at, "last_used_at": feed.last_used_at }))),
+        Ok(None) => Ok(Json(json!({ "enabled": false }))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to load the feed".to_string()))),
+    }
+}
+
+// Makes a new secret url, the old one stops working. The token is only returned here.
+#[post("/ical")]
+async fn create_ical_feed(jar: &CookieJar<'_>, conn: db::DbConn) -> Result<Json<serde_json::Value>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let token = feeds::new_token();
+
+    let feed = models::CalendarFeed {
+        user_id,
+        token_hash: feeds::token_hash(&token),
+        created_at: chrono::Utc::now(),
+        last_used_at: None,
+    };
+    match db::save_calendar_feed(&conn, feed).await {
+        Ok(feed) => Ok(Json(json!({
+            "token": token,
+            "url": format!("/feeds/ical/{}.ics", token),
+            "created_at": feed.created_at,
+        }))),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to create the feed".to_string()))),
+    }
+}
+
+#[delete("/ical")]
+async fn delete_ical_feed(jar: &CookieJar<'_>, conn: db::DbConn) -> Result<Status, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    match db::delete_calendar_feed(&conn, user_id).await {
+        Ok(0) => Err(status::Custom(Status::NotFound, Json("No feed to revoke".to_string()))),
+        Ok(_) => Ok(Status::NoContent),
+        Err(_) => Err(status::Custom(Status::InternalServerError, Json("Failed to revoke the feed".to_string()))),
+    }
+}
+
+// The feed itself, no cookie needed. `space` can be given more than once to only
+// include those spaces, `days` is how far back sessions go.
+#[get("/ical/<token>?<space>&<days>")]
+async fn ical_feed(
+    token: &str,
+    space: Vec<String>,
+    days: Option<i64>,
+    conn: db::DbConn,
+) -> Result<(ContentType, ByteStream<impl futures::Stream<Item = Vec<u8>>>), status::Custom<Json<String>>> {
+    let token = token.strip_suffix(".ics").unwrap_or(token);
+    let user_id = match db::use_calendar_feed(&conn, feeds::token_hash(token)).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return Err(status::Custom(Status::NotFound, Json("Feed not found".to_string()))),
+        Err(_) => return Err(status::Custom(Status::InternalServerError, Json("Failed to load the feed".to_string()))),
+    };
+
+    let days = days.unwrap_or(FEED_DAYS);
+    if !(1..=MAX_FEED_DAYS).contains(&days) {
+        return Err(status::Custom(Status::BadRequest, Json(format!("days must be between 1 and {}", MAX_FEED_DAYS))));
+    }
+
+    // a space given twice would have its sessions in the feed twice
+    let mut seen = std::collections::HashSet::new();
+    let space: Vec<String> = space.into_iter().filter(|name| seen.insert(name.clone())).collect();
+
+    let mut space_ids = Vec::new();
+    for name in &space {
+        match db::get_space_id(&conn, user_id.clone(), name.clone()).await {
+            Ok(id) => space_ids.push(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+        }
+    }
+
+    let notes: Vec<StickyNote> = db::get_notes_with_due_dates(&conn, user_id.clone(), None)
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load the feed".to_string())))?
+        .into_iter()
+        .filter(|note| space_ids.is_empty() || space_ids.contains(&note.space_id))
+        .collect();
+
+    // one pass over all spaces, or one per requested space
+    let spaces: Vec<Option<i32>> = if space_ids.is_empty() { vec![None] } else { space_ids.iter().copied().map(Some).collect() };
+    let filters: Vec<models::SessionFilter> = spaces
+        .into_iter()
+        .map(|space_id| models::SessionFilter {
+            user_id: user_id.clone(),
+            space_id,
+            from: Some(chrono::Utc::now() - chrono::Duration::days(days)),
+            completed_only: true,
+            ..Default::default()
+        })
+        .collect();
+
+    let calendar_name = match space.as_slice() {
+        [] => "RustySpaces".to_string(),
+        names => format!("RustySpaces {}", names.join(", ")),
+    };
+
+    let stream = ByteStream! {
+        yield export::ics_header(&calendar_name);
+
+        for filter in filters {
+            let mut after = None;
+            loop {
+                // without END:VCALENDAR the client sees a broken feed rather than one
+                // that silently lacks events
+                let page = match db::get_sessions_page(&conn, filter.clone(), after, EXPORT_PAGE_SIZE).await {
+                    Ok(page) => page,
+                    Err(e) => {
+                        eprintln!("Error reading sessions for the calendar feed: {:?}", e);
+                        return;
+                    }
+                };
+
+                after = page.last().map(|session| (session.start_time, session.id));
+                for session in &page {
+                    if let Some(event) = export::session_event(session) {
+                        yield event;
+                    }
+                }
+
+                if (page.len() as i64) < EXPORT_PAGE_SIZE {
+                    break;
+                }
+            }
+        }
+
+        let mut space_names: Vec<(i32, String)> = Vec::new();
+        for note in &notes {
+            let space_name = match space_names.iter().find(|(id, _)| *id == note.space_id) {
+                Some((_, name)) => name.clone(),
+                None => {
+                    let name = db::get_space_name(&conn, note.space_id).await.unwrap_or_default();
+                    space_names.push((note.space_id, name.clone()));
+                    name
+                }
+            };
+            yield feeds::due_events(note, &space_name);
+        }
+
+        yield export::ics_footer();
+    };
+
+    Ok((ContentType::Calendar, stream))
+}
+
+// weekly reviews
+
+#[derive(Responder)]
+enum ReviewResponse {
+    Json(Json<serde_json::Value>),
+    Rendered(Box<Template>),
+}
+
+fn review_format(format: Option<String>) -> Result<review::ReviewFormat, status::Custom<Json<String>>> {
+    match format.as_deref() {
+        None => Ok(review::ReviewFormat::Json),
+        Some(value) => review::ReviewFormat::parse(value)
+            .ok_or_else(|| status::Custom(Status::BadRequest, Json("format must be json, markdown or html".to_string()))),
+    }
+}
+
+fn render_review(review: serde_json::Value, format: review::ReviewFormat) -> ReviewResponse {
+    match format.template() {
+        Some(name) => ReviewResponse::Rendered(Box::new(Template::render(name, &review))),
+        None => ReviewResponse::Json(Json(review)),
+    }
+}
+
+// The review of the week `week` (any day in it) falls in, the current week by default
+async fn build_weekly_review(
+    conn: &db::DbConn,
+    user_id: String,
+    space_name: Option<String>,
+    week: Option<String>,
+    tz: Option<String>,
+) -> Result<(i32, review::WeeklyReview), status::Custom<Json<String>>> {
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(conn, user_id.clone(), space_name.clone())
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+    let time_zone = resolve_time_zone(conn, &user_id, Some(space_id), tz).await?;
+    let day = match week {
+        Some(value) => parse_date(&value, "week")?,
+        None => habits::local_today(time_zone),
+    };
+
+    review::build_review(conn, user_id, space_id, space_name, day, time_zone)
+        .await
+        .map(|review| (space_id, review))
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to build the review".to_string())))
+}
+
+#[get("/weekly?<space_name>&<week>&<tz>&<format>")]
+async fn weekly_review(
+    space_name: Option<String>,
+    week: Option<String>,
+    tz: Option<String>,
+    format: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<ReviewResponse, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let format = review_format(format)?;
+    let (_, review) = build_weekly_review(&conn, user_id, space_name, week, tz).await?;
+
+    Ok(render_review(json!(review), format))
+}
+
+// Keeps the review as it is now, saving the same week again replaces it
+#[post("/weekly?<space_name>&<week>&<tz>")]
+async fn save_weekly_review(
+    space_name: Option<String>,
+    week: Option<String>,
+    tz: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<models::SavedReview>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let (space_id, review) = build_weekly_review(&conn, user_id.clone(), space_name, week, tz).await?;
+
+    let saved = models::SavedReview {
+        id: Uuid::new_v4(),
+        space_id,
+        user_id,
+        week_start: review.week_start,
+        review: json!(review),
+        created_at: chrono::Utc::now(),
+    };
+    db::save_weekly_review(&conn, saved)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to save the review".to_string())))
+}
+
+#[get("/?<space_name>")]
+async fn get_weekly_reviews(
+    space_name: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Json<Vec<models::SavedReview>>, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+
+    let space_name = space_name.ok_or_else(|| status::Custom(Status::BadRequest, Json("Missing space_name".to_string())))?;
+    let space_id = db::get_space_id(&conn, user_id.clone(), space_name)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Space not found".to_string())))?;
+
+    db::get_weekly_reviews(&conn, user_id, space_id)
+        .await
+        .map(Json)
+        .map_err(|_| status::Custom(Status::InternalServerError, Json("Failed to load reviews".to_string())))
+}
+
+#[get("/<review_id>?<format>")]
+async fn get_weekly_review(
+    review_id: String,
+    format: Option<String>,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<ReviewResponse, status::Custom<Json<String>>> {
+    let user_id = get_user_id(jar);
+    let format = review_format(format)?;
+    let review_id = Uuid::parse_str(&review_id).map_err(|_| status::Custom(Status::BadRequest, Json("Invalid review id".to_string())))?;
+
+    let saved = db::get_weekly_review(&conn, user_id, review_id)
+        .await
+        .map_err(|_| status::Custom(Status::NotFound, Json("Review not found".to_string())))?;
+    Ok(render_review(saved.review, format))
+}
+
+#[delete("/<review_id>")]
+async fn delete_weekly_review(
+    review_id: String,
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+) -> Result<Status, Status> {
+    let user_id = get_user_id(jar);
+    let review_id = Uuid::parse_str(&review_id).map_err(|_| Status::BadRequest)?;
+
+    match db::delete_weekly_review(&conn, user_id, review_id).await {
+        Ok(0) => Err(Status::NotFound),
+        Ok(_) => Ok(Status::Ok),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[derive(Default)]
+struct MusicState {
+    playlist: Mutex<Vec<String>>, // Store file names or paths
+    current_song: Mutex<Option<String>>, // Current song being played
+}
+
+struct CurrentFileName(Arc<RwLock<Option<String>>>);
+
+#[get("/play")]
+async fn stream_random_music(state: &State<CurrentFileName>) -> Result<(ContentType, ByteStream<impl futures::Stream<Item = Vec<u8>>>), Status> {
+    // List all files in the 'music' directory
+    let music_dir = Path::new("music/");
+    println!("Checking if music directory exists: {:?}", music_dir.display());
+
+    let read_dir = match rocket::tokio::fs::read_dir(music_dir).await {
+        Ok(read_dir) => {
+            println!("Successfully read the music directory.");
+            read_dir
+        },
+        Err(e) => {
+            println!("Error reading music directory: {:?}", e);
+            return Err(Status::InternalServerError);
+        },
+    };
+
+    // Convert ReadDir to a stream and collect entries
+    // Convert ReadDir to a stream and collect entries
+    let entries = ReadDirStream::new(read_dir)
+        .filter_map(|entry| match entry {
+            Ok(entry) => {
+                println!("Found entry: {:?}", entry.path().display());
+                Some(entry)
+            },
+            Err(e) => {
+                println!("Error reading directory entry: {:?}", e);
+                None
+            },
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+    // Filter entries to get file paths
+    let files: Vec<PathBuf> = entries
+        .iter()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let is_file = rocket::tokio::task::block_in_place(|| {
+                let metadata = std::fs::metadata(&path);
+                match metadata {
+                    Ok(metadata) => metadata.is_file(),
+                    Err(e) => {
+                        println!("Error getting metadata for file {}: {:?}", path.display(), e);
+                        false
+                    },
+                }
+            });
+            if is_file {
+                println!("File found: {:?}", path.display());
+                Some(path)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    if files.is_empty() {
+        println!("No files found in the music directory.");
+        return Err(Status::NotFound);
+    }
+
+    // Randomly select a file
     let random_file_path = match files.choose(&mut rand::thread_rng()) {
         Some(path) => path,
         None => {
@@ -652,3 +3494,124 @@ async fn next_song(state: &State<MusicState>) -> Result<Json<String>, Status> {
     Ok(Json(next_song))
 }
 
+// invoices
+
+#[derive(Responder)]
+enum InvoiceResponse {
+    Json(Json<invoices::InvoiceDraft>),
+    Csv(Download<Vec<u8>>),
+    Rendered(Box<Template>),
+}
+
+// A draft over the billable time between `from` and `to` (inclusive, this month so far
+// by default), for one space or all of them. `round` (minutes) and `rounding` (up, down
+// or nearest) replace the rounding set on the spaces.
+#[derive(FromForm)]
+struct InvoiceQuery {
+    space_name: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+    round: Option<i64>,
+    rounding: Option<String>,
+    format: Option<String>,
+}
+
+#[get("/draft?<query..>")]
+async fn invoice_draft(
+    jar: &CookieJar<'_>,
+    conn: db::DbConn,
+    query: InvoiceQuery,
+) -> Result<InvoiceResponse, status::Custom<Json<String>>> {
+    use chrono::Datelike;
+
+    let user_id = get_user_id(jar);
+    let InvoiceQuery { space_name, from, to, tz, round, rounding, format } = query;
+
+    let format = match format.as_deref() {
+        None => invoices::InvoiceFormat::Json,
+        Some(value) => invoices::InvoiceFormat::parse(value)
+            .ok_or_else(|| status::Custom(Status::BadRequest, Json("format must be json, csv or html".to_string())))?,
+    };
+    let mode = match rounding.as_deref() {
+        None => invoices::RoundingMode::Up,
+        Some(value) => invoices::RoundingMode::parse(value)
+            .ok_or_else(|| status::Custom(Status::BadRequest, Json("rounding must be up, down or nearest".to_string())))?,
+    };
+    let rounding = match round {
+        Some(minutes) if !(1..=invoices::MAX_ROUNDING_MINUTES).contains(&minutes) => {
+            return Err(status::Custom(
+                Status::BadRequest,
+                Json(format!("round must be between 1 and {} minutes", invoices::MAX_ROUNDING_MINUTES)),
+            ));
+        }
+        Some(minutes) => Some(invoices::Rounding { minutes, mode }),
+        None => None,
+    };
+
+    let space_id = match &space_name {
+        Some(name) => match db::get_space_id(&conn, user_id.clone(), name.clone()).await {
+            Ok(id) => Some(id),
+            Err(_) => return Err(status::Custom(Status::NotFound, Json("Space not found".to_string()))),
+        },
+        None => None,
+    };
+    let time_zone = resolve_time_zone(&conn, &user_id, space_id, tz).await?;
+    let today = habits::local_today(time_zone);
+    let (from, to) = match from {
+        Some(_) => date_range(from, to, today, 31, 366)?,
+        None => {
+            let (_, to) = date_range(None, to, today, 1, 366)?;
+            (to.with_day(1).unwrap_or(to), to)
+        }
+    };
+    let failed = |_| status::Custom(Status::InternalServerError, Json("Failed to build the invoice".to_string()));
+
+    let mut clients = Vec::new();
+    let mut activities = Vec::new();
+    for (space, settings) in db::get_spaces_with_settings(&conn, user_id.clone(), space_id).await.map_err(failed)? {
+        activities.extend(db::get_activities(&conn, user_id.clone(), space.id, true).await.map_err(failed)?);
+        let settings = settings.unwrap_or(models::SpaceSettings {
+            space_id: space.id,
+            updated_at: chrono::Utc::now(),
+            ..Default::default()
+        });
+        clients.push(invoices::Client { space, settings });
+    }
+
+    let filter = models::SessionFilter {
+        user_id,
+        space_id,
+        from: Some(reports::local_midnight_utc(from, time_zone)),
+        to: Some(reports::local_midnight_utc(day_after(to)?, time_zone)),
+        completed_only: true,
+        ..Default::default()
+    };
+    let mut sessions = Vec::new();
+    let mut after = None;
+    loop {
+        let page = db::get_sessions_page(&conn, filter.clone(), after, EXPORT_PAGE_SIZE).await.map_err(failed)?;
+        after = page.last().map(|session| (session.start_time, session.id));
+        let done = (page.len() as i64) < EXPORT_PAGE_SIZE;
+        sessions.extend(page);
+        if done {
+            break;
+        }
+    }
+
+    let draft = invoices::build_invoice(from, to, time_zone.name().to_string(), &sessions, &activities, &clients, rounding);
+
+    Ok(match format {
+        invoices::InvoiceFormat::Json => InvoiceResponse::Json(Json(draft)),
+        invoices::InvoiceFormat::Csv => {
+            let file_name = format!("invoice_{}_{}_{}.csv", space_name.as_deref().unwrap_or("all"), from, to)
+                .replace(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '_' && c != '-', "_");
+            InvoiceResponse::Csv(Download {
+                inner: invoices::invoice_csv(&draft),
+                content_type: ContentType::CSV,
+                disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", file_name)),
+            })
+        }
+        invoices::InvoiceFormat::Html => InvoiceResponse::Rendered(Box::new(Template::render("invoice", &draft))),
+    })
+}
@@ -1,8 +1,27 @@
-use diesel::{Queryable, Insertable, Selectable};
+use diesel::{AsChangeset, Queryable, QueryableByName, Insertable, Selectable};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use super::schema::sticky_notes;
 use super::schema::time_tracking_sessions;
+use super::schema::note_attachments;
+use super::schema::note_links;
+use super::schema::space_settings;
+use super::schema::session_intervals;
+use super::schema::session_audit_log;
+use super::schema::pomodoro_runs;
+use super::schema::pomodoros;
+use super::schema::activities;
+use super::schema::activity_budgets;
+use super::schema::budget_notifications;
+use super::schema::habits;
+use super::schema::habit_completions;
+use super::schema::line_check_events;
+use super::schema::user_settings;
+use super::schema::goals;
+use super::schema::goal_snapshots;
+use super::schema::note_deletions;
+use super::schema::weekly_reviews;
+use super::schema::calendar_feeds;
 
 
 #[derive(Queryable, Serialize, Deserialize)]
@@ -21,26 +40,267 @@ pub struct NewSpace {
     pub space_name: String,
 }
 
+pub const DEFAULT_NOTE_COLOR: &str = "FFEB3B";
+pub const DEFAULT_NOTE_TEXT_COLOR: &str = "000000";
+
+#[derive(Queryable, Insertable, AsChangeset, Serialize, Deserialize, Default)]
+#[diesel(table_name = space_settings, treat_none_as_null = true)]
+pub struct SpaceSettings {
+    pub space_id: i32,
+    pub description: Option<String>,
+    pub icon: Option<String>, // emoji or icon name
+    pub default_color: Option<String>,
+    pub default_text_color: Option<String>,
+    pub background: Option<String>,
+    pub time_zone: Option<String>, // IANA name, e.g. "Europe/Berlin"
+    pub default_playlist: Option<String>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub client_name: Option<String>, // on invoices, the space name when empty
+    pub billable: bool, // every activity of the space, not only the billable ones
+    pub hourly_rate_cents: Option<i64>, // for activities without a rate of their own
+    pub currency: Option<String>, // ISO 4217 code, e.g. "EUR"
+    pub rounding_minutes: Option<i32>, // each session is rounded to a multiple of this
+    pub rounding_mode: Option<String>, // up, down or nearest (default up)
+}
+
+#[derive(Deserialize)]
+pub struct UpdateSpaceSettings {
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub default_color: Option<String>,
+    pub default_text_color: Option<String>,
+    pub background: Option<String>,
+    pub time_zone: Option<String>,
+    pub default_playlist: Option<String>,
+}
+
+// The invoicing part of the space settings, read and written on its own
+#[derive(Serialize, Deserialize, Default)]
+pub struct BillingSettings {
+    pub client_name: Option<String>,
+    #[serde(default)]
+    pub billable: bool,
+    pub hourly_rate_cents: Option<i64>,
+    pub currency: Option<String>,
+    pub rounding_minutes: Option<i32>,
+    pub rounding_mode: Option<String>,
+}
+
+impl From<&SpaceSettings> for BillingSettings {
+    fn from(settings: &SpaceSettings) -> Self {
+        BillingSettings {
+            client_name: settings.client_name.clone(),
+            billable: settings.billable,
+            hourly_rate_cents: settings.hourly_rate_cents,
+            currency: settings.currency.clone(),
+            rounding_minutes: settings.rounding_minutes,
+            rounding_mode: settings.rounding_mode.clone(),
+        }
+    }
+}
+
+// Preferences that follow the user across spaces
+#[derive(Queryable, Insertable, AsChangeset, Serialize, Deserialize, Default)]
+#[diesel(table_name = user_settings, treat_none_as_null = true)]
+pub struct UserSettings {
+    pub user_id: String,
+    pub time_zone: Option<String>, // IANA name, wins over the space time zone for reports, budgets and habits
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateUserSettings {
+    pub time_zone: Option<String>,
+}
+
+// The user's iCal feed, the token itself is only shown once when it is made
+#[derive(Queryable, Insertable, Serialize, Clone)]
+#[diesel(table_name = calendar_feeds)]
+pub struct CalendarFeed {
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct StickyLine {
+    pub id: String, // stable id of the line, empty until it was stored once
     pub text: String,
     pub color: String,
     pub is_checked: bool,
+    pub parent: Option<String>, // id of the parent line within the same note, an index from older clients
+    pub progress: Option<u8>, // manual progress in percent (0 - 100)
+    pub due: Option<chrono::NaiveDate>,
 }
 
 impl StickyLine {
+    // stored as `text|color|is_checked` with optional `|parent|progress|due|id` on the end,
+    // `due` as 2024-08-25
     pub fn from_string(s: &str) -> Self {
         let parts: Vec<&str> = s.split('|').collect();
         StickyLine {
+            id: parts.get(6).unwrap_or(&"").to_string(),
             text: parts.get(0).unwrap_or(&"").to_string(),
             color: parts.get(1).unwrap_or(&"").to_string(),
             is_checked: parts.get(2).unwrap_or(&"false").parse().unwrap_or(false),
+            parent: parts.get(3).filter(|p| !p.is_empty()).map(|p| p.to_string()),
+            progress: parts.get(4).and_then(|p| p.parse::<u8>().ok()).map(|p| p.min(100)),
+            due: parts.get(5).and_then(|d| d.parse().ok()),
         }
     }
 
     pub fn to_string(&self) -> String {
-        format!("{}|{}|{}", self.text, self.color, self.is_checked)
+        // keep the old three part format for plain lines so existing clients are untouched
+        if self.id.is_empty() && self.parent.is_none() && self.progress.is_none() && self.due.is_none() {
+            return format!("{}|{}|{}", self.text, self.color, self.is_checked);
+        }
+
+        let mut line = format!(
+            "{}|{}|{}|{}|{}",
+            self.text,
+            self.color,
+            self.is_checked,
+            self.parent.clone().unwrap_or_default(),
+            self.progress.map(|p| p.to_string()).unwrap_or_default(),
+        );
+        if self.due.is_some() || !self.id.is_empty() {
+            line.push_str(&format!("|{}", self.due.map(|due| due.to_string()).unwrap_or_default()));
+        }
+        if !self.id.is_empty() {
+            line.push_str(&format!("|{}", self.id));
+        }
+        line
+    }
+
+    // a checked line is always done, otherwise fall back to the manual progress
+    fn own_progress(&self) -> f64 {
+        if self.is_checked {
+            100.0
+        } else {
+            self.progress.map(f64::from).unwrap_or(0.0)
+        }
+    }
+}
+
+// Position of the parent line, found by its id or (from older clients) by its index
+fn parent_index(lines: &[StickyLine], parent: &str) -> Option<usize> {
+    lines
+        .iter()
+        .position(|line| !line.id.is_empty() && line.id == parent)
+        .or_else(|| parent.parse::<usize>().ok().filter(|&index| index < lines.len()))
+}
+
+pub fn new_line_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+// Gives every line an id before it is stored. A line sent without one (or with one a line
+// above already has) keeps the id of an old line with the same text, or gets a new one.
+// Parents are stored by id, so they follow their line wherever it is moved.
+pub fn assign_line_ids(old: &[StickyLine], mut lines: Vec<StickyLine>) -> Vec<StickyLine> {
+    let parents: Vec<Option<usize>> = lines
+        .iter()
+        .map(|line| line.parent.as_deref().and_then(|parent| parent_index(&lines, parent)))
+        .collect();
+
+    let mut taken: Vec<String> = Vec::new();
+    for i in 0..lines.len() {
+        let id = &lines[i].id;
+        if id.is_empty() || taken.contains(id) {
+            let sent_later = |id: &String| lines[i + 1..].iter().any(|line| line.id == *id);
+            lines[i].id = old
+                .iter()
+                .find(|line| !line.id.is_empty() && line.text == lines[i].text && !taken.contains(&line.id) && !sent_later(&line.id))
+                .map_or_else(new_line_id, |line| line.id.clone());
+        }
+        taken.push(lines[i].id.clone());
+    }
+
+    for (i, parent) in parents.into_iter().enumerate() {
+        lines[i].parent = parent.map(|parent| lines[parent].id.clone());
     }
+    lines
+}
+
+// Rolls progress up from child lines: a line with children is worth the average
+// of its children, a leaf line is worth its own progress.
+pub fn line_progress(lines: &[StickyLine]) -> Vec<f64> {
+    fn resolve(index: usize, lines: &[StickyLine], parents: &[Option<usize>], cache: &mut Vec<Option<f64>>, visiting: &mut Vec<bool>) -> f64 {
+        if let Some(value) = cache[index] {
+            return value;
+        }
+        // a parent loop means broken data, treat the line as a leaf
+        if visiting[index] {
+            return lines[index].own_progress();
+        }
+        visiting[index] = true;
+
+        let children: Vec<usize> = parents
+            .iter()
+            .enumerate()
+            .filter(|(i, parent)| *i != index && **parent == Some(index))
+            .map(|(i, _)| i)
+            .collect();
+
+        let value = if children.is_empty() || lines[index].is_checked {
+            lines[index].own_progress()
+        } else {
+            children.iter().map(|&child| resolve(child, lines, parents, cache, visiting)).sum::<f64>() / children.len() as f64
+        };
+
+        visiting[index] = false;
+        cache[index] = Some(value);
+        value
+    }
+
+    let parents: Vec<Option<usize>> = lines
+        .iter()
+        .map(|line| line.parent.as_deref().and_then(|parent| parent_index(lines, parent)))
+        .collect();
+    let mut cache = vec![None; lines.len()];
+    let mut visiting = vec![false; lines.len()];
+    (0..lines.len()).map(|i| resolve(i, lines, &parents, &mut cache, &mut visiting)).collect()
+}
+
+// Titles referenced as `[[note title]]` anywhere in the lines, without duplicates
+pub fn wiki_link_titles(lines: &[StickyLine]) -> Vec<String> {
+    let mut titles: Vec<String> = Vec::new();
+
+    for line in lines {
+        let mut rest = line.text.as_str();
+        while let Some(start) = rest.find("[[") {
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("]]") else { break };
+
+            let title = after[..end].trim();
+            if !title.is_empty() && !titles.iter().any(|t| t == title) {
+                titles.push(title.to_string());
+            }
+            rest = &after[end + 2..];
+        }
+    }
+
+    titles
+}
+
+// Completion of a whole note, averaged over its top level lines
+pub fn note_completion(lines: &[StickyLine]) -> f64 {
+    let progress = line_progress(lines);
+    let top_level: Vec<f64> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.parent.as_deref().and_then(|parent| parent_index(lines, parent)).is_none())
+        .map(|(i, _)| progress[i])
+        .collect();
+
+    if top_level.is_empty() {
+        return 0.0;
+    }
+
+    let completion = top_level.iter().sum::<f64>() / top_level.len() as f64;
+    (completion * 10.0).round() / 10.0
 }
 
 #[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
@@ -52,10 +312,43 @@ pub struct StickyNote {
     pub title: String,
     pub color: String,
     pub text_color: String,
-    pub created_at: chrono::NaiveDateTime,
-    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
     pub tags: Option<Vec<String>>, // Option to handle Nullable in the database
     pub lines: Option<Vec<String>>, // Option to handle Nullable in the database
+    pub archived: bool,
+    pub due_date: Option<chrono::NaiveDate>,
+}
+
+impl StickyNote {
+    pub fn sticky_lines(&self) -> Vec<StickyLine> {
+        self.lines
+            .as_ref()
+            .map(|lines| lines.iter().map(|line| StickyLine::from_string(line)).collect())
+            .unwrap_or_default()
+    }
+}
+
+// note as returned to the board, with the rolled up progress alongside
+#[derive(Serialize)]
+pub struct StickyNoteWithProgress {
+    #[serde(flatten)]
+    pub note: StickyNote,
+    pub completion: f64,
+    pub line_progress: Vec<f64>,
+    pub tracked_seconds: i64, // time tracked against the note, filled in by the board
+}
+
+impl From<StickyNote> for StickyNoteWithProgress {
+    fn from(note: StickyNote) -> Self {
+        let lines = note.sticky_lines();
+        StickyNoteWithProgress {
+            completion: note_completion(&lines),
+            line_progress: line_progress(&lines),
+            tracked_seconds: 0,
+            note,
+        }
+    }
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
@@ -80,31 +373,753 @@ pub struct UpdateHeader {
 #[diesel(table_name = sticky_notes)]
 pub struct NewStickyNote {
     pub title: String,
-    pub color: String,
-    pub text_color: String,
+    pub color: Option<String>, // falls back to the space default when left out
+    pub text_color: Option<String>,
     pub tags: Option<Vec<String>>,
     pub lines: Option<Vec<String>>, // Array of strings for lines
+    pub due_date: Option<chrono::NaiveDate>,
 }
 
+#[derive(Deserialize)]
+pub struct NoteDueDate {
+    pub due_date: Option<chrono::NaiveDate>, // null clears it
+}
 
-// time tracking
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = note_attachments)]
+pub struct NoteAttachment {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub user_id: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    #[serde(skip_serializing)]
+    pub thumbnail_key: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+
+pub const LINK_KIND_WIKI: &str = "wiki";
+pub const LINK_KIND_EXPLICIT: &str = "explicit";
 
 #[derive(Queryable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = note_links)]
+pub struct NoteLink {
+    pub id: Uuid,
+    pub source_note_id: Uuid,
+    pub target_note_id: Option<Uuid>, // None once the target note has been deleted
+    pub target_title: String,
+    pub kind: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize)]
+pub struct NoteLinkView {
+    #[serde(flatten)]
+    pub link: NoteLink,
+    pub broken: bool,
+}
+
+impl From<NoteLink> for NoteLinkView {
+    fn from(link: NoteLink) -> Self {
+        NoteLinkView {
+            broken: link.target_note_id.is_none(),
+            link,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Backlink {
+    #[serde(flatten)]
+    pub link: NoteLink,
+    pub source_title: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewNoteLink {
+    pub target_note_id: Uuid,
+}
+
+
+// bulk operations, applied to every note in `note_ids`
+#[derive(Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    Delete,
+    Archive,
+    Unarchive,
+    Recolor { color: Option<String>, text_color: Option<String> },
+    AddTags { tags: Vec<String> },
+    RemoveTags { tags: Vec<String> },
+    Move { space_name: String },
+}
+
+#[derive(Deserialize)]
+pub struct BulkRequest {
+    pub note_ids: Vec<Uuid>,
+    #[serde(flatten)]
+    pub action: BulkAction,
+}
+
+#[derive(Serialize)]
+pub struct BulkItemResult {
+    pub note_id: Uuid,
+    pub status: String, // "ok", "not_found" or "error"
+    pub error: Option<String>,
+}
+
+
+// time tracking
+
+// Something time is tracked for, one per name and space. Sessions keep a copy
+// of the name in `activity_name` so older clients and exports keep working.
+#[derive(Queryable, QueryableByName, Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = activities)]
+pub struct Activity {
+    pub id: Uuid,
+    pub space_id: i32,
+    pub user_id: String,
+    pub name: String,
+    pub color: Option<String>,
+    pub category: Option<String>,
+    pub billable: bool,
+    pub hourly_rate_cents: Option<i64>,
+    pub archived: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct NewActivity {
+    pub name: String,
+    pub color: Option<String>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub billable: bool,
+    pub hourly_rate_cents: Option<i64>,
+}
+
+// fields left out stay as they are
+#[derive(Deserialize, AsChangeset, Default)]
+#[diesel(table_name = activities)]
+pub struct UpdateActivity {
+    pub name: Option<String>,
+    pub color: Option<String>,
+    pub category: Option<String>,
+    pub billable: Option<bool>,
+    pub hourly_rate_cents: Option<i64>,
+    pub archived: Option<bool>,
+}
+
+#[derive(Queryable, Insertable, Serialize, Deserialize, Clone, Debug)]
 #[diesel(table_name = time_tracking_sessions)]
 pub struct TimeTrackingSession {
     pub id: Uuid,
     pub user_id: String,
     pub space_id: i32,
     pub activity_name: String,
-    pub start_time: chrono::NaiveDateTime,
-    pub end_time: Option<chrono::NaiveDateTime>,
+    pub start_time: chrono::DateTime<chrono::Utc>,
+    pub end_time: Option<chrono::DateTime<chrono::Utc>>,
     pub duration: Option<i64>,
+    pub activity_id: Option<Uuid>,
+    pub notes: Option<String>,
+    pub session_type: String,
+    pub last_heartbeat_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub auto_stopped: bool, // closed by the server, see `reviewed_at`
+    pub auto_stop_reason: Option<String>,
+    pub reviewed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub note_id: Option<Uuid>, // the sticky note the time was spent on
+    pub tags: Vec<String>, // lower case, without a leading #
+    pub note_line_id: Option<String>, // and optionally one of its lines, see `StickyLine::id`
 }
 
-#[derive(Queryable, Insertable, Serialize, Deserialize)]
-#[diesel(table_name = time_tracking_sessions)]
+// What a new timer carries besides its activity
+#[derive(Default)]
+pub struct SessionDetails {
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+    pub note_id: Option<Uuid>,
+    pub note_line_id: Option<String>,
+}
+
+// Time spent on a note in total and per line, sessions without a line only count
+// towards the total
+#[derive(Serialize, Clone, Default)]
+pub struct NoteTime {
+    pub note_id: Uuid,
+    pub total_seconds: i64,
+    pub sessions: i64,
+    pub lines: Vec<NoteLineTime>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct NoteLineTime {
+    pub line_id: String,
+    pub line: Option<i32>, // where the line is now, None (like `text`) once it is gone from the note
+    pub text: Option<String>,
+    pub total_seconds: i64,
+    pub sessions: i64,
+}
+
+// Body of `POST /notes/<note_id>/track`, everything is optional. Without an
+// activity the note title is used as the activity name.
+#[derive(Deserialize, Default)]
+pub struct NoteTimerStart {
+    pub line_id: Option<String>,
+    pub line: Option<i32>, // by index, for older clients
+    pub activity_id: Option<Uuid>,
+    pub activity_name: Option<String>,
+    #[serde(default, deserialize_with = "client_time::deserialize_option")]
+    pub manual_start_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+pub const SESSION_TYPE_REGULAR: &str = "regular";
+pub const SESSION_TYPE_POMODORO: &str = "pomodoro";
+
+// "max 2h a day" or "min 10h a week" for one activity, `period` is day, week or
+// month and `kind` is max or min
+#[derive(Queryable, Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = activity_budgets)]
+pub struct ActivityBudget {
+    pub id: Uuid,
+    pub activity_id: Uuid,
+    pub user_id: String,
+    pub period: String,
+    pub kind: String,
+    pub limit_seconds: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct NewActivityBudget {
+    pub activity_id: Uuid,
+    pub period: String,
+    pub kind: String,
+    pub limit_seconds: i64,
+}
+
+#[derive(Queryable, Insertable, Serialize, Clone)]
+#[diesel(table_name = budget_notifications)]
+pub struct BudgetNotification {
+    pub budget_id: Uuid,
+    pub period_start: chrono::NaiveDate,
+    pub tracked_seconds: i64,
+    pub sent_at: chrono::DateTime<chrono::Utc>,
+}
+
+// A daily habit, `kind` is checklist (checked off by hand or through the note line
+// `note_id`/`line_id`) or time (`target_seconds` of `activity_id` every day)
+#[derive(Queryable, Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = habits)]
+pub struct Habit {
+    pub id: Uuid,
+    pub space_id: i32,
+    pub user_id: String,
+    pub name: String,
+    pub kind: String,
+    pub activity_id: Option<Uuid>,
+    pub target_seconds: Option<i64>,
+    pub note_id: Option<Uuid>,
+    pub line_text: Option<String>, // the line as it read when the habit was made
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub line_id: Option<String>, // None for lines from before lines had ids, the text stands in
+}
+
+// A time habit needs an activity (by id or name) and a target, a checklist habit may
+// follow one line of a note, its name defaults to the line text
+#[derive(Deserialize)]
+pub struct NewHabit {
+    #[serde(default)]
+    pub name: String,
+    pub kind: String,
+    pub activity_id: Option<Uuid>,
+    pub activity_name: Option<String>,
+    pub target_seconds: Option<i64>,
+    pub note_id: Option<Uuid>,
+    pub line_id: Option<String>,
+    pub line: Option<i32>, // by index, for older clients
+}
+
+#[derive(Queryable, Insertable, Serialize, Clone)]
+#[diesel(table_name = habit_completions)]
+pub struct HabitCompletion {
+    pub habit_id: Uuid,
+    pub day: chrono::NaiveDate,
+    pub completed_at: chrono::DateTime<chrono::Utc>,
+}
+
+// "30 hours of Rust this month" (kind time, `target` in seconds of `activity_id`) or
+// "5 blog posts this month" (kind checklist, `target` in lines checked in `note_id`,
+// in any note of the space when None) between `start_date` and `end_date` (inclusive)
+#[derive(Queryable, Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = goals)]
+pub struct Goal {
+    pub id: Uuid,
+    pub space_id: i32,
+    pub user_id: String,
+    pub name: String,
+    pub kind: String,
+    pub target: i64,
+    pub activity_id: Option<Uuid>,
+    pub note_id: Option<Uuid>,
+    pub start_date: chrono::NaiveDate,
+    pub end_date: chrono::NaiveDate,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Without dates the goal covers the current `period` (day, week or month, month by default)
+#[derive(Deserialize)]
+pub struct NewGoal {
+    #[serde(default)]
+    pub name: String,
+    pub kind: String,
+    pub target: i64,
+    pub activity_id: Option<Uuid>,
+    pub activity_name: Option<String>,
+    pub note_id: Option<Uuid>,
+    pub period: Option<String>,
+    pub start_date: Option<chrono::NaiveDate>,
+    pub end_date: Option<chrono::NaiveDate>,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Serialize, Clone)]
+#[diesel(table_name = goal_snapshots)]
+pub struct GoalSnapshot {
+    pub goal_id: Uuid,
+    pub day: chrono::NaiveDate,
+    pub progress: i64,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+}
+
+// What a note was when it got deleted
+#[derive(Queryable, Insertable, Serialize, Clone)]
+#[diesel(table_name = note_deletions)]
+pub struct NoteDeletion {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub space_id: i32,
+    pub user_id: String,
+    pub title: String,
+    pub lines: Option<Vec<String>>,
+    pub deleted_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<&StickyNote> for NoteDeletion {
+    fn from(note: &StickyNote) -> Self {
+        NoteDeletion {
+            id: Uuid::new_v4(),
+            note_id: note.id,
+            space_id: note.space_id,
+            user_id: note.user_id.clone(),
+            title: note.title.clone(),
+            lines: note.lines.clone(),
+            deleted_at: chrono::Utc::now(),
+        }
+    }
+}
+
+// A weekly review as it was saved, `review` holds a `review::WeeklyReview`
+#[derive(Queryable, Insertable, Serialize, Clone)]
+#[diesel(table_name = weekly_reviews)]
+pub struct SavedReview {
+    pub id: Uuid,
+    pub space_id: i32,
+    pub user_id: String,
+    pub week_start: chrono::NaiveDate,
+    pub review: serde_json::Value,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Queryable, Insertable, Serialize, Clone)]
+#[diesel(table_name = line_check_events)]
+pub struct LineCheckEvent {
+    pub id: Uuid,
+    pub note_id: Uuid,
+    pub user_id: String,
+    pub space_id: i32,
+    pub line_text: String,
+    pub checked: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub line_id: Option<String>,
+}
+
+// Lines whose checked state differs between two versions of a note. Lines are told apart
+// by their id, a new line that starts out checked counts as checked.
+pub fn line_check_changes<'a>(old: &[StickyLine], new: &'a [StickyLine]) -> Vec<&'a StickyLine> {
+    new.iter()
+        .filter(|line| !line.text.trim().is_empty())
+        .filter(|line| {
+            let was_checked = old.iter().any(|old_line| old_line.id == line.id && old_line.is_checked);
+            line.is_checked != was_checked
+        })
+        .collect()
+}
+
+// one stretch of active time, a session is split up by every pause
+#[derive(Queryable, Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = session_intervals)]
+pub struct SessionInterval {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub start_time: chrono::DateTime<chrono::Utc>,
+    pub end_time: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Cuts the intervals down to a new `start`..`end` (None while the session runs) and
+// stretches the outer ones out to it, so pauses inside the range are kept
+pub fn fit_intervals(
+    intervals: &[SessionInterval],
+    session_id: Uuid,
+    start: chrono::DateTime<chrono::Utc>,
+    end: Option<chrono::DateTime<chrono::Utc>>,
+) -> Vec<SessionInterval> {
+    let mut fitted: Vec<SessionInterval> = intervals
+        .iter()
+        .filter(|interval| end.is_none_or(|end| interval.start_time < end))
+        .filter(|interval| interval.end_time.is_none_or(|interval_end| interval_end > start))
+        .cloned()
+        .collect();
+    fitted.sort_by_key(|interval| interval.start_time);
+
+    if fitted.is_empty() {
+        return vec![SessionInterval { id: Uuid::new_v4(), session_id, start_time: start, end_time: end }];
+    }
+    if let Some(first) = fitted.first_mut() {
+        first.start_time = start;
+    }
+    if let (Some(last), Some(end)) = (fitted.last_mut(), end) {
+        last.end_time = Some(end);
+    }
+    fitted
+}
+
+// total active seconds, an interval that is still open counts up to `until`
+pub fn active_seconds(intervals: &[SessionInterval], until: chrono::DateTime<chrono::Utc>) -> i64 {
+    let millis: i64 = intervals
+        .iter()
+        .map(|interval| {
+            let end = interval.end_time.unwrap_or(until);
+            end.signed_duration_since(interval.start_time).num_milliseconds().max(0)
+        })
+        .sum();
+    millis / 1000
+}
+
+#[derive(Serialize)]
+pub struct TimeTrackingSessionWithIntervals {
+    #[serde(flatten)]
+    pub session: TimeTrackingSession,
+    pub paused: bool,
+    pub intervals: Vec<SessionInterval>,
+}
+
+impl TimeTrackingSessionWithIntervals {
+    pub fn new(session: TimeTrackingSession, intervals: Vec<SessionInterval>) -> Self {
+        let paused = session.end_time.is_none()
+            && !intervals.is_empty()
+            && intervals.iter().all(|interval| interval.end_time.is_some());
+
+        TimeTrackingSessionWithIntervals { session, paused, intervals }
+    }
+}
+
+// pomodoro
+
+#[derive(Queryable, Insertable, AsChangeset, Serialize, Clone)]
+#[diesel(table_name = pomodoro_runs, treat_none_as_null = true)]
+pub struct PomodoroRun {
+    pub id: Uuid,
+    pub user_id: String,
+    pub space_id: i32,
+    pub activity_id: Uuid,
+    pub work_seconds: i64,
+    pub short_break_seconds: i64,
+    pub long_break_seconds: i64,
+    pub long_break_every: i32,
+    pub phase: String,
+    pub phase_started_at: chrono::DateTime<chrono::Utc>,
+    pub phase_ends_at: chrono::DateTime<chrono::Utc>,
+    pub completed_pomodoros: i32,
+    pub session_id: Option<Uuid>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub ended_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Queryable, Insertable, Serialize)]
+#[diesel(table_name = pomodoros)]
+pub struct Pomodoro {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub user_id: String,
+    pub space_id: i32,
+    pub activity_id: Uuid,
+    pub session_id: Option<Uuid>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub completed_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Lengths left out fall back to the server defaults in `time_tracking.pomodoro`
+#[derive(Deserialize)]
+pub struct NewPomodoroRun {
+    #[serde(default)]
+    pub activity_name: String,
+    pub activity_id: Option<Uuid>,
+    pub work_seconds: Option<i64>,
+    pub short_break_seconds: Option<i64>,
+    pub long_break_seconds: Option<i64>,
+    pub long_break_every: Option<i32>,
+}
+
+// A session entered after the fact, both ends are required
+#[derive(Deserialize)]
+pub struct ManualSession {
+    #[serde(default)]
+    pub activity_name: String,
+    pub activity_id: Option<Uuid>,
+    #[serde(deserialize_with = "client_time::deserialize")]
+    pub start_time: chrono::DateTime<chrono::Utc>,
+    #[serde(deserialize_with = "client_time::deserialize")]
+    pub end_time: chrono::DateTime<chrono::Utc>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub note_id: Option<Uuid>,
+    pub note_line_id: Option<String>,
+    pub note_line: Option<i32>, // by index, for older clients
+}
+
+// Fields left out keep their value, an empty `notes` clears them and `tags`
+// replaces the whole list
+#[derive(Deserialize)]
+pub struct SessionEdit {
+    pub activity_name: Option<String>,
+    pub activity_id: Option<Uuid>,
+    #[serde(default, deserialize_with = "client_time::deserialize_option")]
+    pub start_time: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, deserialize_with = "client_time::deserialize_option")]
+    pub end_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub notes: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+// A checked `SessionEdit`, with the range it ends up with. An activity given by name
+// is only looked up (or created) once the range was found to be free.
+pub struct SessionChanges {
+    pub start_time: chrono::DateTime<chrono::Utc>,
+    pub end_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub activity: Option<ActivityChoice>,
+    pub notes: Option<Option<String>>, // Some(None) clears them
+    pub tags: Option<Vec<String>>,
+}
+
+pub enum ActivityChoice {
+    Existing(Activity),
+    Named(String),
+}
+
+// How starting a timer went, a user runs one at a time
+pub enum TimerStart<T> {
+    Started(T, Option<crate::pomodoro::PhaseChange>), // with the pomodoro run stopped to make room
+    Running,  // another timer runs and is kept
+    TooEarly, // the running timer would have to stop before it was last started
+}
+
+// How a manual entry or an edit went, they are refused when they overlap another session
+pub enum SessionSaveOutcome {
+    Saved(TimeTrackingSession),
+    Overlaps(TimeTrackingSession), // the first of the user's other sessions in the range
+}
+
+// `before` and `after` are the whole session row as JSON
+#[derive(Queryable, Insertable, Serialize)]
+#[diesel(table_name = session_audit_log)]
+pub struct SessionAuditEntry {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub user_id: String,
+    pub action: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl SessionAuditEntry {
+    pub fn new(action: &str, before: Option<&TimeTrackingSession>, after: Option<&TimeTrackingSession>) -> Self {
+        let session = after.or(before);
+        SessionAuditEntry {
+            id: Uuid::new_v4(),
+            session_id: session.map(|session| session.id).unwrap_or_default(),
+            user_id: session.map(|session| session.user_id.clone()).unwrap_or_default(),
+            action: action.to_string(),
+            before: before.and_then(|session| serde_json::to_value(session).ok()),
+            after: after.and_then(|session| serde_json::to_value(session).ok()),
+            created_at: chrono::Utc::now(),
+        }
+    }
+}
+
+// which sessions to read when listing or exporting, times are UTC
+#[derive(Clone, Default)]
+pub struct SessionFilter {
+    pub user_id: String,
+    pub space_id: Option<i32>,
+    pub activity_name: Option<String>,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub completed_only: bool,
+    pub search: Option<String>, // part of the notes or the activity name
+    pub tags: Vec<String>, // sessions carrying all of them
+}
+
+#[derive(Serialize)]
+pub struct CurrentTimer {
+    #[serde(flatten)]
+    pub session: TimeTrackingSessionWithIntervals,
+    pub space_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct NewTimeTrackingSession {
+    #[serde(default)]
     pub activity_name: String,
-    pub start_time: chrono::NaiveDateTime,
+    // picks an existing activity, otherwise one is looked up (or created) by name
+    pub activity_id: Option<Uuid>,
+    // only used when sent on purpose, otherwise the session starts at the server time
+    #[serde(default, deserialize_with = "client_time::deserialize_option")]
+    pub manual_start_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
+// Times sent by clients. RFC 3339 with an offset, or a plain date and time that is
+// read as UTC the way every time was before they carried a zone.
+pub mod client_time {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use serde::{de, Deserialize, Deserializer};
+
+    pub fn parse(value: &str) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|time| time.with_timezone(&Utc))
+            .ok()
+            .or_else(|| value.parse::<NaiveDateTime>().ok().map(|time| time.and_utc()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        parse(&value).ok_or_else(|| de::Error::custom(format!("invalid date and time: {}", value)))
+    }
+
+    pub fn deserialize_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| parse(&value).ok_or_else(|| de::Error::custom(format!("invalid date and time: {}", value))))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(id: &str, parent: Option<&str>, is_checked: bool, progress: Option<u8>) -> StickyLine {
+        StickyLine {
+            id: id.to_string(),
+            text: format!("line {}", id),
+            color: String::new(),
+            is_checked,
+            parent: parent.map(|parent| parent.to_string()),
+            progress,
+            due: None,
+        }
+    }
+
+    #[test]
+    fn line_progress_rolls_children_up_by_id() {
+        let lines = vec![
+            line("a", None, false, None),
+            line("b", Some("a"), true, None),
+            line("c", Some("a"), false, Some(50)),
+            line("d", None, false, Some(20)),
+        ];
+        assert_eq!(line_progress(&lines), vec![75.0, 100.0, 50.0, 20.0]);
+    }
+
+    #[test]
+    fn line_progress_follows_the_parent_when_lines_are_reordered() {
+        let lines = vec![
+            line("c", Some("a"), false, Some(50)),
+            line("d", None, false, Some(20)),
+            line("b", Some("a"), true, None),
+            line("a", None, false, None),
+        ];
+        assert_eq!(line_progress(&lines), vec![50.0, 20.0, 100.0, 75.0]);
+    }
+
+    #[test]
+    fn line_progress_checked_parent_wins_over_children() {
+        let lines = vec![line("a", None, true, None), line("b", Some("a"), false, None)];
+        assert_eq!(line_progress(&lines), vec![100.0, 0.0]);
+    }
+
+    #[test]
+    fn line_progress_reads_index_parents_from_older_clients() {
+        let lines = vec![line("", None, false, None), line("", Some("0"), true, None), line("", Some("7"), false, None)];
+        assert_eq!(line_progress(&lines), vec![100.0, 100.0, 0.0]);
+    }
+
+    #[test]
+    fn line_progress_survives_parent_loops() {
+        let lines = vec![line("a", Some("b"), false, Some(10)), line("b", Some("a"), false, Some(30))];
+        let progress = line_progress(&lines);
+        assert_eq!(progress.len(), 2);
+        assert!(progress.iter().all(|value| value.is_finite()));
+    }
+
+    #[test]
+    fn note_completion_averages_top_level_lines() {
+        let lines = vec![
+            line("a", None, false, None),
+            line("b", Some("a"), true, None),
+            line("c", Some("a"), false, Some(50)),
+            line("d", None, false, Some(20)),
+            line("e", Some("missing"), false, None),
+        ];
+        // a (75), d (20) and e, whose parent is gone (0)
+        assert_eq!(note_completion(&lines), 31.7);
+    }
+
+    #[test]
+    fn note_completion_of_an_empty_note_is_zero() {
+        assert_eq!(note_completion(&[]), 0.0);
+    }
+
+    #[test]
+    fn assign_line_ids_keeps_ids_and_turns_index_parents_into_ids() {
+        let old = vec![line("a", None, false, None)];
+        let mut moved = line("", Some("0"), false, None);
+        moved.text = "line a".to_string();
+        let sent = vec![moved, line("", Some("0"), true, None)];
+
+        let lines = assign_line_ids(&old, sent);
+        assert_eq!(lines[0].id, "a");
+        assert!(!lines[1].id.is_empty() && lines[1].id != "a");
+        assert_eq!(lines[1].parent.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn sticky_line_round_trips_with_an_id() {
+        let stored = "text|red|true|p1||2026-10-20|id1";
+        let line = StickyLine::from_string(stored);
+        assert_eq!(line.id, "id1");
+        assert_eq!(line.parent.as_deref(), Some("p1"));
+        assert_eq!(line.to_string(), stored);
+        assert_eq!(StickyLine::from_string("text|red|false").to_string(), "text|red|false");
+    }
+}
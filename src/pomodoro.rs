@@ -0,0 +1,305 @@
+// Pomodoro runs. The work phases are ordinary time tracking sessions (with
+// `session_type = "pomodoro"`), the server moves a run from phase to phase on
+// its own and tells the client through a `pomodoro_phase` event.
+
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use diesel::sql_types::{BigInt, Text, Uuid as SqlUuid};
+use diesel::QueryableByName;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::db::{self, DbConn, DbPool};
+use crate::events::{Notifier, TrackingEvent};
+use crate::models::{Pomodoro, PomodoroRun, TimeTrackingSession, SESSION_TYPE_POMODORO};
+
+// Server defaults, read from `time_tracking.pomodoro` in Rocket.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PomodoroConfig {
+    pub work_secs: i64,
+    pub short_break_secs: i64,
+    pub long_break_secs: i64,
+    pub long_break_every: i32, // a long break after every n-th pomodoro
+    pub check_interval_secs: u64,
+}
+
+impl Default for PomodoroConfig {
+    fn default() -> Self {
+        PomodoroConfig {
+            work_secs: 25 * 60,
+            short_break_secs: 5 * 60,
+            long_break_secs: 15 * 60,
+            long_break_every: 4,
+            check_interval_secs: 5,
+        }
+    }
+}
+
+// No phase can be longer than a day
+pub const MAX_PHASE_SECONDS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+    Finished,
+}
+
+impl Phase {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "work" => Some(Phase::Work),
+            "short_break" => Some(Phase::ShortBreak),
+            "long_break" => Some(Phase::LongBreak),
+            "finished" => Some(Phase::Finished),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Work => "work",
+            Phase::ShortBreak => "short_break",
+            Phase::LongBreak => "long_break",
+            Phase::Finished => "finished",
+        }
+    }
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct PomodoroCount {
+    #[diesel(sql_type = SqlUuid)]
+    pub activity_id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub activity_name: String,
+    #[diesel(sql_type = BigInt)]
+    pub pomodoros: i64,
+    #[diesel(sql_type = BigInt)]
+    pub focus_seconds: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseChange {
+    pub run_id: Uuid,
+    pub space_id: i32,
+    pub activity_id: Uuid,
+    pub previous_phase: Phase,
+    pub phase: Phase,
+    pub phase_started_at: DateTime<Utc>,
+    pub phase_ends_at: Option<DateTime<Utc>>, // None once the run is finished
+    pub completed_pomodoros: i32,
+    pub session_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct PomodoroStatus {
+    #[serde(flatten)]
+    pub run: PomodoroRun,
+    pub remaining_seconds: i64,
+}
+
+impl PomodoroStatus {
+    pub fn new(run: PomodoroRun) -> Self {
+        let remaining = run.phase_ends_at.signed_duration_since(chrono::Utc::now()).num_seconds();
+        PomodoroStatus { remaining_seconds: remaining.max(0), run }
+    }
+}
+
+// The open work session a new work phase starts with
+pub fn work_session(run: &PomodoroRun, activity_name: &str, at: DateTime<Utc>) -> TimeTrackingSession {
+    TimeTrackingSession {
+        id: Uuid::new_v4(),
+        user_id: run.user_id.clone(),
+        space_id: run.space_id,
+        activity_name: activity_name.to_string(),
+        start_time: at,
+        end_time: None,
+        duration: None,
+        activity_id: Some(run.activity_id),
+        notes: None,
+        session_type: SESSION_TYPE_POMODORO.to_string(),
+        last_heartbeat_at: None,
+        auto_stopped: false,
+        auto_stop_reason: None,
+        reviewed_at: None,
+        note_id: None,
+        note_line_id: None,
+        tags: Vec::new(),
+    }
+}
+
+// How a run leaves its current phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advance {
+    Elapsed, // the phase ran its full length
+    Skipped, // the user moved on early, a skipped work phase does not count
+    Stop,    // the run ends here
+}
+
+// What a run moving on from its phase writes, see `db::save_pomodoro_transition`
+pub struct Transition {
+    pub previous_phase_started_at: DateTime<Utc>,
+    pub run: PomodoroRun,
+    pub close_at: Option<(Uuid, DateTime<Utc>)>, // the work session to close
+    pub pomodoro: Option<Pomodoro>,
+    pub open: Option<TimeTrackingSession>, // the work session a new work phase starts with
+    pub change: PhaseChange,
+}
+
+// Works out how `run` moves on from its current phase at `at`. `session` is its work session
+// as last read and `activity_name` names the next one, should a work phase start.
+pub fn transition(
+    run: &PomodoroRun,
+    session: Option<&TimeTrackingSession>,
+    activity_name: &str,
+    at: DateTime<Utc>,
+    how: Advance,
+) -> Transition {
+    let previous_phase = Phase::parse(&run.phase).unwrap_or(Phase::Work);
+
+    // a work session that was stopped elsewhere (or deleted) ends the run
+    let session_open = session.is_some_and(|session| session.end_time.is_none());
+    let how = if previous_phase == Phase::Work && !session_open { Advance::Stop } else { how };
+
+    let mut next = run.clone();
+    let close_at = if session_open { run.session_id.map(|session_id| (session_id, at)) } else { None };
+
+    // a work phase counts once it ran its full length, even when the run stops right there
+    let completed = previous_phase == Phase::Work && session_open && how != Advance::Skipped && at >= run.phase_ends_at;
+    let pomodoro = if completed {
+        next.completed_pomodoros += 1;
+        Some(Pomodoro {
+            id: Uuid::new_v4(),
+            run_id: run.id,
+            user_id: run.user_id.clone(),
+            space_id: run.space_id,
+            activity_id: run.activity_id,
+            session_id: run.session_id,
+            started_at: run.phase_started_at,
+            completed_at: at,
+        })
+    } else {
+        None
+    };
+
+    let mut phase = match (how, previous_phase) {
+        (Advance::Stop, _) => Phase::Finished,
+        (_, Phase::Work) if next.long_break_every > 0 && pomodoro.is_some()
+            && next.completed_pomodoros % next.long_break_every == 0 => Phase::LongBreak,
+        (_, Phase::Work) => Phase::ShortBreak,
+        _ => Phase::Work,
+    };
+    let length = match phase {
+        Phase::Work => next.work_seconds,
+        Phase::ShortBreak => next.short_break_seconds,
+        Phase::LongBreak => next.long_break_seconds,
+        Phase::Finished => 0,
+    };
+
+    // lengths are capped when a run starts, older rows might not be, so a run whose next
+    // phase cannot end is finished instead
+    let ends_at = chrono::TimeDelta::try_seconds(length).and_then(|length| at.checked_add_signed(length));
+    let ends_at = match ends_at {
+        Some(ends_at) => ends_at,
+        None => {
+            eprintln!("Pomodoro run {} has a phase of {} seconds, finishing it", run.id, length);
+            phase = Phase::Finished;
+            at
+        }
+    };
+
+    next.phase = phase.as_str().to_string();
+    next.phase_started_at = at;
+    next.phase_ends_at = ends_at;
+    if phase == Phase::Finished {
+        next.ended_at = Some(at);
+    }
+
+    let open = (phase == Phase::Work).then(|| work_session(&next, activity_name, at));
+    next.session_id = open.as_ref().map(|session| session.id);
+
+    let change = PhaseChange {
+        run_id: next.id,
+        space_id: next.space_id,
+        activity_id: next.activity_id,
+        previous_phase,
+        phase,
+        phase_started_at: next.phase_started_at,
+        phase_ends_at: next.ended_at.is_none().then_some(next.phase_ends_at),
+        completed_pomodoros: next.completed_pomodoros,
+        session_id: next.session_id,
+    };
+    Transition {
+        previous_phase_started_at: run.phase_started_at,
+        run: next,
+        close_at,
+        pomodoro,
+        open,
+        change,
+    }
+}
+
+// Moves `run` on from its current phase at `at` and saves it. Returns the change,
+// None when nothing happened because the run was moved on concurrently.
+pub async fn advance(
+    conn: &DbConn,
+    run: PomodoroRun,
+    at: DateTime<Utc>,
+    how: Advance,
+) -> Result<Option<PhaseChange>, diesel::result::Error> {
+    let session = match run.session_id {
+        Some(session_id) => db::get_time_tracking_session(conn, run.user_id.clone(), session_id).await.ok(),
+        None => None,
+    };
+    let activity_name = match &session {
+        Some(session) => session.activity_name.clone(),
+        None => db::get_activity(conn, run.user_id.clone(), run.activity_id).await.map(|activity| activity.name).unwrap_or_default(),
+    };
+
+    let transition = transition(&run, session.as_ref(), &activity_name, at, how);
+    let change = transition.change.clone();
+    if !db::save_pomodoro_transition(conn, transition).await? {
+        return Ok(None);
+    }
+    Ok(Some(change))
+}
+
+// Moves every run whose phase is over along. A run that fell behind by more than
+// a phase (the server was down) is finished where it stopped instead of replaying
+// the missed phases.
+pub async fn advance_due_runs(conn: &DbConn, notifier: &dyn Notifier) -> Result<(), diesel::result::Error> {
+    let now = chrono::Utc::now();
+
+    for run in db::get_due_pomodoro_runs(conn, now).await? {
+        let behind = now.signed_duration_since(run.phase_ends_at).num_seconds();
+        let longest = run.short_break_seconds.max(run.long_break_seconds).max(run.work_seconds);
+        let how = if behind > longest { Advance::Stop } else { Advance::Elapsed };
+
+        let user_id = run.user_id.clone();
+        let at = run.phase_ends_at;
+        if let Some(change) = advance(conn, run, at, how).await? {
+            notifier.notify(&user_id, TrackingEvent::PomodoroPhase(change)).await;
+        }
+    }
+
+    Ok(())
+}
+
+// Runs for as long as the server does, see `time_tracking.pomodoro.check_interval_secs`
+pub async fn run_ticker(pool: DbPool, notifier: Arc<dyn Notifier>, interval: Duration) {
+    let mut ticker = rocket::tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let Some(conn) = db::checkout(&pool).await else {
+            eprintln!("Pomodoro check skipped, no database connection available");
+            continue;
+        };
+        if let Err(e) = advance_due_runs(&conn, notifier.as_ref()).await {
+            eprintln!("Error advancing pomodoro runs: {:?}", e);
+        }
+    }
+}
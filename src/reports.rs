@@ -0,0 +1,351 @@
+// Time tracking reports. The sums come out of SQL (see `db::time_report_rows`),
+// this module only arranges the rows into the response.
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::sql_types::{BigInt, Date, Nullable, Text};
+use diesel::QueryableByName;
+use serde::Serialize;
+
+// Years the API takes dates from. Anything else is a typo, and dates near chrono's
+// limits would overflow the day arithmetic done on them.
+pub const MIN_YEAR: i32 = 1900;
+pub const MAX_YEAR: i32 = 2999;
+
+// longest range a report covers, the previous period of the same length comes on top
+pub const MAX_REPORT_DAYS: i64 = 10 * 366;
+
+pub fn date_in_range(date: NaiveDate) -> bool {
+    (MIN_YEAR..=MAX_YEAR).contains(&date.year())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "day" => Some(Granularity::Day),
+            "week" => Some(Granularity::Week),
+            "month" => Some(Granularity::Month),
+            _ => None,
+        }
+    }
+
+    // name understood by postgres `date_trunc`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+
+    // first day of the bucket `date` falls in, weeks start on monday like in postgres
+    pub fn bucket_start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => date,
+            Granularity::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Granularity::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    // first day of the bucket after the one `date` falls in
+    pub fn next_bucket_start(&self, date: NaiveDate) -> NaiveDate {
+        let start = self.bucket_start(date);
+        match self {
+            Granularity::Day => start + Duration::days(1),
+            Granularity::Week => start + Duration::days(7),
+            Granularity::Month => start.checked_add_months(Months::new(1)).unwrap_or(start),
+        }
+    }
+
+    // how many buckets the inclusive range touches
+    pub fn bucket_count(&self, from: NaiveDate, to: NaiveDate) -> i64 {
+        if from > to {
+            return 0;
+        }
+        match self {
+            Granularity::Day => (to - from).num_days() + 1,
+            Granularity::Week => (self.bucket_start(to) - self.bucket_start(from)).num_days() / 7 + 1,
+            Granularity::Month => {
+                let months = |date: NaiveDate| date.year() as i64 * 12 + date.month0() as i64;
+                months(to) - months(from) + 1
+            }
+        }
+    }
+}
+
+// UTC instant at which `date` starts in `time_zone`
+pub fn local_midnight_utc(date: NaiveDate, time_zone: Tz) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    time_zone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or(midnight.and_utc())
+}
+
+// One grouping set of the report query, `grouping` says which of the columns are filled
+#[derive(QueryableByName, Debug)]
+pub struct ReportRow {
+    #[diesel(sql_type = Text)]
+    pub grouping: String,
+    #[diesel(sql_type = Nullable<Date>)]
+    pub bucket: Option<NaiveDate>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub activity_name: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub space_name: Option<String>,
+    #[diesel(sql_type = BigInt)]
+    pub total_seconds: i64,
+    #[diesel(sql_type = BigInt)]
+    pub sessions: i64,
+}
+
+#[derive(Serialize)]
+pub struct ActivityTotal {
+    pub activity_name: String,
+    pub total_seconds: i64,
+    pub sessions: i64,
+    pub average_session_seconds: i64,
+    pub previous_total_seconds: i64,
+    pub change_seconds: i64,
+    pub change_percent: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct BucketActivity {
+    pub activity_name: String,
+    pub total_seconds: i64,
+}
+
+#[derive(Serialize)]
+pub struct BucketTotal {
+    pub start: NaiveDate,
+    pub total_seconds: i64,
+    pub sessions: i64,
+    pub activities: Vec<BucketActivity>,
+}
+
+#[derive(Serialize)]
+pub struct SpaceTotal {
+    pub space_name: String,
+    pub total_seconds: i64,
+    pub sessions: i64,
+}
+
+#[derive(Serialize)]
+pub struct TimeReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub group_by: &'static str,
+    pub time_zone: String,
+    pub total_seconds: i64,
+    pub sessions: i64,
+    pub average_session_seconds: i64,
+    pub average_per_bucket_seconds: i64,
+    pub previous_total_seconds: i64,
+    pub change_seconds: i64,
+    pub change_percent: Option<f64>,
+    pub buckets: Vec<BucketTotal>,
+    pub activities: Vec<ActivityTotal>,
+    pub spaces: Vec<SpaceTotal>,
+}
+
+fn average(total: i64, count: i64) -> i64 {
+    if count > 0 { total / count } else { 0 }
+}
+
+fn change_percent(current: i64, previous: i64) -> Option<f64> {
+    if previous == 0 {
+        return None;
+    }
+    let percent = (current - previous) as f64 / previous as f64 * 100.0;
+    Some((percent * 10.0).round() / 10.0)
+}
+
+pub fn build_report(
+    from: NaiveDate,
+    to: NaiveDate,
+    granularity: Granularity,
+    time_zone: String,
+    current: Vec<ReportRow>,
+    previous: Vec<ReportRow>,
+) -> TimeReport {
+    let (total_seconds, sessions) = current
+        .iter()
+        .find(|row| row.grouping == "total")
+        .map(|row| (row.total_seconds, row.sessions))
+        .unwrap_or((0, 0));
+    let previous_total_seconds = previous
+        .iter()
+        .find(|row| row.grouping == "total")
+        .map(|row| row.total_seconds)
+        .unwrap_or(0);
+
+    let mut buckets: Vec<BucketTotal> = current
+        .iter()
+        .filter(|row| row.grouping == "bucket")
+        .filter_map(|row| {
+            Some(BucketTotal {
+                start: row.bucket?,
+                total_seconds: row.total_seconds,
+                sessions: row.sessions,
+                activities: Vec::new(),
+            })
+        })
+        .collect();
+    buckets.sort_by_key(|bucket| bucket.start);
+
+    for row in current.iter().filter(|row| row.grouping == "bucket_activity") {
+        let (Some(start), Some(activity_name)) = (row.bucket, row.activity_name.clone()) else { continue };
+        if let Some(bucket) = buckets.iter_mut().find(|bucket| bucket.start == start) {
+            bucket.activities.push(BucketActivity { activity_name, total_seconds: row.total_seconds });
+        }
+    }
+    for bucket in &mut buckets {
+        bucket.activities.sort_by_key(|total| std::cmp::Reverse(total.total_seconds));
+    }
+
+    let mut activities: Vec<ActivityTotal> = current
+        .iter()
+        .filter(|row| row.grouping == "activity")
+        .filter_map(|row| {
+            let activity_name = row.activity_name.clone()?;
+            let previous_total = previous
+                .iter()
+                .find(|prev| prev.grouping == "activity" && prev.activity_name.as_deref() == Some(activity_name.as_str()))
+                .map(|prev| prev.total_seconds)
+                .unwrap_or(0);
+
+            Some(ActivityTotal {
+                activity_name,
+                total_seconds: row.total_seconds,
+                sessions: row.sessions,
+                average_session_seconds: average(row.total_seconds, row.sessions),
+                previous_total_seconds: previous_total,
+                change_seconds: row.total_seconds - previous_total,
+                change_percent: change_percent(row.total_seconds, previous_total),
+            })
+        })
+        .collect();
+    activities.sort_by_key(|total| std::cmp::Reverse(total.total_seconds));
+
+    let mut spaces: Vec<SpaceTotal> = current
+        .iter()
+        .filter(|row| row.grouping == "space")
+        .filter_map(|row| {
+            Some(SpaceTotal {
+                space_name: row.space_name.clone()?,
+                total_seconds: row.total_seconds,
+                sessions: row.sessions,
+            })
+        })
+        .collect();
+    spaces.sort_by_key(|total| std::cmp::Reverse(total.total_seconds));
+
+    TimeReport {
+        from,
+        to,
+        group_by: granularity.as_str(),
+        time_zone,
+        total_seconds,
+        sessions,
+        average_session_seconds: average(total_seconds, sessions),
+        average_per_bucket_seconds: average(total_seconds, granularity.bucket_count(from, to)),
+        previous_total_seconds,
+        change_seconds: total_seconds - previous_total_seconds,
+        change_percent: change_percent(total_seconds, previous_total_seconds),
+        buckets,
+        activities,
+        spaces,
+    }
+}
+
+// One grouping set of the tag report query, `tag` is None for untagged sessions
+#[derive(QueryableByName, Debug)]
+pub struct TagReportRow {
+    #[diesel(sql_type = Text)]
+    pub grouping: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub tag: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub activity_name: Option<String>,
+    #[diesel(sql_type = BigInt)]
+    pub total_seconds: i64,
+    #[diesel(sql_type = BigInt)]
+    pub sessions: i64,
+}
+
+#[derive(Serialize)]
+pub struct TagTotal {
+    pub tag: Option<String>,
+    pub total_seconds: i64,
+    pub sessions: i64,
+    pub activities: Vec<BucketActivity>,
+}
+
+// A session with several tags counts towards each of them, so the tags can add
+// up to more than `total_seconds`
+#[derive(Serialize)]
+pub struct TagReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub time_zone: String,
+    pub activity: Option<String>,
+    pub total_seconds: i64,
+    pub sessions: i64,
+    pub tags: Vec<TagTotal>,
+}
+
+pub fn build_tag_report(
+    from: NaiveDate,
+    to: NaiveDate,
+    time_zone: String,
+    activity: Option<String>,
+    rows: Vec<TagReportRow>,
+) -> TagReport {
+    let (total_seconds, sessions) = rows
+        .iter()
+        .find(|row| row.grouping == "total")
+        .map(|row| (row.total_seconds, row.sessions))
+        .unwrap_or((0, 0));
+
+    let mut tags: Vec<TagTotal> = rows
+        .iter()
+        .filter(|row| row.grouping == "tag")
+        .map(|row| TagTotal {
+            tag: row.tag.clone(),
+            total_seconds: row.total_seconds,
+            sessions: row.sessions,
+            activities: Vec::new(),
+        })
+        .collect();
+
+    for row in rows.iter().filter(|row| row.grouping == "tag_activity") {
+        let Some(activity_name) = row.activity_name.clone() else { continue };
+        if let Some(total) = tags.iter_mut().find(|total| total.tag == row.tag) {
+            total.activities.push(BucketActivity { activity_name, total_seconds: row.total_seconds });
+        }
+    }
+    for total in &mut tags {
+        total.activities.sort_by_key(|activity| std::cmp::Reverse(activity.total_seconds));
+    }
+    // untagged time goes last
+    tags.sort_by_key(|total| (total.tag.is_none(), std::cmp::Reverse(total.total_seconds)));
+
+    TagReport {
+        from,
+        to,
+        time_zone,
+        activity,
+        total_seconds,
+        sessions,
+        tags,
+    }
+}
@@ -0,0 +1,221 @@
+// The weekly review of a space: what got checked off, what is still open, where the
+// time went, which notes came and went and how the habit streaks moved. It is built
+// from the data as it is now, a saved review keeps the JSON as it was at the time.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use diesel::sql_types::{Text, Timestamptz, Uuid as SqlUuid};
+use diesel::QueryableByName;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::db::{self, DbConn};
+use crate::habits;
+use crate::reports::{self, local_midnight_utc, Granularity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewFormat {
+    Json,
+    Markdown,
+    Html,
+}
+
+impl ReviewFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(ReviewFormat::Json),
+            "markdown" | "md" => Some(ReviewFormat::Markdown),
+            "html" => Some(ReviewFormat::Html),
+            _ => None,
+        }
+    }
+
+    // see templates/, None for json
+    pub fn template(&self) -> Option<&'static str> {
+        match self {
+            ReviewFormat::Json => None,
+            ReviewFormat::Markdown => Some("weekly_review_markdown"),
+            ReviewFormat::Html => Some("weekly_review"),
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+pub struct CheckedLineRow {
+    #[diesel(sql_type = SqlUuid)]
+    pub note_id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub note_title: String,
+    #[diesel(sql_type = Text)]
+    pub line_text: String,
+    #[diesel(sql_type = Timestamptz)]
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ReviewLine {
+    pub note_id: Uuid,
+    pub note_title: String,
+    pub text: String,
+    pub checked_at: Option<DateTime<Utc>>, // completed lines only
+}
+
+#[derive(Serialize)]
+pub struct ReviewNote {
+    pub note_id: Uuid,
+    pub title: String,
+    pub lines: usize,
+    pub at: DateTime<Utc>, // when it was created or deleted
+}
+
+#[derive(Serialize)]
+pub struct ActivityTime {
+    pub activity_name: String,
+    pub total_seconds: i64,
+    pub sessions: i64,
+    pub previous_total_seconds: i64,
+    pub change_seconds: i64,
+}
+
+#[derive(Serialize)]
+pub struct StreakChange {
+    pub habit_id: Uuid,
+    pub name: String,
+    pub days_done: i64, // within the week
+    pub streak_before: i64, // when the week started
+    pub streak_after: i64,  // when it ended, or today for the running week
+    pub change: i64,
+}
+
+#[derive(Serialize)]
+pub struct WeeklyReview {
+    pub space_name: String,
+    pub week_start: NaiveDate,
+    pub week_end: NaiveDate, // inclusive
+    pub time_zone: String,
+    pub generated_at: DateTime<Utc>,
+    pub total_seconds: i64,
+    pub previous_total_seconds: i64,
+    pub change_seconds: i64,
+    pub activities: Vec<ActivityTime>,
+    pub completed_lines: Vec<ReviewLine>,
+    pub remaining_lines: Vec<ReviewLine>, // unchecked lines of the notes that are not archived, as of now
+    pub created_notes: Vec<ReviewNote>,
+    pub deleted_notes: Vec<ReviewNote>,
+    pub habits: Vec<StreakChange>,
+}
+
+pub async fn build_review(
+    conn: &DbConn,
+    user_id: String,
+    space_id: i32,
+    space_name: String,
+    day: NaiveDate,
+    time_zone: Tz,
+) -> Result<WeeklyReview, diesel::result::Error> {
+    let week_start = Granularity::Week.bucket_start(day);
+    let next_week = Granularity::Week.next_bucket_start(day);
+    let week_end = next_week - Duration::days(1);
+    let from = local_midnight_utc(week_start, time_zone);
+    let to = local_midnight_utc(next_week, time_zone);
+    let zone = time_zone.name().to_string();
+
+    let current = db::time_report_rows(conn, user_id.clone(), Some(space_id), zone.clone(), Granularity::Week, week_start, next_week).await?;
+    let previous = db::time_report_rows(
+        conn, user_id.clone(), Some(space_id), zone.clone(), Granularity::Week, week_start - Duration::days(7), week_start,
+    ).await?;
+    let report = reports::build_report(week_start, week_end, Granularity::Week, zone.clone(), current, previous);
+    let activities = report
+        .activities
+        .into_iter()
+        .map(|total| ActivityTime {
+            activity_name: total.activity_name,
+            total_seconds: total.total_seconds,
+            sessions: total.sessions,
+            previous_total_seconds: total.previous_total_seconds,
+            change_seconds: total.change_seconds,
+        })
+        .collect();
+
+    let completed_lines = db::checked_lines_between(conn, space_id, from, to)
+        .await?
+        .into_iter()
+        .map(|row| ReviewLine {
+            note_id: row.note_id,
+            note_title: row.note_title,
+            text: row.line_text,
+            checked_at: Some(row.checked_at),
+        })
+        .collect();
+
+    let remaining_lines = db::get_sticky_notes(conn, user_id.clone(), space_id, false)
+        .await?
+        .into_iter()
+        .flat_map(|note| {
+            note.sticky_lines()
+                .into_iter()
+                .filter(|line| !line.is_checked && !line.text.trim().is_empty())
+                .map(|line| ReviewLine {
+                    note_id: note.id,
+                    note_title: note.title.clone(),
+                    text: line.text,
+                    checked_at: None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let created_notes = db::get_notes_created_between(conn, user_id.clone(), space_id, from, to)
+        .await?
+        .into_iter()
+        .map(|note| ReviewNote {
+            note_id: note.id,
+            lines: note.sticky_lines().len(),
+            title: note.title,
+            at: note.created_at,
+        })
+        .collect();
+    let deleted_notes = db::get_note_deletions(conn, space_id, from, to)
+        .await?
+        .into_iter()
+        .map(|deletion| ReviewNote {
+            note_id: deletion.note_id,
+            title: deletion.title,
+            lines: deletion.lines.map_or(0, |lines| lines.len()),
+            at: deletion.deleted_at,
+        })
+        .collect();
+
+    // the running week counts up to today, a streak is not broken before its day is over
+    let last_day = week_end.min(habits::local_today(time_zone));
+    let mut streak_changes = Vec::new();
+    for habit in db::get_habits(conn, user_id, Some(space_id)).await? {
+        let days = habits::completed_days(conn, &habit, time_zone, None, next_week).await?;
+        let before = habits::streaks(&days, week_start - Duration::days(1)).current;
+        let after = habits::streaks(&days, last_day).current;
+        streak_changes.push(StreakChange {
+            habit_id: habit.id,
+            name: habit.name,
+            days_done: days.iter().filter(|day| **day >= week_start).count() as i64,
+            streak_before: before,
+            streak_after: after,
+            change: after - before,
+        });
+    }
+
+    Ok(WeeklyReview {
+        space_name,
+        week_start,
+        week_end,
+        time_zone: zone,
+        generated_at: chrono::Utc::now(),
+        total_seconds: report.total_seconds,
+        previous_total_seconds: report.previous_total_seconds,
+        change_seconds: report.change_seconds,
+        activities,
+        completed_lines,
+        remaining_lines,
+        created_notes,
+        deleted_notes,
+        habits: streak_changes,
+    })
+}
@@ -1,5 +1,223 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    activities (id) {
+        id -> Uuid,
+        space_id -> Int4,
+        user_id -> Text,
+        name -> Text,
+        color -> Nullable<Text>,
+        category -> Nullable<Text>,
+        billable -> Bool,
+        hourly_rate_cents -> Nullable<Int8>,
+        archived -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    activity_budgets (id) {
+        id -> Uuid,
+        activity_id -> Uuid,
+        user_id -> Text,
+        period -> Text,
+        kind -> Text,
+        limit_seconds -> Int8,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    budget_notifications (budget_id, period_start) {
+        budget_id -> Uuid,
+        period_start -> Date,
+        tracked_seconds -> Int8,
+        sent_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    calendar_feeds (user_id) {
+        user_id -> Text,
+        token_hash -> Text,
+        created_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    goal_snapshots (goal_id, day) {
+        goal_id -> Uuid,
+        day -> Date,
+        progress -> Int8,
+        recorded_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    goals (id) {
+        id -> Uuid,
+        space_id -> Int4,
+        user_id -> Text,
+        name -> Text,
+        kind -> Text,
+        target -> Int8,
+        activity_id -> Nullable<Uuid>,
+        note_id -> Nullable<Uuid>,
+        start_date -> Date,
+        end_date -> Date,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    habit_completions (habit_id, day) {
+        habit_id -> Uuid,
+        day -> Date,
+        completed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    habits (id) {
+        id -> Uuid,
+        space_id -> Int4,
+        user_id -> Text,
+        name -> Text,
+        kind -> Text,
+        activity_id -> Nullable<Uuid>,
+        target_seconds -> Nullable<Int8>,
+        note_id -> Nullable<Uuid>,
+        line_text -> Nullable<Text>,
+        created_at -> Timestamptz,
+        line_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    line_check_events (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        user_id -> Text,
+        space_id -> Int4,
+        line_text -> Text,
+        checked -> Bool,
+        created_at -> Timestamptz,
+        line_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    note_attachments (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        user_id -> Text,
+        file_name -> Text,
+        content_type -> Text,
+        size_bytes -> Int8,
+        storage_key -> Text,
+        thumbnail_key -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    note_deletions (id) {
+        id -> Uuid,
+        note_id -> Uuid,
+        space_id -> Int4,
+        user_id -> Text,
+        title -> Text,
+        lines -> Nullable<Array<Text>>,
+        deleted_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    note_links (id) {
+        id -> Uuid,
+        source_note_id -> Uuid,
+        target_note_id -> Nullable<Uuid>,
+        target_title -> Text,
+        kind -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    pomodoro_runs (id) {
+        id -> Uuid,
+        user_id -> Text,
+        space_id -> Int4,
+        activity_id -> Uuid,
+        work_seconds -> Int8,
+        short_break_seconds -> Int8,
+        long_break_seconds -> Int8,
+        long_break_every -> Int4,
+        phase -> Text,
+        phase_started_at -> Timestamptz,
+        phase_ends_at -> Timestamptz,
+        completed_pomodoros -> Int4,
+        session_id -> Nullable<Uuid>,
+        started_at -> Timestamptz,
+        ended_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    pomodoros (id) {
+        id -> Uuid,
+        run_id -> Uuid,
+        user_id -> Text,
+        space_id -> Int4,
+        activity_id -> Uuid,
+        session_id -> Nullable<Uuid>,
+        started_at -> Timestamptz,
+        completed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    session_audit_log (id) {
+        id -> Uuid,
+        session_id -> Uuid,
+        user_id -> Text,
+        action -> Text,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    session_intervals (id) {
+        id -> Uuid,
+        session_id -> Uuid,
+        start_time -> Timestamptz,
+        end_time -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    space_settings (space_id) {
+        space_id -> Int4,
+        description -> Nullable<Text>,
+        icon -> Nullable<Text>,
+        default_color -> Nullable<Text>,
+        default_text_color -> Nullable<Text>,
+        background -> Nullable<Text>,
+        time_zone -> Nullable<Text>,
+        default_playlist -> Nullable<Text>,
+        updated_at -> Timestamptz,
+        client_name -> Nullable<Text>,
+        billable -> Bool,
+        hourly_rate_cents -> Nullable<Int8>,
+        currency -> Nullable<Text>,
+        rounding_minutes -> Nullable<Int4>,
+        rounding_mode -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     spaces (id) {
         id -> Int4,
@@ -16,10 +234,12 @@ diesel::table! {
         title -> Text,
         color -> Text,
         text_color -> Text,
-        created_at -> Timestamp,
-        updated_at -> Nullable<Timestamp>,
+        created_at -> Timestamptz,
+        updated_at -> Nullable<Timestamptz>,
         tags -> Nullable<Array<Text>>,
         lines -> Nullable<Array<Text>>,
+        archived -> Bool,
+        due_date -> Nullable<Date>,
     }
 }
 
@@ -29,14 +249,91 @@ diesel::table! {
         user_id -> Text,
         space_id -> Int4,
         activity_name -> Text,
-        start_time -> Timestamp,
-        end_time -> Nullable<Timestamp>,
+        start_time -> Timestamptz,
+        end_time -> Nullable<Timestamptz>,
         duration -> Nullable<Int8>,
+        activity_id -> Nullable<Uuid>,
+        notes -> Nullable<Text>,
+        session_type -> Text,
+        last_heartbeat_at -> Nullable<Timestamptz>,
+        auto_stopped -> Bool,
+        auto_stop_reason -> Nullable<Text>,
+        reviewed_at -> Nullable<Timestamptz>,
+        note_id -> Nullable<Uuid>,
+        tags -> Array<Text>,
+        note_line_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    user_settings (user_id) {
+        user_id -> Text,
+        time_zone -> Nullable<Text>,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    weekly_reviews (id) {
+        id -> Uuid,
+        space_id -> Int4,
+        user_id -> Text,
+        week_start -> Date,
+        review -> Jsonb,
+        created_at -> Timestamptz,
     }
 }
 
+diesel::joinable!(activities -> spaces (space_id));
+diesel::joinable!(activity_budgets -> activities (activity_id));
+diesel::joinable!(budget_notifications -> activity_budgets (budget_id));
+diesel::joinable!(goal_snapshots -> goals (goal_id));
+diesel::joinable!(goals -> activities (activity_id));
+diesel::joinable!(goals -> spaces (space_id));
+diesel::joinable!(goals -> sticky_notes (note_id));
+diesel::joinable!(habit_completions -> habits (habit_id));
+diesel::joinable!(habits -> activities (activity_id));
+diesel::joinable!(habits -> spaces (space_id));
+diesel::joinable!(habits -> sticky_notes (note_id));
+diesel::joinable!(line_check_events -> spaces (space_id));
+diesel::joinable!(line_check_events -> sticky_notes (note_id));
+diesel::joinable!(note_attachments -> sticky_notes (note_id));
+diesel::joinable!(note_deletions -> spaces (space_id));
+diesel::joinable!(note_links -> sticky_notes (source_note_id));
+diesel::joinable!(pomodoro_runs -> activities (activity_id));
+diesel::joinable!(pomodoro_runs -> spaces (space_id));
+diesel::joinable!(pomodoro_runs -> time_tracking_sessions (session_id));
+diesel::joinable!(pomodoros -> activities (activity_id));
+diesel::joinable!(pomodoros -> pomodoro_runs (run_id));
+diesel::joinable!(pomodoros -> spaces (space_id));
+diesel::joinable!(pomodoros -> time_tracking_sessions (session_id));
+diesel::joinable!(session_intervals -> time_tracking_sessions (session_id));
+diesel::joinable!(space_settings -> spaces (space_id));
+diesel::joinable!(time_tracking_sessions -> activities (activity_id));
+diesel::joinable!(time_tracking_sessions -> sticky_notes (note_id));
+diesel::joinable!(weekly_reviews -> spaces (space_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    activities,
+    activity_budgets,
+    budget_notifications,
+    calendar_feeds,
+    goal_snapshots,
+    goals,
+    habit_completions,
+    habits,
+    line_check_events,
+    note_attachments,
+    note_deletions,
+    note_links,
+    pomodoro_runs,
+    pomodoros,
+    session_audit_log,
+    session_intervals,
+    space_settings,
     spaces,
     sticky_notes,
     time_tracking_sessions,
+    user_settings,
+    weekly_reviews,
 );
@@ -0,0 +1,224 @@
+// Storage for files attached to sticky notes.
+//
+// Attachments live on the local disk by default, set `attachments.backend = "s3"`
+// in Rocket.toml to push them to any S3 compatible bucket instead.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use rocket::tokio::fs;
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AttachmentConfig {
+    pub backend: String,
+    pub local_path: String,
+    pub max_bytes: u64,
+    pub allowed_types: Vec<String>,
+    pub thumbnail_size: u32,
+    pub s3_endpoint: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        AttachmentConfig {
+            backend: "local".to_string(),
+            local_path: "uploads".to_string(),
+            max_bytes: 10 * 1024 * 1024,
+            allowed_types: vec![
+                "image/png".to_string(),
+                "image/jpeg".to_string(),
+                "image/gif".to_string(),
+                "image/webp".to_string(),
+                "application/pdf".to_string(),
+                "text/plain".to_string(),
+                "text/markdown".to_string(),
+            ],
+            thumbnail_size: 256,
+            s3_endpoint: None,
+            s3_bucket: None,
+            s3_region: None,
+            s3_access_key: None,
+            s3_secret_key: None,
+        }
+    }
+}
+
+impl AttachmentConfig {
+    pub fn is_allowed(&self, content_type: &str) -> bool {
+        self.allowed_types.iter().any(|allowed| allowed.eq_ignore_ascii_case(content_type))
+    }
+}
+
+#[rocket::async_trait]
+pub trait AttachmentStorage: Send + Sync {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()>;
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+// Managed state wrapping whichever backend was configured
+pub struct Storage {
+    pub backend: Box<dyn AttachmentStorage>,
+    pub config: AttachmentConfig,
+}
+
+impl Storage {
+    pub fn from_config(config: AttachmentConfig) -> Result<Self, String> {
+        let backend: Box<dyn AttachmentStorage> = match config.backend.as_str() {
+            "local" => Box::new(LocalStorage::new(&config.local_path)),
+            "s3" => Box::new(S3Storage::from_config(&config)?),
+            other => return Err(format!("Unknown attachment backend: {}", other)),
+        };
+
+        Ok(Storage { backend, config })
+    }
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &str) -> Self {
+        LocalStorage { root: PathBuf::from(root) }
+    }
+
+    fn path_for(&self, key: &str) -> io::Result<PathBuf> {
+        // keys are generated by us, but never let one walk out of the upload dir
+        if key.is_empty() || key.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid storage key"));
+        }
+        Ok(self.root.join(Path::new(key)))
+    }
+}
+
+#[rocket::async_trait]
+impl AttachmentStorage for LocalStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, bytes).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path_for(key)?).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path_for(key)?).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+pub struct S3Storage {
+    bucket: Bucket,
+    credentials: Credentials,
+    client: reqwest::Client,
+}
+
+const S3_URL_EXPIRY: Duration = Duration::from_secs(60);
+
+impl S3Storage {
+    pub fn from_config(config: &AttachmentConfig) -> Result<Self, String> {
+        let missing = |name: &str| format!("attachments.{} is required for the s3 backend", name);
+
+        let endpoint = config.s3_endpoint.clone().ok_or_else(|| missing("s3_endpoint"))?;
+        let name = config.s3_bucket.clone().ok_or_else(|| missing("s3_bucket"))?;
+        let region = config.s3_region.clone().unwrap_or_else(|| "us-east-1".to_string());
+        let access_key = config.s3_access_key.clone().ok_or_else(|| missing("s3_access_key"))?;
+        let secret_key = config.s3_secret_key.clone().ok_or_else(|| missing("s3_secret_key"))?;
+
+        let endpoint = endpoint.parse().map_err(|e| format!("Invalid s3_endpoint: {}", e))?;
+        let bucket = Bucket::new(endpoint, UrlStyle::Path, name, region)
+            .map_err(|e| format!("Invalid s3 bucket: {}", e))?;
+
+        Ok(S3Storage {
+            bucket,
+            credentials: Credentials::new(access_key, secret_key),
+            client: reqwest::Client::new(),
+        })
+    }
+}
+
+fn s3_error(e: reqwest::Error) -> io::Error {
+    if e.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+        io::Error::new(io::ErrorKind::NotFound, e)
+    } else {
+        io::Error::other(e)
+    }
+}
+
+#[rocket::async_trait]
+impl AttachmentStorage for S3Storage {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()> {
+        let url = self.bucket.put_object(Some(&self.credentials), key).sign(S3_URL_EXPIRY);
+        self.client.put(url.as_str()).body(bytes).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(s3_error)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let url = self.bucket.get_object(Some(&self.credentials), key).sign(S3_URL_EXPIRY);
+        let response = self.client.get(url.as_str()).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(s3_error)?;
+        let bytes = response.bytes().await.map_err(s3_error)?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let url = self.bucket.delete_object(Some(&self.credentials), key).sign(S3_URL_EXPIRY);
+        match self.client.delete(url.as_str()).send().await.and_then(|response| response.error_for_status()) {
+            Ok(_) => Ok(()),
+            Err(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => Ok(()),
+            Err(e) => Err(s3_error(e)),
+        }
+    }
+}
+
+// The type an upload really has, going by its first bytes rather than what the client
+// claims. Text has no signature, so valid UTF-8 keeps a declared text/* type (e.g.
+// text/markdown) and is text/plain otherwise.
+pub fn sniff_content_type(bytes: &[u8], declared: &str) -> String {
+    let sniffed = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else if bytes.starts_with(b"%PDF-") {
+        "application/pdf"
+    } else if !bytes.contains(&0) && std::str::from_utf8(bytes).is_ok() {
+        if declared.starts_with("text/") {
+            return declared.to_string();
+        }
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    };
+    sniffed.to_string()
+}
+
+// Shrinks an uploaded image down to a PNG thumbnail, None for anything we can't decode
+pub fn make_thumbnail(bytes: &[u8], size: u32) -> Option<Vec<u8>> {
+    let image = image::load_from_memory(bytes).ok()?;
+    let thumbnail = image.thumbnail(size, size);
+
+    let mut out = io::Cursor::new(Vec::new());
+    thumbnail.write_to(&mut out, image::ImageFormat::Png).ok()?;
+    Some(out.into_inner())
+}
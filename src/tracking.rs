@@ -0,0 +1,39 @@
+// Server side settings for time tracking, read from the `time_tracking` table in Rocket.toml
+
+use serde::Deserialize;
+use crate::pomodoro::PomodoroConfig;
+
+// What happens when a timer is started while another one is still open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ActiveTimerPolicy {
+    #[default]
+    AutoStop, // stop the running timer at the moment the new one starts
+    Reject,   // refuse to start until the running timer is stopped
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TrackingConfig {
+    pub active_timer_policy: ActiveTimerPolicy,
+    pub budget_check_interval_secs: u64, // how often the budget checker runs
+    pub pomodoro: PomodoroConfig,
+    pub max_session_secs: i64, // running sessions are stopped after this long, 0 turns it off
+    pub idle_grace_secs: i64,  // and this long after the client's last heartbeat, 0 turns it off
+    pub auto_stop_check_interval_secs: u64,
+    pub goal_snapshot_interval_secs: u64, // how often the progress of running goals is snapshotted
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        TrackingConfig {
+            active_timer_policy: ActiveTimerPolicy::default(),
+            budget_check_interval_secs: 60,
+            pomodoro: PomodoroConfig::default(),
+            max_session_secs: 12 * 60 * 60,
+            idle_grace_secs: 15 * 60,
+            auto_stop_check_interval_secs: 60,
+            goal_snapshot_interval_secs: 60 * 60,
+        }
+    }
+}